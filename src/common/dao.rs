@@ -25,6 +25,18 @@ pub trait Dao {
         Ok(result)
     }
 
+    /// 检查表中的字段是否存在，用于旧版本缓存表的迁移
+    async fn check_column (&self, table_name: &'static str, column_name: &'static str) -> Result<bool, Box<dyn Error>> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let result = conn.call( move|conn| {
+            let mut stmt = conn.prepare(format!("SELECT name FROM pragma_table_info('{}') WHERE name='{}'", table_name, column_name).as_str())?;
+            let column_iter = stmt.query_map([], |row| Ok(row.get::<usize, String>(0)?))?;
+            Ok(column_iter.count() > 0)
+        }).await?;
+        Ok(result)
+    }
+
     /// 创建数据表
     async fn create_table(&self) -> tokio_rusqlite::Result<()>;
 
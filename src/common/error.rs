@@ -43,4 +43,40 @@ impl Display for DriverError {
     }
 }
 
-impl Error for DriverError {}
\ No newline at end of file
+impl Error for DriverError {}
+
+/// error of device command
+/// invalid param is replied as param failure, the others as device error
+#[derive(Debug)]
+pub enum CommandError {
+    // the command or its param is invalid, e.g. channel out of range
+    Param(String),
+    // the device cannot execute the command, e.g. bus is not available
+    Device(DriverError),
+}
+
+impl CommandError {
+    pub fn msg(&self) -> &str {
+        match self {
+            CommandError::Param(msg) => msg,
+            CommandError::Device(e) => &e.0,
+        }
+    }
+}
+
+impl From<DriverError> for CommandError {
+    fn from(e: DriverError) -> Self {
+        CommandError::Device(e)
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CommandError::Param(msg) => write!(f, "invalid command param: {}", msg),
+            CommandError::Device(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for CommandError {}
\ No newline at end of file
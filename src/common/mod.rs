@@ -4,4 +4,5 @@ pub mod sqlite;
 pub mod http;
 pub mod error;
 pub mod mqtt;
-pub mod dao;
\ No newline at end of file
+pub mod dao;
+pub mod supervisor;
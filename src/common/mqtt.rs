@@ -1,12 +1,23 @@
 //! MQTT 服务连接器
 
-use std::{pin::Pin, sync::{mpsc, Arc}};
+use std::{pin::Pin, sync::{mpsc, Arc}, time::Duration};
 
 use paho_mqtt;
 use crate::{debug, error, info, trace, warn};
 
 const LOG_TAG : &str = "mqtt";
 
+/// TLS 证书文件配置
+#[derive(Debug, Clone, Default)]
+pub struct MqttTlsConfig {
+    /// CA 证书，None 使用系统默认证书
+    pub ca_file: Option<String>,
+    /// 客户端证书
+    pub cert_file: Option<String>,
+    /// 客户端私钥
+    pub key_file: Option<String>,
+}
+
 pub struct MqttConnection {
     /// 远程服务器地址
     host: String,
@@ -17,6 +28,21 @@ pub struct MqttConnection {
     // client_id
     client_id: String,
 
+    /// 自动重连最小间隔
+    reconnect_min_interval: Duration,
+
+    /// 自动重连最大间隔
+    reconnect_max_interval: Duration,
+
+    /// 用户名密码
+    credentials: Option<(String, String)>,
+
+    /// TLS 设置，None 表示不使用 TLS
+    tls: Option<MqttTlsConfig>,
+
+    /// 遗嘱消息，连接异常断开时由服务器发布
+    last_will: Option<paho_mqtt::Message>,
+
     /// 连接客户端对象
     client: Option<paho_mqtt::AsyncClient>
 }
@@ -27,10 +53,48 @@ impl MqttConnection {
             host: host.to_string(),
             port,
             client_id: client_id.to_string(),
+            reconnect_min_interval: Duration::from_secs(1),
+            reconnect_max_interval: Duration::from_secs(60),
+            credentials: None,
+            tls: None,
+            last_will: None,
             client: None
         }
     }
 
+    /// set the interval range of automatic reconnect, must be called before connect
+    pub fn set_reconnect_interval(&mut self, min: Duration, max: Duration) {
+        self.reconnect_min_interval = min;
+        self.reconnect_max_interval = max;
+    }
+
+    /// set username and password, must be called before connect
+    pub fn set_credentials(&mut self, username: &str, password: &str) {
+        self.credentials = Some((username.to_string(), password.to_string()));
+    }
+
+    /// enable TLS connection, must be called before connect
+    pub fn set_tls(&mut self, tls: MqttTlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    /// set retained last will message, must be called before connect
+    pub fn set_last_will(&mut self, topic: &str, payload: &str, qos: i32) {
+        self.last_will = Some(paho_mqtt::Message::new_retained(topic, payload, qos));
+    }
+
+    /// callback when the connection is re-established by automatic reconnect
+    pub fn set_connected_callback(&mut self, callback: impl Fn(&paho_mqtt::AsyncClient) + Send + 'static) {
+        self.client.as_mut().unwrap().set_connected_callback(callback);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        match &self.client {
+            Some(client) => client.is_connected(),
+            None => false,
+        }
+    }
+
     pub fn set_callback(&mut self, callback: impl FnMut(&paho_mqtt::AsyncClient, Option<paho_mqtt::Message>) + Send + 'static) {
         self.client.as_mut().unwrap().set_message_callback(callback);
     }
@@ -38,20 +102,42 @@ impl MqttConnection {
     /// init mqtt server
     /// 传入的 tx 为发送消息的 mpsc 通道，目前支持标准库的多发单收
     pub fn connect(&mut self) -> Result<(), paho_mqtt::Error> {
+        let scheme = if self.tls.is_some() { "ssl" } else { "tcp" };
         let create_opts = paho_mqtt::CreateOptionsBuilder::new()
-            .server_uri(format!("tcp://{}:{}", self.host.as_str(), self.port))
+            .server_uri(format!("{}://{}:{}", scheme, self.host.as_str(), self.port))
             .client_id(self.client_id.as_str())
             .finalize();
         
         let client = paho_mqtt::AsyncClient::new(create_opts)?;
         
-        let conn_opts = paho_mqtt::ConnectOptionsBuilder::new() 
+        let mut conn_builder = paho_mqtt::ConnectOptionsBuilder::new();
+        conn_builder
             .keep_alive_interval(std::time::Duration::from_secs(20))
             .clean_session(true)
-            .finalize();
+            .automatic_reconnect(self.reconnect_min_interval, self.reconnect_max_interval);
+        if let Some(will) = &self.last_will {
+            conn_builder.will_message(will.clone());
+        }
+        if let Some((username, password)) = &self.credentials {
+            conn_builder.user_name(username.as_str()).password(password.as_str());
+        }
+        if let Some(tls) = &self.tls {
+            let mut ssl_builder = paho_mqtt::SslOptionsBuilder::new();
+            if let Some(ca_file) = &tls.ca_file {
+                ssl_builder.trust_store(ca_file)?;
+            }
+            if let Some(cert_file) = &tls.cert_file {
+                ssl_builder.key_store(cert_file)?;
+            }
+            if let Some(key_file) = &tls.key_file {
+                ssl_builder.private_key(key_file)?;
+            }
+            conn_builder.ssl_options(ssl_builder.finalize());
+        }
+        let conn_opts = conn_builder.finalize();
 
         client.set_connection_lost_callback(|_cli| {
-            error!(LOG_TAG, "*** mqtt Connection lost ***");
+            error!(LOG_TAG, "*** mqtt Connection lost, waiting for reconnect ***");
         });
 
         // client.set_message_callback(move |_cli, msg| {
@@ -75,8 +161,13 @@ impl MqttConnection {
     }
     
 
-    pub fn publish(&self, topic: &str, payload: &str) -> Result<(), paho_mqtt::Error> {
-        let msg = paho_mqtt::Message::new(topic, payload, 0);
+    /// publish message, retained message with empty payload will clear the retained message of the topic
+    pub fn publish(&self, topic: &str, payload: &str, qos: i32, retained: bool) -> Result<(), paho_mqtt::Error> {
+        let msg = if retained {
+            paho_mqtt::Message::new_retained(topic, payload, qos)
+        } else {
+            paho_mqtt::Message::new(topic, payload, qos)
+        };
         if let Some(client) = &self.client {
             client.publish(msg).wait()?;
         } else {
@@ -86,9 +177,18 @@ impl MqttConnection {
         Ok(())
     }
 
-    pub fn subscribe(&self, topic: &str) -> Result<(), paho_mqtt::Error> {
+    /// disconnect normally, last will message will not be published
+    pub fn disconnect(&self) -> Result<(), paho_mqtt::Error> {
+        if let Some(client) = &self.client {
+            client.disconnect(None).wait()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn subscribe(&self, topic: &str, qos: i32) -> Result<(), paho_mqtt::Error> {
         if let Some(client) = &self.client {
-            client.subscribe(topic, 0).wait()?;
+            client.subscribe(topic, qos).wait()?;
         } else {
             error!(LOG_TAG, "mqtt subscribe failed, no connection");
         }
@@ -110,7 +210,7 @@ mod test {
         init_logger().expect("初始化日志失败");
         let mut mqtt = MqttConnection::new("127.0.0.1", 1883, "test_client");
         mqtt.connect().unwrap();
-        mqtt.subscribe("test").unwrap();
+        mqtt.subscribe("test", 0).unwrap();
         println!("wait for message");
         println!("进程已结束，进入等待……");
     }
@@ -24,6 +24,13 @@ pub struct Server {
     pub server_id: String,
     pub server_ip: String,
     pub server_type: String,
+    /// 收到 SIGINT / SIGTERM 后等待设备进入安全状态、各线程退出的最长时间（毫秒）
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    5000
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,6 +49,114 @@ pub struct Mqtt {
     pub broker_host: String,
     pub broker_port: i32,
     pub client_id: String,
+    /// 断线重连最小间隔（秒），每次失败后翻倍
+    #[serde(default = "default_reconnect_min_interval")]
+    pub reconnect_min_interval: u64,
+    /// 断线重连最大间隔（秒）
+    #[serde(default = "default_reconnect_max_interval")]
+    pub reconnect_max_interval: u64,
+    /// 断线期间缓存的上行消息数量上限
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// 缓存已满时的丢弃策略
+    #[serde(default)]
+    pub buffer_drop_policy: BufferDropPolicy,
+    /// 指令 topic 的订阅 QoS，指令回复也使用该 QoS
+    #[serde(default = "default_command_qos")]
+    pub command_qos: i32,
+    /// 设备状态上报的 QoS
+    #[serde(default)]
+    pub status_qos: i32,
+    /// 服务器心跳的 QoS
+    #[serde(default)]
+    pub heartbeat_qos: i32,
+    /// 设备状态是否以 retained 消息发布，后订阅的客户端可以立即拿到当前状态
+    #[serde(default)]
+    pub retain_status: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 是否使用 TLS 连接（ssl://）
+    #[serde(default)]
+    pub tls: bool,
+    /// CA 证书文件，不填则使用系统默认证书
+    pub ca_file: Option<String>,
+    /// 客户端证书文件（双向认证）
+    pub cert_file: Option<String>,
+    /// 客户端私钥文件（双向认证）
+    pub key_file: Option<String>,
+}
+
+/// drop policy of mqtt outbound buffer when it is full
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BufferDropPolicy {
+    /// drop the oldest buffered message, keep the latest state
+    #[default]
+    Oldest,
+    /// drop the incoming message, keep the buffered ones
+    Newest,
+}
+
+fn default_reconnect_min_interval() -> u64 {
+    1
+}
+
+fn default_reconnect_max_interval() -> u64 {
+    60
+}
+
+fn default_buffer_size() -> usize {
+    1000
+}
+
+fn default_command_qos() -> i32 {
+    1
+}
+
+/// 设备配置来源
+#[derive(Debug, Deserialize, Default)]
+pub struct DeviceConfig {
+    #[serde(default)]
+    pub source: DeviceConfigSource,
+    /// 本地设备配置文件，按扩展名解析 yaml / yml / toml / json
+    pub file: Option<String>,
+}
+
+/// source of device config
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceConfigSource {
+    /// get config from flow server, use local cache if flow server is not available
+    #[default]
+    Remote,
+    /// read config from local file only
+    Local,
+    /// get config from flow server, then devices in local file are added or replace the remote ones
+    RemoteWithLocalOverride,
+}
+
+/// 本地定时指令
+#[derive(Debug, Deserialize)]
+pub struct Scheduler {
+    /// cron 表达式匹配的时区名称，如 Asia/Shanghai，遵循夏令时；省略时使用系统本地时区
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// 错过执行时间（如重启期间）的定时指令，延迟不超过该时间（毫秒）仍然执行，否则单次定时指令丢弃，cron 定时指令跳到下一次
+    #[serde(default = "default_misfire_grace")]
+    pub misfire_grace: u64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            timezone: None,
+            misfire_grace: default_misfire_grace(),
+        }
+    }
+}
+
+fn default_misfire_grace() -> u64 {
+    60000
 }
 
 #[derive(Debug, Deserialize)]
@@ -58,7 +173,11 @@ pub struct Settings {
     pub web: Web,
     pub pm2: Pm2,
     pub mqtt: Mqtt,
-    pub upstream: Upstream
+    pub upstream: Upstream,
+    #[serde(default)]
+    pub device_config: DeviceConfig,
+    #[serde(default)]
+    pub scheduler: Scheduler,
 }
 
 impl Default for Settings {
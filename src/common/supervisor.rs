@@ -0,0 +1,157 @@
+//! 工作线程守护
+//! - 工作函数返回错误或 panic 时，按退避时间在当前线程重新运行，正常返回则结束
+//! - 记录所有工作线程的运行状态和重启次数，随心跳上报
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+
+use crate::entity::dto::worker_status_dto::WorkerStatusDto;
+use crate::util::time::get_timestamp_millis;
+use crate::{error, info};
+
+const LOG_TAG: &str = "supervisor";
+
+// restart interval doubles after each failure, from min to max (millis)
+const RESTART_MIN_INTERVAL: u64 = 1000;
+const RESTART_MAX_INTERVAL: u64 = 60000;
+// restart interval is reset if the worker has been running longer than this (millis)
+const RESTART_RESET_DURATION: u64 = 60000;
+// interval of checking whether the joined thread has exited (millis)
+const JOIN_CHECK_INTERVAL: u64 = 10;
+
+pub struct Supervisor {
+    workers: Mutex<HashMap<String, WorkerStatusDto>>,
+}
+
+impl Supervisor {
+    pub fn get<'a>() -> &'a Self {
+        lazy_static! {
+            static ref SUPERVISOR: Supervisor = Supervisor {
+                workers: Mutex::new(HashMap::new()),
+            };
+        }
+        &SUPERVISOR
+    }
+
+    /// status of all running workers
+    pub fn get_worker_status(&self) -> HashMap<String, WorkerStatusDto> {
+        self.workers.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut WorkerStatusDto)) {
+        let mut workers = self.workers.lock().unwrap_or_else(|e| e.into_inner());
+        f(workers.entry(name.to_string()).or_default());
+    }
+
+    fn remove(&self, name: &str) {
+        self.workers.lock().unwrap_or_else(|e| e.into_inner()).remove(name);
+    }
+}
+
+/// run work in current thread until it returns Ok
+/// on error or panic, on_failure is called with the error message, then work is restarted with backoff
+pub fn supervise<E: Display>(
+    name: &str,
+    mut on_failure: impl FnMut(&str),
+    mut work: impl FnMut() -> Result<(), E>,
+) {
+    let mut interval = RESTART_MIN_INTERVAL;
+    loop {
+        Supervisor::get().update(name, |status| status.alive = true);
+        let started = Instant::now();
+        let error_msg = match panic::catch_unwind(AssertUnwindSafe(&mut work)) {
+            Ok(Ok(())) => {
+                info!(LOG_TAG, "worker exited: {}", name);
+                Supervisor::get().remove(name);
+                return;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(payload) => panic_message(payload),
+        };
+
+        if started.elapsed() >= Duration::from_millis(RESTART_RESET_DURATION) {
+            interval = RESTART_MIN_INTERVAL;
+        }
+        error!(
+            LOG_TAG,
+            "worker failed: {}, restart in {} ms, error msg: {}", name, interval, error_msg
+        );
+        Supervisor::get().update(name, |status| {
+            status.alive = false;
+            status.restart_count += 1;
+            status.error_msg = Some(error_msg.clone());
+            status.error_timestamp = Some(get_timestamp_millis());
+        });
+        on_failure(&error_msg);
+
+        thread::sleep(Duration::from_millis(interval));
+        interval = (interval * 2).min(RESTART_MAX_INTERVAL);
+    }
+}
+
+/// wait for the worker thread to exit, at most timeout
+/// return false if the thread is still running after timeout, the thread is detached then
+pub fn join_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    let started = Instant::now();
+    while !handle.is_finished() {
+        if started.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(JOIN_CHECK_INTERVAL));
+    }
+    let _ = handle.join();
+    true
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        format!("panic: {}", msg)
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        format!("panic: {}", msg)
+    } else {
+        "panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supervise_restart() {
+        let mut count = 0;
+        let mut failures: Vec<String> = Vec::new();
+        supervise(
+            "test_worker",
+            |msg| failures.push(msg.to_string()),
+            || {
+                count += 1;
+                match count {
+                    1 => Err("first failure".to_string()),
+                    2 => panic!("second failure"),
+                    _ => Ok(()),
+                }
+            },
+        );
+        assert_eq!(count, 3);
+        assert_eq!(failures, vec!["first failure".to_string(), "panic: second failure".to_string()]);
+        assert!(!Supervisor::get().get_worker_status().contains_key("test_worker"));
+    }
+
+    #[test]
+    fn test_join_timeout() {
+        let handle = thread::spawn(|| thread::sleep(Duration::from_millis(50)));
+        assert!(join_timeout(handle, Duration::from_millis(1000)));
+
+        let handle = thread::spawn(|| thread::sleep(Duration::from_millis(1000)));
+        assert!(!join_timeout(handle, Duration::from_millis(50)));
+    }
+}
@@ -0,0 +1,333 @@
+//! 设备配置加载
+//! 启动和热加载共用，配置来源由 settings 中的 device_config.source 决定
+//! - 从 flow server 拉取设备配置，写入本地缓存
+//! - 从本地缓存读取设备配置
+//! - 从本地配置文件读取设备配置（yaml / toml / json）
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::device_dao::DeviceDao;
+use super::driver_registry::DriverRegistry;
+use super::entity::device_po::DevicePo;
+use crate::common::dao::Dao;
+use crate::common::error::{DeviceServerError, ServerErrorCode};
+use crate::common::http;
+use crate::common::setting::{DeviceConfigSource, Settings};
+use crate::{error, info, warn};
+
+// url to update device config
+const UPDATE_CONFIG_URL: &str = "api/v1.2/device/config";
+const LOG_TAG: &str = "config_loader";
+
+pub struct DeviceConfigLoader {
+    device_dao: DeviceDao,
+}
+
+impl DeviceConfigLoader {
+    pub fn new() -> Self {
+        DeviceConfigLoader {
+            device_dao: DeviceDao::new(),
+        }
+    }
+
+    /// load device config according to the config source in settings
+    /// if fallback_to_cache is true, the local cache is used when flow server is not available
+    pub async fn load(&self, fallback_to_cache: bool) -> Result<Vec<DevicePo>, DeviceServerError> {
+        let setting = &Settings::get().device_config;
+        let device_po_list = match setting.source {
+            DeviceConfigSource::Remote => self.load_remote(fallback_to_cache).await?,
+            DeviceConfigSource::Local => load_from_file(setting.file.as_deref())?,
+            DeviceConfigSource::RemoteWithLocalOverride => {
+                let remote_list = self.load_remote(fallback_to_cache).await?;
+                let local_list = load_from_file(setting.file.as_deref())?;
+                merge_config(remote_list, local_list)
+            }
+        };
+        check_config(&device_po_list)?;
+        Ok(device_po_list)
+    }
+
+    /// get config from flow server and then load from database
+    async fn load_remote(&self, fallback_to_cache: bool) -> Result<Vec<DevicePo>, DeviceServerError> {
+        // 1. make sure the table exists
+        self.ensure_table().await?;
+
+        // 2. get remote config data and write to db
+        if let Err(e) = self.update_from_remote().await {
+            if !fallback_to_cache {
+                return Err(e);
+            }
+            error!(LOG_TAG, "cannot pull device config data from flow server, will use local data cache, err msg: {}", e);
+        }
+
+        // 3. load data from database
+        self.load_from_db().await
+    }
+
+    /// make sure the table exists
+    pub async fn ensure_table(&self) -> Result<(), DeviceServerError> {
+        self.device_dao
+            .ensure_table_exist()
+            .await
+            .map_err(|e| DeviceServerError {
+                code: ServerErrorCode::DatabaseError,
+                msg: format!("cannot ensure device table exist, error msg: {}", e),
+            })
+    }
+
+    /// get remote config data and replace the local cache
+    /// the local cache is kept if remote data cannot be got or parsed
+    pub async fn update_from_remote(&self) -> Result<(), DeviceServerError> {
+        let json_data = self.get_remote().await?;
+        let device_po_list = parse_config_data(&json_data)?;
+        check_config(&device_po_list)?;
+
+        // clear all data
+        self.device_dao
+            .clear_table()
+            .await
+            .map_err(|e| DeviceServerError {
+                code: ServerErrorCode::DatabaseError,
+                msg: format!("cannot save device config to db, clear table error: {}", e),
+            })?;
+        self.write_to_db(device_po_list).await?;
+        info!(
+            LOG_TAG,
+            "successfully got device config data from flow server"
+        );
+        Ok(())
+    }
+
+    /// load config from database
+    pub async fn load_from_db(&self) -> Result<Vec<DevicePo>, DeviceServerError> {
+        self.device_dao
+            .get_all()
+            .await
+            .map_err(|e| DeviceServerError {
+                code: ServerErrorCode::DatabaseError,
+                msg: format!(
+                    "error loading device config from database, error msg: {}",
+                    e
+                ),
+            })
+    }
+
+    /// get config data from remote
+    async fn get_remote(&self) -> Result<Value, DeviceServerError> {
+        let url = format!("{}/{}", UPDATE_CONFIG_URL, Settings::get().server.server_id);
+        info!(
+            LOG_TAG,
+            "get remote config data from flow server, url: {}", &url
+        );
+        http::api_get(url.as_str()).await
+    }
+
+    /// svae device config to db
+    async fn write_to_db(&self, device_po_list: Vec<DevicePo>) -> Result<(), DeviceServerError> {
+        for device_po in device_po_list {
+            self.device_dao
+                .add_device_config(device_po)
+                .await
+                .map_err(|e| DeviceServerError {
+                    code: ServerErrorCode::DatabaseError,
+                    msg: format!("error writing device config to database, error msg: {}", e),
+                })?;
+        }
+        Ok(())
+    }
+}
+
+/// read device config from local file, the format is decided by file extension
+fn load_from_file(file_path: Option<&str>) -> Result<Vec<DevicePo>, DeviceServerError> {
+    let file_path = file_path.ok_or(DeviceServerError {
+        code: ServerErrorCode::FileConfigError,
+        msg: "device config file is not set, please set device_config.file in settings".to_string(),
+    })?;
+    info!(LOG_TAG, "load device config from local file: {}", file_path);
+    let content = std::fs::read_to_string(file_path).map_err(|e| DeviceServerError {
+        code: ServerErrorCode::FileSystemError,
+        msg: format!("cannot read device config file: {}, error msg: {}", file_path, e),
+    })?;
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    parse_config_file(&content, extension).map_err(|e| DeviceServerError {
+        code: ServerErrorCode::FileConfigError,
+        msg: format!("cannot parse device config file: {}, error msg: {}", file_path, e.msg),
+    })
+}
+
+/// parse config file content, the content has the same structure as remote config data
+fn parse_config_file(content: &str, extension: &str) -> Result<Vec<DevicePo>, DeviceServerError> {
+    let json_data: Value = match extension {
+        "yaml" | "yml" => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+        "toml" => toml::from_str(content).map_err(|e| e.to_string()),
+        "json" => serde_json::from_str(content).map_err(|e| e.to_string()),
+        _ => Err(format!("unsupported file type: {}", extension)),
+    }
+    .map_err(|msg| DeviceServerError {
+        code: ServerErrorCode::FileConfigError,
+        msg,
+    })?;
+    parse_config_data(&json_data)
+}
+
+/// devices in local config replace the remote ones with the same device_id, new devices are appended
+fn merge_config(remote_list: Vec<DevicePo>, local_list: Vec<DevicePo>) -> Vec<DevicePo> {
+    let mut device_po_list = remote_list;
+    for local_po in local_list {
+        match device_po_list
+            .iter_mut()
+            .find(|device_po| device_po.device_id == local_po.device_id)
+        {
+            Some(device_po) => *device_po = local_po,
+            None => device_po_list.push(local_po),
+        }
+    }
+    device_po_list
+}
+
+/// parse device list from remote config data, invalid device config is skipped
+fn parse_config_data(json_data: &Value) -> Result<Vec<DevicePo>, DeviceServerError> {
+    let device_list = json_data
+        .get("config")
+        .and_then(|config| config.as_array())
+        .ok_or(DeviceServerError {
+            code: ServerErrorCode::HttpError,
+            msg: "error reading config, cannot find list in config".to_string(),
+        })?;
+    let mut device_po_list = Vec::new();
+    for device in device_list {
+        if let Some(device_po) = transform_json_data_to_po(device.clone()) {
+            device_po_list.push(device_po);
+        } else {
+            warn!(LOG_TAG, "cannot parse device config json: {:?}", device);
+        }
+    }
+    Ok(device_po_list)
+}
+
+/// check config of every device against the typed config of its driver
+/// unknown device type is left to device factory, which marks the device as error
+fn check_config(device_po_list: &[DevicePo]) -> Result<(), DeviceServerError> {
+    for device_po in device_po_list {
+        if let Ok(driver) = DriverRegistry::get().find(&device_po.device_type) {
+            driver.check_config(&device_po.device_id, &device_po.config).map_err(|e| DeviceServerError {
+                code: ServerErrorCode::DeviceInfoError,
+                msg: format!("error loading device config, {}", e.0),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// make json object to device po
+fn transform_json_data_to_po(json_object: Value) -> Option<DevicePo> {
+    let device_po = DevicePo {
+        device_id: json_object.get("device_id")?.as_str()?.to_string(),
+        device_class: json_object.get("device_class")?.as_str()?.to_string(),
+        device_type: json_object.get("device_type")?.as_str()?.to_string(),
+        name: json_object.get("name")?.as_str()?.to_string(),
+        description: json_object.get("description")?.as_str()?.to_string(),
+        room: json_object.get("room").and_then(|room| room.as_str()).unwrap_or_default().to_string(),
+        config: json_object.get("config")?.clone(),
+    };
+    Some(device_po)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_config_data() {
+        let json_data = json!({"config": [
+            {"device_id": "bus", "device_class": "bus", "device_type": "dmx_bus", "name": "bus", "description": "", "config": {}},
+            {"device_id": "broken"},
+        ]});
+        let device_po_list = parse_config_data(&json_data).unwrap();
+        assert_eq!(device_po_list.len(), 1);
+        assert_eq!(device_po_list[0].device_id, "bus");
+        assert_eq!(device_po_list[0].room, "");
+
+        assert!(parse_config_data(&json!({})).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let yaml = r#"
+config:
+  - device_id: bus
+    device_class: bus
+    device_type: dmx_bus
+    name: bus
+    description: ""
+    config:
+      serial_port: /dev/ttyUSB0
+"#;
+        let device_po_list = parse_config_file(yaml, "yaml").unwrap();
+        assert_eq!(device_po_list[0].config["serial_port"], "/dev/ttyUSB0");
+
+        let toml = r#"
+[[config]]
+device_id = "bus"
+device_class = "bus"
+device_type = "dmx_bus"
+name = "bus"
+description = ""
+config = { serial_port = "/dev/ttyUSB0" }
+"#;
+        assert_eq!(parse_config_file(toml, "toml").unwrap(), device_po_list);
+
+        let json = r#"{"config": [{"device_id": "bus", "device_class": "bus", "device_type": "dmx_bus",
+            "name": "bus", "description": "", "config": {"serial_port": "/dev/ttyUSB0"}}]}"#;
+        assert_eq!(parse_config_file(json, "json").unwrap(), device_po_list);
+
+        assert!(parse_config_file(json, "xml").is_err());
+        assert!(parse_config_file("config: [", "yaml").is_err());
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let make_po = |device_id: &str, name: &str| DevicePo {
+            device_id: device_id.to_string(),
+            device_class: "bus".to_string(),
+            device_type: "dmx_bus".to_string(),
+            name: name.to_string(),
+            description: "".to_string(),
+            room: "".to_string(),
+            config: json!({}),
+        };
+        let merged = merge_config(
+            vec![make_po("a", "remote"), make_po("b", "remote")],
+            vec![make_po("b", "local"), make_po("c", "local")],
+        );
+        let names: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|device_po| (device_po.device_id.as_str(), device_po.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("a", "remote"), ("b", "local"), ("c", "local")]);
+    }
+
+    #[test]
+    fn test_check_config() {
+        let json_data = json!({"config": [
+            {"device_id": "bus", "device_class": "bus", "device_type": "modbus_bus", "name": "bus", "description": "",
+                "config": {"serial_port": "/dev/modbus0"}},
+            {"device_id": "do_output", "device_class": "do", "device_type": "modbus_do_port", "name": "do", "description": "",
+                "config": {"master_device_id": "do_controller", "address": "one"}},
+            {"device_id": "unknown", "device_class": "unknown", "device_type": "unknown_type", "name": "unknown", "description": "",
+                "config": {}},
+        ]});
+        let device_po_list = parse_config_data(&json_data).unwrap();
+        assert!(check_config(&device_po_list[..1]).is_ok());
+        // unknown device type is marked as error by device factory
+        assert!(check_config(&device_po_list[2..]).is_ok());
+        let e = check_config(&device_po_list).unwrap_err();
+        assert!(e.msg.contains("device_id: do_output, field: address"));
+    }
+}
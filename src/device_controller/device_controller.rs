@@ -7,24 +7,21 @@ use std::collections::HashMap;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread::JoinHandle;
 
-use super::device_dao::DeviceDao;
+use super::config_loader::DeviceConfigLoader;
 use super::entity::device_po::DevicePo;
-use super::workers::device_thread::device_thread;
+use super::workers::device_thread::{device_thread, DeviceThreadCtx};
 use super::workers::heartbeating_thread::heartbeating_thread;
 use super::workers::reporting_thread::reporting_thread;
-use crate::common::dao::Dao;
-use crate::common::error::{DeviceServerError, ServerErrorCode};
-use crate::common::http;
-use crate::common::setting::Settings;
+use super::workers::scheduler_thread::scheduler_thread;
+use super::workers::scene_thread::scene_thread;
+use super::workers::state_snapshot_thread::state_snapshot_thread;
+use crate::common::error::DeviceServerError;
 use crate::device_controller::device_info_maker_helper::make_device_info;
-use crate::entity::dto::device_command_dto::DeviceCommandDto;
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
-use crate::entity::dto::mqtt_dto::DeviceToMqttEnum;
-use crate::{debug, error, info, trace, warn};
+use crate::entity::dto::mqtt_dto::{DeviceToMqttEnum, MqttToDeviceEnum};
+use crate::{debug, info};
 use std::sync::{mpsc, Arc, Mutex};
 
-// url to update device config
-const UPDATE_CONFIG_URL: &str = "api/v1.2/device/config";
 const LOG_TAG: &str = "device_manager";
 const HEARTBEAT_INTERVAL: u64 = 10000;
 
@@ -33,7 +30,7 @@ const HEARTBEAT_INTERVAL: u64 = 10000;
 /// - manage device incoming and outgoing data
 /// - double thread architecture, one thread for outgoing data, one thread for incoming data
 pub struct DeviceController {
-    device_dao: DeviceDao,
+    config_loader: DeviceConfigLoader,
     // configuration map of devices
     pub config_map: HashMap<String, DevicePo>,
     // configuration list of devices
@@ -45,7 +42,7 @@ pub struct DeviceController {
 impl DeviceController {
     pub fn new() -> Self {
         DeviceController {
-            device_dao: DeviceDao::new(),
+            config_loader: DeviceConfigLoader::new(),
             config_map: HashMap::new(),
             config_list: Vec::new(),
             device_info_map: Arc::new(Mutex::new(HashMap::new())),
@@ -55,24 +52,43 @@ impl DeviceController {
     /// start the device manager work
     /// - heartbeating thread: send heartbeat periodically
     /// - device thread: create device and controller command sending
-    /// - reporting thread: listen to devices status change and report to mqtt client
+    /// - reporting thread: listen to devices status change and report to mqtt client, fire local rules through device_command_tx
+    /// - scheduler thread: fire scheduled commands through device_command_tx
+    /// - scene thread: save and load scenes, send the commands of recalled scene through device_command_tx
     ///
     /// CAUTION: after calling this function, DeviceManager will drop,
     /// so be sure that device_command_tx is cloned before calling this function
     pub fn run_threads(
         self,
         device_to_mqtt_tx: Sender<DeviceToMqttEnum>,
-        device_command_rx: Receiver<DeviceCommandDto>,
+        device_command_tx: Sender<MqttToDeviceEnum>,
+        device_command_rx: Receiver<MqttToDeviceEnum>,
     ) -> Vec<JoinHandle<()>> {
         let (state_report_tx, state_report_rx) = mpsc::channel();
+        // device config is replaced by device thread when reloading
+        let config_map = Arc::new(Mutex::new(self.config_map.clone()));
+        // heartbeating stops when device thread is shutting down
+        let (heartbeat_stop_tx, heartbeat_stop_rx) = mpsc::channel();
+        // state snapshot stops when device thread is shutting down
+        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+        // scheduler stops when device thread is shutting down
+        let (scheduler_tx, scheduler_rx) = mpsc::channel();
+        // scene thread stops when device thread is shutting down
+        let (scene_tx, scene_rx) = mpsc::channel();
         let mut ret: Vec<JoinHandle<()>> = Vec::new();
         // 1 start device thread
-        let device_handle = device_thread(
+        let device_thread_ctx = DeviceThreadCtx {
             state_report_tx,
-            device_command_rx,
-            self.config_list.clone(),
-            self.device_info_map.clone(),
-        );
+            command_rx: device_command_rx,
+            device_to_mqtt_tx: device_to_mqtt_tx.clone(),
+            device_config_map: config_map.clone(),
+            device_info_map: self.device_info_map.clone(),
+            heartbeat_stop_tx,
+            snapshot_tx: snapshot_tx.clone(),
+            scheduler_tx,
+            scene_tx,
+        };
+        let device_handle = device_thread(device_thread_ctx, self.config_list.clone());
         ret.push(device_handle);
         debug!(
             LOG_TAG,
@@ -84,6 +100,8 @@ impl DeviceController {
             state_report_rx,
             device_to_mqtt_tx.clone(),
             self.device_info_map.clone(),
+            snapshot_tx,
+            device_command_tx.clone(),
         );
         ret.push(reporting_handle);
         debug!(
@@ -95,8 +113,9 @@ impl DeviceController {
         let heartbeating_handle = heartbeating_thread(
             HEARTBEAT_INTERVAL,
             self.device_info_map.clone(),
-            self.config_map.clone(),
+            config_map.clone(),
             device_to_mqtt_tx.clone(),
+            heartbeat_stop_rx,
         );
         ret.push(heartbeating_handle);
         debug!(
@@ -104,60 +123,68 @@ impl DeviceController {
             "device manager worker starting: heartbeating thread called"
         );
 
+        // 4 start state snapshot thread
+        let snapshot_handle = state_snapshot_thread(snapshot_rx);
+        ret.push(snapshot_handle);
+        debug!(
+            LOG_TAG,
+            "device manager worker starting: state snapshot thread called"
+        );
+
+        // 5 start scheduler thread
+        let scheduler_handle = scheduler_thread(
+            scheduler_rx,
+            device_command_tx.clone(),
+            device_to_mqtt_tx.clone(),
+            self.device_info_map.clone(),
+        );
+        ret.push(scheduler_handle);
+        debug!(
+            LOG_TAG,
+            "device manager worker starting: scheduler thread called"
+        );
+
+        // 6 start scene thread
+        let scene_handle = scene_thread(
+            scene_rx,
+            device_command_tx,
+            device_to_mqtt_tx.clone(),
+            self.device_info_map.clone(),
+        );
+        ret.push(scene_handle);
+        debug!(
+            LOG_TAG,
+            "device manager worker starting: scene thread called"
+        );
+
         ret
     }
 
     pub fn start(
         mut self,
         device_to_mqtt_tx: Sender<DeviceToMqttEnum>,
-        device_command_rx: Receiver<DeviceCommandDto>,
+        device_command_tx: Sender<MqttToDeviceEnum>,
+        device_command_rx: Receiver<MqttToDeviceEnum>,
     ) -> Result<Vec<JoinHandle<()>>, DeviceServerError> {
         self.ready()?;
-        Ok(self.run_threads(device_to_mqtt_tx, device_command_rx))
+        Ok(self.run_threads(device_to_mqtt_tx, device_command_tx, device_command_rx))
     }
 
     /// init device manager
-    /// - read config from remote server
+    /// - read config from remote server or local file
     /// - update local data
     pub fn ready(&mut self) -> Result<(), DeviceServerError> {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async {
-        // 1. make sure the table exists
-            self.device_dao
-                .ensure_table_exist()
-                .await
-                .map_err(|e| DeviceServerError {
-                    code: ServerErrorCode::DatabaseError,
-                    msg: format!("cannot ensure device table exist, error msg: {}", e),
-                })?;
-
-            // 2. get remote config data and write to db
-            match self.get_remote().await {
-                Ok(json_data) => {
-                    // clear all data
-                    self.device_dao
-                        .clear_table()
-                        .await
-                        .map_err(|e| DeviceServerError {
-                            code: ServerErrorCode::DatabaseError,
-                            msg: format!("cannot save device config to db, clear table error: {}", e),
-                        })?;
-                    self.write_to_db(json_data).await?;
-                    info!(
-                        LOG_TAG,
-                        "successfully got device config data from flow server"
-                    );
-                }
-                Err(e) => {
-                    error!(LOG_TAG, "cannot pull device config data from flow server, will use local data cache, err msg: {}", e);
-                }
+            // 1. load device config from flow server, local cache or local file
+            for device_config_po in self.config_loader.load(true).await? {
+                self.config_map
+                    .insert(device_config_po.device_id.clone(), device_config_po.clone());
+                self.config_list.push(device_config_po);
             }
+            info!(LOG_TAG, "successfully load device config data");
 
-            // 3. load data from database
-            self.load_from_db().await?;
-            info!(LOG_TAG, "successfully load device config data from db");
-
-            // 4. make device info map
+            // 2. make device info map
             self.device_info_map = Arc::new(Mutex::new(make_device_info(
                 self.config_list.clone(),
             )?));
@@ -168,74 +195,6 @@ impl DeviceController {
         info!(LOG_TAG, "device manager data ready");
         Ok(())
     }
-
-    /// get config data from remote
-    async fn get_remote(&mut self) -> Result<Value, DeviceServerError> {
-        let url = format!("{}/{}", UPDATE_CONFIG_URL, Settings::get().server.server_id);
-        info!(
-            LOG_TAG,
-            "get remote config data from flow server, url: {}", &url
-        );
-        http::api_get(url.as_str()).await
-    }
-
-    /// svae device config to db
-    async fn write_to_db(&self, json_data: Value) -> Result<(), DeviceServerError> {
-        let device_list = json_data
-            .get("config")
-            .unwrap()
-            .as_array()
-            .expect("error writing config, cannot find list in config");
-        for device in device_list {
-            if let Some(device_po) = transform_json_data_to_po(device.clone()) {
-                self.device_dao
-                    .add_device_config(device_po)
-                    .await
-                    .map_err(|e| DeviceServerError {
-                        code: ServerErrorCode::DatabaseError,
-                        msg: format!("error writing device config to database, error msg: {}", e),
-                    })?;
-            } else {
-                warn!(LOG_TAG, "cannot parse device config json: {:?}", device);
-            }
-        }
-        Ok(())
-    }
-
-    /// load config from database
-    async fn load_from_db(&mut self) -> Result<(), DeviceServerError> {
-        let device_config_po_list: Vec<DevicePo> =
-            self.device_dao
-                .get_all()
-                .await
-                .map_err(|e| DeviceServerError {
-                    code: ServerErrorCode::DatabaseError,
-                    msg: format!(
-                        "error loading device config from database, error msg: {}",
-                        e
-                    ),
-                })?;
-        for device_config_po in device_config_po_list {
-            self.config_map
-                .insert(device_config_po.device_id.clone(), device_config_po.clone());
-            self.config_list.push(device_config_po);
-        }
-        Ok(())
-    }
-}
-
-// make json object to device po
-fn transform_json_data_to_po(json_object: Value) -> Option<DevicePo> {
-    let device_data = json_object.as_object()?;
-    let device_po = DevicePo {
-        device_id: json_object.get("device_id")?.as_str()?.to_string(),
-        device_class: json_object.get("device_class")?.as_str()?.to_string(),
-        device_type: json_object.get("device_type")?.as_str()?.to_string(),
-        name: json_object.get("name")?.as_str()?.to_string(),
-        description: json_object.get("description")?.as_str()?.to_string(),
-        config: json_object.get("config")?.clone(),
-    };
-    Some(device_po)
 }
 
 // make device config to str
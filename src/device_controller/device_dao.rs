@@ -40,6 +40,7 @@ impl Dao for DeviceDao {
                         device_type     TEXT NOT NULL,
                         name            TEXT NOT NULL,
                         description     TEXT NOT NULL,
+                        room            TEXT NOT NULL DEFAULT '',
                         config          TEXT NOT NULL
                     )",
                 (),
@@ -65,6 +66,11 @@ impl DeviceDao {
         let is_exist = self.check_table(self.table_name).await?;
         if is_exist {
             debug!(LOG_TAG, "device cache table already exist");
+            // cache table created by older version has no room column
+            if !self.check_column(self.table_name, "room").await? {
+                self.add_room_column().await?;
+                info!(LOG_TAG, "device cache table migrated, room column added");
+            }
         } else {
             self.create_table().await?;
             debug!(LOG_TAG, "device cache table init");
@@ -72,6 +78,16 @@ impl DeviceDao {
         Ok(())
     }    
 
+    async fn add_room_column(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(|conn| {
+            conn.execute("ALTER TABLE device ADD COLUMN room TEXT NOT NULL DEFAULT ''", ())
+        }).await?;
+
+        Ok(())
+    }
+
     /// 将单个设备加入缓存
     pub async fn add_device_config(&self, device_config: DevicePo) -> tokio_rusqlite::Result<()> {
         let conn = SqliteConnection::get().open().await?;
@@ -80,13 +96,14 @@ impl DeviceDao {
 
         conn.call(move |conn| {
             conn.execute(
-                "INSERT INTO device (device_id, device_class, device_type, name, description, config) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                "INSERT INTO device (device_id, device_class, device_type, name, description, room, config) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
                 params![
                     device_config_copy.device_id,
                     device_config_copy.device_class,
                     device_config_copy.device_type, 
                     device_config_copy.name, 
                     device_config_copy.description,
+                    device_config_copy.room,
                     device_config_copy.config.to_string()
                 ],
             )
@@ -100,16 +117,17 @@ impl DeviceDao {
 
         let res = conn.call(|conn| {
             let mut stmt = conn.prepare(
-                "SELECT device_id, device_class, device_type, name, description, config FROM device",
+                "SELECT device_id, device_class, device_type, name, description, room, config FROM device",
             )?;
             let device_iter = stmt.query_map([], |row| {
-                let config_str: String = row.get(5)?;
+                let config_str: String = row.get(6)?;
                 Ok(DevicePo {
                     device_id: row.get(0)?,
                     device_class: row.get(1)?,
                     device_type: row.get(2)?,
                     name: row.get(3)?,
                     description: row.get(4)?,
+                    room: row.get(5)?,
                     config: serde_json::from_str(&config_str).unwrap_or_default(),
                 })
             })?;
@@ -1,17 +1,15 @@
 //! device factory registery
 
+use super::driver_registry::DriverRegistry;
 use super::entity::device_enum::DeviceRefEnum;
 use super::entity::device_po::DevicePo;
-use super::factory::*;
-use crate::driver::modbus::traits::{ModbusDiControllerListener, ModbusListener};
-use crate::driver::modbus::{modbus_bus, modbus_di_controller_coil};
+use super::traits::DriverContext;
+use crate::common::error::DriverError;
 use crate::entity::dto::device_meta_info_dto::{DeviceMetaInfoDto, DeviceStatusEnum};
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::{common::error::DriverError, driver::modbus::modbus_bus::ModbusBus};
+use crate::util::time::get_timestamp_millis;
 use crate::{debug, error, info, trace, warn};
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 use std::sync::{mpsc, Arc, Mutex};
 
 const LOG_TAG: &str = "device_factory";
@@ -25,6 +23,8 @@ pub struct DeviceInstanceFactory {
     device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
     device_po_list: Vec<DevicePo>,
     report_tx_dummy: mpsc::Sender<StateToDeviceControllerDto>,
+    // devices failed to create and the reason
+    failed_list: Vec<(String, DriverError)>,
 }
 
 impl DeviceInstanceFactory {
@@ -34,6 +34,7 @@ impl DeviceInstanceFactory {
             device_info_map: Arc::new(Mutex::new(HashMap::new())),
             device_po_list: Vec::new(),
             report_tx_dummy,
+            failed_list: Vec::new(),
         }
     }
 
@@ -64,350 +65,186 @@ impl DeviceInstanceFactory {
             let device_map_guard = self
                 .device_info_map
                 .lock()
-                .map_err(|e| DriverError(format!("get device info map mutex error: {}", e)))?;
+                .unwrap_or_else(|e| e.into_inner());
             device_info_map = device_map_guard.clone();
         }
 
-        for device_po in device_po_list {
+        // 1. sort devices so that master device is made before its slaves
+        let (device_id_list, failed_list) = sort_by_dependency(&device_po_list, &device_info_map);
+        for (device_id, e) in failed_list {
+            error!(LOG_TAG, "cannot create device, device_id: {}, error msg: {}", device_id, e.0);
+            self.failed_list.push((device_id, e));
+        }
+
+        let mut created_list: Vec<String> = Vec::new();
+        for device_id in device_id_list {
             let device_info =
                 device_info_map
-                    .get(device_po.device_id.as_str())
+                    .get(device_id.as_str())
                     .ok_or(DriverError(format!(
                         "cannot find device info for device_id: {}",
-                        device_po.device_id.as_str()
+                        device_id.as_str()
                     )))?;
-            // 1. use device info to make device object, create_device will put device object to device_enum_map, and will change DeviceInfoDto statue to initialized
-            let result = self.create_device(&device_info);
-
-            // 2. update device status to "Initialized"
-            {
-                let mut device_map_guard = self
-                    .device_info_map
-                    .lock()
-                    .map_err(|e| DriverError(format!("get device info map mutex error: {}", e)))?;
-                if let Some(data_mut) = device_map_guard.get_mut(device_po.device_id.as_str()) {
-                    data_mut.device_status = DeviceStatusEnum::Initialized;
-                } else {
-                    return Err(DriverError(format!(
-                        "update device status failed, getting mut reference failed, device_id: {}",
-                        device_po.device_id
-                    )));
+            // 2. skip the device if its master device failed, the failure cascades to all slaves
+            if let Some(master_device_id) = &device_info.master_device_id {
+                if self.failed_list.iter().any(|(failed_id, _)| failed_id == master_device_id) {
+                    let e = DriverError(format!(
+                        "master device `{}` failed to create, device_id: {}",
+                        master_device_id, device_id
+                    ));
+                    error!(LOG_TAG, "cannot create device, device_id: {}, error msg: {}", device_id, e.0);
+                    self.failed_list.push((device_id, e));
+                    continue;
                 }
             }
 
-            match result {
-                Ok(_) => {}
+            // 3. use device info to make device object, create_device will put device object to device_enum_map
+            match self.create_device(&device_info, &device_info_map) {
+                Ok(_) => created_list.push(device_id),
                 Err(e) => {
                     error!(
                         LOG_TAG,
                         "create device failed, device_id: {}, error msg: {}",
-                        device_po.device_id,
+                        device_id,
                         e.0.as_str()
                     );
+                    self.failed_list.push((device_id, e));
                 }
             }
         }
-        Ok(())
-    }
 
-    /// make device by one device info bo
-    /// this function will make the device and change device_enum map
-    fn create_device(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        if dto.device_type == "modbus_bus" {
-            let _ = self.make_modbus(dto)?;
-        } else if dto.device_type == "serial_bus" {
-            let _ = self.make_serial_bus(dto)?;
-        } else if dto.device_type == "dmx_bus" {
-            let _ = self.make_dmx_bus(dto)?;
-        } else if dto.device_type == "modbus_do_controller" {
-            let _ = self.make_do_controller(dto)?;
-        } else if dto.device_type == "modbus_do_port" {
-            let _ = self.make_do_port(dto)?;
-        } else if dto.device_type == "modbus_di_controller" {
-            let _ = self.make_di_controller(dto)?;
-        } else if dto.device_type == "modbus_di_port" {
-            let _ = self.make_di_port(dto)?;
-        } else if dto.device_type == "remote" {
-            let _ = self.make_remote_controller(dto)?;
-        } else if dto.device_type == "audio" {
-            let _ = self.make_audio(dto)?;
-        } else if dto.device_type == "dmx_channel" {
-            let _ = self.make_dmx_channel_device(dto)?;
-        } else {
-            return Err(DriverError(format!(
-                "unknown device type. device_type={}",
-                dto.device_type
-            )));
+        // 4. update device status, "Initialized" for created devices and "ERROR" for failed ones
+        {
+            let mut device_map_guard = self
+                .device_info_map
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            for device_id in created_list.iter() {
+                if let Some(data_mut) = device_map_guard.get_mut(device_id.as_str()) {
+                    data_mut.device_status = DeviceStatusEnum::Initialized;
+                }
+            }
+            let error_timestamp = get_timestamp_millis();
+            for (device_id, e) in self.failed_list.iter() {
+                if let Some(data_mut) = device_map_guard.get_mut(device_id.as_str()) {
+                    data_mut.device_status = DeviceStatusEnum::ERROR;
+                    data_mut.error_msg = Some(e.0.clone());
+                    data_mut.error_timestamp = Some(error_timestamp);
+                }
+            }
         }
-        info!(
-            LOG_TAG,
-            "device created! device_id: {}, device_type: {}", dto.device_id, dto.device_type
-        );
         Ok(())
     }
 
-    fn get_master_device_enum(&self, device_id: &str) -> Result<&DeviceRefEnum, DriverError> {
-        let master_device_enum =
-            self.device_enum_map
-                .get(device_id)
-                .ok_or(DriverError(format!(
-                    "device factory: cannot find master device: {}",
-                    device_id
-                )))?;
-        Ok(master_device_enum)
+    /// devices failed to create, including the ones with invalid master device
+    pub fn take_failed_devices(&mut self) -> Vec<(String, DriverError)> {
+        std::mem::take(&mut self.failed_list)
     }
 
-    fn make_audio(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        let audio = audio_factory::make(&dto, self.report_tx_dummy.clone())?;
-        self.device_enum_map.insert(
-            dto.device_id.clone(),
-            DeviceRefEnum::Audio(Rc::new(RefCell::new(audio))),
-        );
-        Ok(())
-    }
-
-    fn make_modbus(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        let modbus_bus = modbus_bus_factory::make(&dto, self.report_tx_dummy.clone())?;
-        self.device_enum_map.insert(
-            dto.device_id.clone(),
-            DeviceRefEnum::ModbusBus(Rc::new(RefCell::new(modbus_bus))),
-        );
-        Ok(())
-    }
-
-    fn make_dmx_bus(&mut self, bo: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        let dmx_bus = dmx_bus_factory::make(&bo, self.report_tx_dummy.clone())?;
-        self.device_enum_map.insert(
-            bo.device_id.clone(),
-            DeviceRefEnum::DmxBus(Rc::new(RefCell::new(dmx_bus))),
-        );
-        Ok(())
-    }
-
-    fn make_dmx_channel_device(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        if let Some(master_device_id) = &dto.master_device_id {
-            // 1 get dmx bus master device
-            let master_device_enum = self.get_master_device_enum(master_device_id.as_str())?;
-
-            if let DeviceRefEnum::DmxBus(dmx_bus) = master_device_enum {
-                // 2 make dmx channel
-                let dmx_channel = channel_device_factory::make(
-                    &dto,
-                    dmx_bus.clone(),
-                    self.report_tx_dummy.clone(),
-                )?;
-                self.device_enum_map.insert(
-                    dto.device_id.clone(),
-                    DeviceRefEnum::DmxChannel(Rc::new(RefCell::new(dmx_channel))),
-                );
-                Ok(())
-            } else {
-                Err(DriverError(format!(
-                    "device factory: cannot find master device during dmx channel init, master_device_id={}, device_id={}",
-                    master_device_id,
-                    dto.device_id
-                )))
-            }
-        } else {
-            Err(DriverError(format!(
-                "device_factory: no master_device_id for dmx channel, device_id={}",
-                dto.device_id
-            )))
+    /// make device by the driver of device type
+    /// the device is put into device_enum_map, unless it is mounted to its master device
+    fn create_device(
+        &mut self,
+        dto: &DeviceMetaInfoDto,
+        device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+    ) -> Result<(), DriverError> {
+        let driver = DriverRegistry::get().find(&dto.device_type)?;
+        let ctx = DriverContext {
+            device_info: dto,
+            device_enum_map: &self.device_enum_map,
+            device_info_map,
+            report_tx: self.report_tx_dummy.clone(),
+        };
+        if let Some(device_ref) = driver.make(&ctx)? {
+            self.device_enum_map.insert(dto.device_id.clone(), device_ref);
         }
-    }
-
-    fn make_serial_bus(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        let serial_bus = serial_bus_factory::make(&dto)?;
-        self.device_enum_map.insert(
-            dto.device_id.clone(),
-            DeviceRefEnum::SerialBus(Rc::new(RefCell::new(serial_bus))),
+        info!(
+            LOG_TAG,
+            "device created! device_id: {}, device_type: {}", dto.device_id, dto.device_type
         );
         Ok(())
     }
+}
 
-    fn make_remote_controller(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        if let Some(master_device_id) = &dto.master_device_id {
-            // get serial master device
-            let master_device_enum = self.get_master_device_enum(&master_device_id.as_str())?;
-            if let DeviceRefEnum::SerialBus(serial_bus_device) = master_device_enum {
-                // make remote controller
-                let remote_controller = remote_factory::make(&dto, self.report_tx_dummy.clone())?;
-                let mut serial_bus = serial_bus_device.borrow_mut();
-                serial_bus.add_listener(Box::new(remote_controller));
-                Ok(())
-            } else {
-                Err(DriverError(format!(
-                    "device facotry: cannot make serial remote controller, master_device_id={}, device_id={}",
-                    master_device_id,
-                    dto.device_id
-                )))
-            }
-        } else {
-            Err(DriverError(format!(
-                "device factory: no master_device_id for do controller, device_id={}",
-                dto.device_id
-            )))
-        }
-    }
+/// sort device ids by dependency of master_device_id, master device comes first, the order of config is kept otherwise
+/// devices with missing master device, in a master device cycle, or whose master device is invalid are returned as failed
+fn sort_by_dependency(
+    device_po_list: &[DevicePo],
+    device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+) -> (Vec<String>, Vec<(String, DriverError)>) {
+    let master_map: HashMap<&str, Option<&str>> = device_po_list
+        .iter()
+        .map(|device_po| {
+            let master_device_id = device_info_map
+                .get(device_po.device_id.as_str())
+                .and_then(|device_info| device_info.master_device_id.as_deref());
+            (device_po.device_id.as_str(), master_device_id)
+        })
+        .collect();
 
-    fn make_do_controller(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        if let Some(master_device_id) = &dto.master_device_id {
-            // get modbus master device
-            let master_device_enum = self.get_master_device_enum(master_device_id.as_str())?;
-            if let DeviceRefEnum::ModbusBus(master_device) = master_device_enum {
-                // make do controller device
-                let do_controller =
-                    do_controller_factory::make(&dto, master_device, self.report_tx_dummy.clone())?;
-                self.device_enum_map.insert(
-                    dto.device_id.clone(),
-                    DeviceRefEnum::ModbusDoController(Rc::new(RefCell::new(do_controller))),
-                );
-                Ok(())
-            } else {
-                Err(DriverError(format!(
-                    "device factory: cannot find master device during do controller init, master_device_id={}, device_id={}",
-                    master_device_id,
-                    dto.device_id
-                )))
+    // depth of device in dependency tree, or error message
+    let mut depth_map: HashMap<&str, Result<usize, String>> = HashMap::new();
+    for device_po in device_po_list {
+        // walk up along master device until a resolved device, a root device, a missing master or a cycle
+        let mut path: Vec<&str> = Vec::new();
+        let mut current = device_po.device_id.as_str();
+        loop {
+            if depth_map.contains_key(current) {
+                break;
             }
-        } else {
-            Err(DriverError(format!(
-                "device_factory: no master_device_id for do controller, device_id={}",
-                dto.device_id
-            )))
-        }
-    }
-
-    fn make_do_port(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        if let Some(master_device_id) = &dto.master_device_id {
-            // get modbus master device
-            let master_device_enum = self.get_master_device_enum(master_device_id.as_str())?;
-            if let DeviceRefEnum::ModbusDoController(master_device) = master_device_enum {
-                // make do port device
-                let do_port = do_port_factory::make(
-                    &dto,
-                    Rc::clone(master_device),
-                    self.report_tx_dummy.clone(),
-                )?;
-                let _ = self.device_enum_map.insert(
-                    dto.device_id.clone(),
-                    DeviceRefEnum::ModbusDoPort(Rc::new(RefCell::new(do_port))),
-                );
-                Ok(())
-            } else {
-                Err(DriverError(format!(
-                    "device factory: the master device is not modbus_controller during making do port: master_device_id={}, device_id={}",
-                    master_device_id,
-                    dto.device_id
-                )))
+            if let Some(pos) = path.iter().position(|device_id| *device_id == current) {
+                let cycle = format!("{} -> {}", path[pos..].join(" -> "), current);
+                for device_id in path.drain(pos..) {
+                    depth_map.insert(device_id, Err(format!("master device cycle: {}", cycle)));
+                }
+                break;
             }
-        } else {
-            Err(DriverError(format!(
-                "device_factory: do not find master device_id for do port, device_id={}",
-                dto.device_id
-            )))
-        }
-    }
-
-    fn make_di_controller(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        // init modbus_di_controller
-        // get master device (modbus), and insert itself into it
-        if let Some(master_device_id) = &dto.master_device_id {
-            // get modbus master device
-            let master_device_enum = self.get_master_device_enum(master_device_id.as_str())?;
-            if let DeviceRefEnum::ModbusBus(master_modbus_ref) = master_device_enum {
-                // make di controller device
-                let di_controller =
-                    di_controller_factory::make(&dto, self.report_tx_dummy.clone())?;
-                // mount to modbus bus device
-                let mut modbus = master_modbus_ref.borrow_mut();
-                modbus.add_di_controller(di_controller.get_unit(), Box::new(di_controller));
-                Ok(())
-            } else {
-                Err(DriverError(format!(
-                    "device factory: when init di_controller, the master device is not modbus_bus, master_device_id: {}, device_id: {}",
-                    master_device_id,
-                    dto.device_id
-                )))
+            match master_map.get(current).copied().flatten() {
+                None => {
+                    depth_map.insert(current, Ok(0));
+                    break;
+                }
+                Some(master_device_id) if !master_map.contains_key(master_device_id) => {
+                    depth_map.insert(
+                        current,
+                        Err(format!("master device `{}` not found in device config", master_device_id)),
+                    );
+                    break;
+                }
+                Some(master_device_id) => {
+                    path.push(current);
+                    current = master_device_id;
+                }
             }
-        } else {
-            Err(DriverError(format!(
-                "device_factory: do not find master_device_id for di port, device_id={}",
-                dto.device_id
-            )))
+        }
+        // the master device of each device in path is resolved now
+        for device_id in path.into_iter().rev() {
+            let master_device_id = master_map[device_id].unwrap_or_default();
+            let depth = match &depth_map[master_device_id] {
+                Ok(depth) => Ok(depth + 1),
+                Err(_) => Err(format!("master device `{}` is invalid", master_device_id)),
+            };
+            depth_map.insert(device_id, depth);
         }
     }
 
-    /// make di port is complicated, because di_port was hold by di_controller, and the controller was hold by modbus, the steps are:
-    /// 1 find master_device_id (di_controller)
-    /// 2 find di_controller's master deivce (modbus)
-    /// 3 bowrrow modbus
-    /// 4 borrow di_controller
-    /// 5 make di_port device
-    /// 6 mount di_port onto di_controller
-    fn make_di_port(&mut self, dto: &DeviceMetaInfoDto) -> Result<(), DriverError> {
-        // find modbus_di_controller, and insert modbus_di_port into it
-        if let Some(master_device_id) = &dto.master_device_id {
-            // find modbus controller's master_device_id
-            let controller_master_device_id: String;
-
-            {
-                let device_map_guard = self
-                    .device_info_map
-                    .lock()
-                    .map_err(|e| DriverError(format!("get device info map mutex error: {}", e)))?;
-                controller_master_device_id = device_map_guard.get(master_device_id.as_str())
-                            .ok_or(DriverError(format!(
-                                "device factory: cannot find master_device_id of di_controller for di port, di_controller_id: {}, device_id: {}",
-                                master_device_id, dto.device_id
-                            )))?.master_device_id.clone().ok_or(
-                                DriverError(format!(
-                                    "device factory: cannot find master_device_id of di_controller for di port, di_controller_id: {}, device_id: {}",
-                                    master_device_id, dto.device_id
-                                ))
-                            )?;
-            }
-
-            // borrow modbus bus
-            let master_device_enum =
-                self.get_master_device_enum(controller_master_device_id.as_str())?;
-            if let DeviceRefEnum::ModbusBus(master_modbus_ref) = master_device_enum {
-                // borrow modbus_di_controller
-                if let DeviceRefEnum::ModbusDiController(master_di_controller_ref) = self
-                    .device_enum_map
-                    .get(master_device_id.as_str())
-                    .ok_or(DriverError(format!(
-                        "device factory: cannot find master device for modbus_di_port: {}",
-                        master_device_id
-                    )))?
-                {
-                    // make di port device
-                    let di_port = di_port_factory::make(&dto, self.report_tx_dummy.clone())?;
-                    // mount to modbus_di_controller
-                    let mut di_controller = master_di_controller_ref.borrow_mut();
-                    di_controller.add_di_port(di_port.get_address(), Box::new(di_port))?;
-                    Ok(())
-                } else {
-                    return Err(DriverError(format!(
-                                "device factory: no master_di_controller for modbus_di_port in device config: master_device_id: {}, device_id: {}",
-                                master_device_id,
-                                dto.device_id
-                            )));
-                }
-            } else {
-                Err(DriverError(format!(
-                            "device factory: no find master device for modbus_di_port, master_device_id: {}, device_id: {}",
-                            master_device_id,
-                            dto.device_id
-                        )))
-            }
-        } else {
-            Err(DriverError(format!(
-                "device_factory: no master_device_id for di port, device_id={}",
-                dto.device_id
-            )))
+    let mut device_id_list: Vec<(usize, String)> = Vec::new();
+    let mut failed_list: Vec<(String, DriverError)> = Vec::new();
+    for device_po in device_po_list {
+        match &depth_map[device_po.device_id.as_str()] {
+            Ok(depth) => device_id_list.push((*depth, device_po.device_id.clone())),
+            Err(msg) => failed_list.push((
+                device_po.device_id.clone(),
+                DriverError(format!("{}, device_id: {}", msg, device_po.device_id)),
+            )),
         }
     }
+    // stable sort keeps config order for devices of the same depth
+    device_id_list.sort_by_key(|(depth, _)| *depth);
+    (
+        device_id_list.into_iter().map(|(_, device_id)| device_id).collect(),
+        failed_list,
+    )
 }
 
 #[cfg(test)]
@@ -417,6 +254,10 @@ mod tests {
 
     use super::*;
     use crate::common::logger::init_logger;
+    use crate::device_controller::device_info_maker_helper::make_device_info;
+    use crate::driver::modbus::traits::ModbusListener;
+    use crate::entity::dto::device_state_dto::{DiStateDto, StateDtoEnum};
+    use crate::entity::po::device_config_po::RestorePolicyEnum;
 
     fn set_env() {
         let _ = init_logger();
@@ -439,4 +280,136 @@ mod tests {
         // let _ = device_factory.make_device_with_config_map(modbus_config_value);
         // println!("done");
     }
+
+    fn make_config(list: &[(&str, Option<&str>)]) -> (Vec<DevicePo>, HashMap<String, DeviceMetaInfoDto>) {
+        let mut device_po_list = Vec::new();
+        let mut device_info_map = HashMap::new();
+        for (device_id, master_device_id) in list {
+            device_po_list.push(DevicePo {
+                device_id: device_id.to_string(),
+                device_class: "".to_string(),
+                device_type: "".to_string(),
+                name: "".to_string(),
+                description: "".to_string(),
+                room: "".to_string(),
+                config: json!({}),
+            });
+            device_info_map.insert(
+                device_id.to_string(),
+                DeviceMetaInfoDto {
+                    device_id: device_id.to_string(),
+                    master_device_id: master_device_id.map(|s| s.to_string()),
+                    device_type: "".to_string(),
+                    config: json!({}),
+                    device_status: DeviceStatusEnum::NotInitialized,
+                    stale_timeout: None,
+                    restore_policy: RestorePolicyEnum::Upstream,
+                    rules: Vec::new(),
+                    error_msg: None,
+                    error_timestamp: None,
+                    last_update: None,
+                    state: StateDtoEnum::Empty,
+                },
+            );
+        }
+        (device_po_list, device_info_map)
+    }
+
+    #[test]
+    fn test_sort_by_dependency_order() {
+        let (device_po_list, device_info_map) = make_config(&[
+            ("port_1", Some("controller_1")),
+            ("controller_1", Some("bus_1")),
+            ("bus_1", None),
+            ("bus_2", None),
+        ]);
+        let (device_id_list, failed_list) = sort_by_dependency(&device_po_list, &device_info_map);
+        assert!(failed_list.is_empty());
+        assert_eq!(device_id_list, vec!["bus_1", "bus_2", "controller_1", "port_1"]);
+    }
+
+    #[test]
+    fn test_sort_by_dependency_errors() {
+        let (device_po_list, device_info_map) = make_config(&[
+            ("a", Some("b")),
+            ("b", Some("a")),
+            ("c", Some("a")),
+            ("d", Some("missing")),
+            ("e", None),
+        ]);
+        let (device_id_list, failed_list) = sort_by_dependency(&device_po_list, &device_info_map);
+        assert_eq!(device_id_list, vec!["e"]);
+        let failed: HashMap<String, String> = failed_list.into_iter().map(|(id, e)| (id, e.0)).collect();
+        assert_eq!(failed.len(), 4);
+        assert!(failed["a"].contains("master device cycle: a -> b -> a"));
+        assert!(failed["b"].contains("master device cycle"));
+        assert!(failed["c"].contains("master device `a` is invalid"));
+        assert!(failed["d"].contains("master device `missing` not found"));
+    }
+
+    #[test]
+    fn test_make_devices_failure_cascade() {
+        set_env();
+        // device type is empty, creating device fails
+        let (device_po_list, device_info_map) = make_config(&[
+            ("controller_1", None),
+            ("port_1", Some("controller_1")),
+            ("port_2", Some("missing")),
+        ]);
+        let device_info_map = Arc::new(Mutex::new(device_info_map));
+        let (tx, _rx) = mpsc::channel();
+        let mut device_factory = DeviceInstanceFactory::new(tx);
+        device_factory.make_devices(device_info_map.clone(), device_po_list).unwrap();
+
+        let failed: Vec<String> = device_factory.take_failed_devices().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(failed.len(), 3);
+        let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+        for device_id in ["controller_1", "port_1", "port_2"] {
+            let device_info = &map_guard[device_id];
+            assert_eq!(device_info.device_status, DeviceStatusEnum::ERROR);
+            assert!(device_info.error_msg.is_some());
+            assert!(device_info.error_timestamp.is_some());
+        }
+        assert!(map_guard["port_1"].error_msg.as_ref().unwrap().contains("master device `controller_1` failed"));
+    }
+
+    #[test]
+    fn test_make_di_port() {
+        set_env();
+        let device_po_list: Vec<DevicePo> = [
+            ("bus_1", "modbus_bus", json!({"serial_port": "/dev/ttyUSB0"})),
+            ("di_controller_1", "modbus_di_controller", json!({"master_device_id": "bus_1", "unit": 1, "num": 8})),
+            ("di_1", "modbus_di_port", json!({"master_device_id": "di_controller_1", "address": 2})),
+        ]
+        .into_iter()
+        .map(|(device_id, device_type, config)| DevicePo {
+            device_id: device_id.to_string(),
+            device_class: "".to_string(),
+            device_type: device_type.to_string(),
+            name: "".to_string(),
+            description: "".to_string(),
+            room: "".to_string(),
+            config,
+        })
+        .collect();
+        let device_info_map = Arc::new(Mutex::new(make_device_info(device_po_list.clone()).unwrap()));
+        let (tx, rx) = mpsc::channel();
+        let mut device_factory = DeviceInstanceFactory::new(tx);
+        device_factory.make_devices(device_info_map, device_po_list).unwrap();
+        assert!(device_factory.take_failed_devices().is_empty());
+
+        // the controller polled by modbus bus relays the value to the mounted port
+        let device_map = device_factory.get_device_map();
+        let Some(DeviceRefEnum::ModbusDiController(di_controller_ref)) = device_map.get("di_controller_1") else {
+            panic!("di controller is not created");
+        };
+        let values = vec![false, false, true, false, false, false, false, false];
+        di_controller_ref.lock().unwrap().notify_from_bus(0, values).unwrap();
+        let port_states: Vec<StateDtoEnum> = rx
+            .try_iter()
+            .filter(|dto| dto.device_id == "di_1")
+            .map(|dto| dto.status.state)
+            .collect();
+        assert!(matches!(port_states[..], [StateDtoEnum::Di(DiStateDto { on: true })]));
+    }
 }
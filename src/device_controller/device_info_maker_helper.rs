@@ -4,6 +4,9 @@ use std::collections::HashMap;
 
 use crate::{common::error::{DeviceServerError, DriverError, ServerErrorCode}, debug, entity::dto::{device_meta_info_dto::{DeviceMetaInfoDto, DeviceStatusEnum}, device_state_dto::StateDtoEnum}, error, info, trace, warn};
 
+use crate::entity::po::device_config_po::{RestorePolicyEnum, RulePo};
+
+use super::driver_registry::DriverRegistry;
 use super::entity::{
     device_po::DevicePo,
 };
@@ -20,6 +23,18 @@ pub fn make_device_info(
             .as_str()
             .map(|s| s.to_string());
 
+        // invalid restore policy does not stop the device from working
+        let restore_policy = RestorePolicyEnum::from_config(&device_po.device_id, &device_po.config).unwrap_or_else(|e| {
+            warn!(LOG_TAG, "invalid restore_policy, use upstream, error msg: {}", e);
+            RestorePolicyEnum::Upstream
+        });
+
+        // invalid rules are all ignored, so that a typo does not fire unexpected commands
+        let rules = RulePo::from_config(&device_po.device_id, &device_po.config).unwrap_or_else(|e| {
+            warn!(LOG_TAG, "invalid rules, ignored, error msg: {}", e);
+            Vec::new()
+        });
+
         // 1. make device info
         let device_info = DeviceMetaInfoDto {
             device_id: device_po.device_id.clone(),
@@ -27,6 +42,9 @@ pub fn make_device_info(
             master_device_id: master_device_id,
             config: device_po.config.clone(),
             device_status: DeviceStatusEnum::NotInitialized,
+            stale_timeout: DriverRegistry::get().stale_timeout(&device_po.device_type, &device_po.config),
+            restore_policy,
+            rules,
 
             error_msg: None,
             error_timestamp: None,
@@ -0,0 +1,209 @@
+//! 设备驱动注册表
+//! - 设备工厂按 device_type 找到驱动创建设备
+//! - 指令解析、发送和状态读取按 device_type 分发到驱动
+//! - 各设备类型的能力（主设备类型、支持的指令）随心跳上报
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use super::entity::device_enum::DeviceRefEnum;
+use super::factory::driver_list;
+use super::traits::RegisteredDriver;
+use crate::common::error::{CommandError, DriverError};
+use crate::entity::dto::{
+    device_command_dto::{CommandParamsEnum, DeviceCommandDto},
+    device_state_dto::StateDtoEnum,
+    driver_capability_dto::DriverCapabilityDto,
+};
+use crate::warn;
+
+const LOG_TAG: &str = "driver_registry";
+
+pub struct DriverRegistry {
+    driver_map: HashMap<&'static str, Box<dyn RegisteredDriver>>,
+}
+
+impl DriverRegistry {
+    pub fn get<'a>() -> &'a Self {
+        lazy_static! {
+            static ref REGISTRY: DriverRegistry = DriverRegistry::new(driver_list());
+        }
+        &REGISTRY
+    }
+
+    /// the later driver replaces the former one with the same device type
+    fn new(driver_list: Vec<Box<dyn RegisteredDriver>>) -> Self {
+        let mut driver_map: HashMap<&'static str, Box<dyn RegisteredDriver>> = HashMap::new();
+        for driver in driver_list {
+            if driver_map.contains_key(driver.device_type()) {
+                warn!(LOG_TAG, "driver is registered more than once, device_type: {}", driver.device_type());
+            }
+            driver_map.insert(driver.device_type(), driver);
+        }
+        DriverRegistry { driver_map }
+    }
+
+    pub fn find(&self, device_type: &str) -> Result<&dyn RegisteredDriver, DriverError> {
+        self.driver_map
+            .get(device_type)
+            .map(|driver| driver.as_ref())
+            .ok_or(DriverError(format!("unknown device type. device_type={}", device_type)))
+    }
+
+    /// parse action and param according to device type
+    pub fn parse_command(&self, device_type: &str, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        match self.driver_map.get(device_type) {
+            Some(driver) => driver.parse_command(action, param),
+            None => Err(serde::de::Error::custom(format!(
+                "device type `{}` does not accept command",
+                device_type
+            ))),
+        }
+    }
+
+    /// check if device type can receive command
+    pub fn is_commandable(&self, device_type: &str) -> bool {
+        self.driver_map
+            .get(device_type)
+            .is_some_and(|driver| !driver.actions().is_empty())
+    }
+
+    /// staleness window of device type, `stale_timeout` in device config overrides the driver default
+    /// zero in device config disables the check
+    pub fn stale_timeout(&self, device_type: &str, config: &Value) -> Option<u64> {
+        match config.get("stale_timeout").and_then(Value::as_u64) {
+            Some(0) => None,
+            Some(timeout) => Some(timeout),
+            None => self.driver_map.get(device_type)?.stale_timeout(),
+        }
+    }
+
+    /// send command to device, return the state after command
+    pub fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        self.find(&dto.device_type)?.command(device_ref, dto)
+    }
+
+    /// read current state from device, none if the device does not provide state on demand
+    pub fn read_state(&self, device_type: &str, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        self.driver_map.get(device_type)?.read_state(device_ref)
+    }
+
+    /// master types and actions of all registered device types
+    pub fn get_capabilities(&self) -> HashMap<String, DriverCapabilityDto> {
+        self.driver_map
+            .values()
+            .map(|driver| {
+                (
+                    driver.device_type().to_string(),
+                    DriverCapabilityDto {
+                        master_types: driver.master_types().iter().map(|s| s.to_string()).collect(),
+                        actions: driver.actions().iter().map(|s| s.to_string()).collect(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::dto::device_command_dto::{
+        DmxBusCommandEnum, DoBlinkParamsDto, DoControllerCommandEnum, DoDurationParamsDto, DoPortCommandEnum,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_declared_actions_are_parsable() {
+        let registry = DriverRegistry::get();
+        for (device_type, capability) in registry.get_capabilities() {
+            for action in capability.actions.iter() {
+                // param may be invalid, but the action must be known by the command parser
+                if let Err(e) = registry.parse_command(&device_type, action, Value::Null) {
+                    assert!(
+                        !e.to_string().contains("unknown variant"),
+                        "{} action `{}` is not parsable: {}",
+                        device_type,
+                        action,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let registry = DriverRegistry::get();
+        let capabilities = registry.get_capabilities();
+        assert_eq!(capabilities["modbus_do_port"].master_types, vec!["modbus_do_controller"]);
+        assert!(capabilities["dmx_channel"].actions.contains(&"set_all".to_string()));
+        assert!(registry.is_commandable("audio"));
+        assert!(!registry.is_commandable("modbus_di_port"));
+        assert!(registry.find("unknown").is_err());
+    }
+
+    #[test]
+    fn test_stale_timeout() {
+        let registry = DriverRegistry::get();
+        assert_eq!(registry.stale_timeout("modbus_di_controller", &serde_json::json!({})), Some(5000));
+        assert_eq!(registry.stale_timeout("modbus_di_controller", &serde_json::json!({"stale_timeout": 0})), None);
+        assert_eq!(registry.stale_timeout("modbus_do_port", &serde_json::json!({})), None);
+        assert_eq!(registry.stale_timeout("modbus_do_port", &serde_json::json!({"stale_timeout": 3000})), Some(3000));
+    }
+
+    #[test]
+    fn test_parse_command_unit_action() {
+        let registry = DriverRegistry::get();
+        let params = registry.parse_command("modbus_do_port", "on", Value::Null).unwrap();
+        assert!(matches!(params, CommandParamsEnum::DoPort(DoPortCommandEnum::On)));
+        let params = registry.parse_command("dmx_bus", "blackout", json!({})).unwrap();
+        assert!(matches!(params, CommandParamsEnum::DmxBus(DmxBusCommandEnum::Blackout)));
+    }
+
+    #[test]
+    fn test_parse_command_param_action() {
+        let registry = DriverRegistry::get();
+        let params = registry.parse_command(
+            "modbus_do_controller",
+            "write_multi",
+            json!({"address": 2, "values": [true, false]}),
+        )
+        .unwrap();
+        match params {
+            CommandParamsEnum::DoController(DoControllerCommandEnum::WriteMulti(dto)) => {
+                assert_eq!(dto.address, 2);
+                assert_eq!(dto.values, vec![true, false]);
+            }
+            _ => panic!("wrong command: {:?}", params),
+        }
+    }
+
+    #[test]
+    fn test_parse_command_do_port_timed_action() {
+        let registry = DriverRegistry::get();
+        let params = registry.parse_command("modbus_do_port", "pulse", Value::Null).unwrap();
+        assert!(matches!(params, CommandParamsEnum::DoPort(DoPortCommandEnum::Pulse(None))));
+        let params = registry.parse_command("modbus_do_port", "pulse", json!({"duration": 200})).unwrap();
+        assert!(matches!(params, CommandParamsEnum::DoPort(DoPortCommandEnum::Pulse(Some(DoDurationParamsDto { duration: 200 })))));
+        let params = registry.parse_command("modbus_do_port", "blink", json!({"period": 500, "count": 3})).unwrap();
+        assert!(matches!(params, CommandParamsEnum::DoPort(DoPortCommandEnum::Blink(DoBlinkParamsDto { period: 500, count: 3 }))));
+        assert!(registry.parse_command("modbus_do_port", "on_for", Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_parse_command_invalid() {
+        let registry = DriverRegistry::get();
+        // unknown action
+        let err = registry.parse_command("modbus_do_port", "flash", Value::Null).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `flash`"));
+        // value out of range
+        assert!(registry.parse_command("dmx_bus", "set_channels", json!({"offset": 0, "values": [256]})).is_err());
+        // missing field
+        assert!(registry.parse_command("audio", "play", Value::Null).is_err());
+        // unknown device type
+        assert!(registry.parse_command("modbus_di_port", "on", Value::Null).is_err());
+    }
+}
@@ -1,8 +1,12 @@
 //! device enum
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
 use crate::driver::{
-    device::audio_output::AudioOutput,
+    device::{audio_output::AudioOutput, sequence_detector::SequenceDetector},
     dmx::{dmx_bus::DmxBus, dmx_channel_device::DmxChannelDevice},
     modbus::{
         modbus_bus::ModbusBus, modbus_di_controller_coil::ModbusDiControllerCoil,
@@ -20,8 +24,11 @@ pub enum DeviceRefEnum {
     ModbusBus(Rc<RefCell<ModbusBus>>),
     ModbusDoController(Rc<RefCell<ModbusDoControllerCoil>>),
     ModbusDoPort(Rc<RefCell<ModbusDoPort>>),
-    ModbusDiController(Rc<RefCell<ModbusDiControllerCoil>>),
+    // shared with modbus bus thread, which polls it after the bus starts
+    ModbusDiController(Arc<Mutex<ModbusDiControllerCoil>>),
     ModbusDiPort(Rc<RefCell<ModbusDiPort>>),
     SerialRemoteController(Rc<RefCell<SerialRemoteController>>),
     Audio(Rc<RefCell<AudioOutput>>),
+    // virtual device matching input events of other devices
+    SequenceDetector(Rc<RefCell<SequenceDetector>>),
 }
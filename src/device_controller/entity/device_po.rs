@@ -3,7 +3,7 @@ use serde_json::Value;
 
 
 /// 数据库对象：设备
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DevicePo {
     // 设备 id
     pub device_id: String,
@@ -15,6 +15,9 @@ pub struct DevicePo {
     pub name: String,
     // 设备描述
     pub description: String,
+    // 设备所在房间，可为空
+    #[serde(default)]
+    pub room: String,
     // 设备配置（json string）
     pub config: Value,
 }
\ No newline at end of file
@@ -1,2 +1,5 @@
 pub mod device_enum;
-pub mod device_po;
\ No newline at end of file
+pub mod device_po;
+pub mod state_snapshot_po;
+pub mod schedule_po;
+pub mod scene_po;
\ No newline at end of file
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::entity::dto::batch_command_dto::BatchCommandItemDto;
+
+
+/// 数据库对象：场景，一组设备指令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenePo {
+    // 场景 id
+    pub scene_id: String,
+    // 场景名称
+    pub name: String,
+    // 设备指令列表（json），调用场景时按顺序执行
+    pub targets: Vec<BatchCommandItemDto>,
+    // 更新时间（毫秒时间戳）
+    pub update_time: u64,
+}
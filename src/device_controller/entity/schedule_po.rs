@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+
+/// 数据库对象：定时指令，重启后继续生效
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchedulePo {
+    // 定时指令 id
+    pub schedule_id: String,
+    // 目标设备 id
+    pub device_id: String,
+    // 设备指令
+    pub action: String,
+    // 指令参数（json）
+    pub param: Value,
+    // 单次执行时间（毫秒时间戳），与 cron 二选一
+    pub at: Option<u64>,
+    // 重复执行的 cron 表达式
+    pub cron: Option<String>,
+    // 创建时间（毫秒时间戳）
+    pub create_time: u64,
+    // cron 定时指令的下次执行时间（毫秒时间戳），每次执行后更新，重启后据此补执行错过的指令
+    pub next_fire_time: Option<u64>,
+}
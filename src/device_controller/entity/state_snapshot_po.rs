@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+
+/// 数据库对象：设备状态快照，用于重启后恢复输出设备状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshotPo {
+    // 设备 id
+    pub device_id: String,
+    // 设备类型
+    pub device_type: String,
+    // 最后一次上报的设备状态（json）
+    pub state: Value,
+    // 保存时间（毫秒时间戳）
+    pub update_time: u64,
+}
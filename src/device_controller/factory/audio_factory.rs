@@ -1,33 +1,65 @@
 use std::sync::mpsc::Sender;
+use std::{cell::RefCell, rc::Rc};
 
-use crate::common::error::DriverError;
+use serde_json::Value;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
+use crate::common::error::{CommandError, DriverError};
 use crate::driver::device::audio_output::{AudioOutput, ChannelEnum};
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::util::json;
+use crate::entity::po::device_config_po::{AudioChannelEnum, AudioConfigPo};
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &AudioConfigPo,
     report_tx: Sender<StateToDeviceControllerDto>,
 ) -> Result<AudioOutput, DriverError> {
-    let soundcard_id = json::get_config_str(&device_info.config, "soundcard_id")?;
-    let channel = json::get_config_str(&device_info.config, "channel")?;
-    let channel_enum: ChannelEnum;
-    if channel == "left" {
-        channel_enum = ChannelEnum::Left;
-    } else if channel == "right" {
-        channel_enum = ChannelEnum::Right;
-    } else {
-        return Err(DriverError(format!(
-            "device factory: invalid channel str, channel: {}, device_id: {}",
-            channel, device_info.device_id
-        )));
-    }
+    let channel_enum = match config.to_channel {
+        AudioChannelEnum::Left => ChannelEnum::Left,
+        AudioChannelEnum::Right => ChannelEnum::Right,
+        AudioChannelEnum::Stereo => ChannelEnum::Stereo,
+    };
     let obj = AudioOutput::new(
         device_info.device_id.as_str(),
-        soundcard_id.as_str(),
+        config.soundcard_id.as_str(),
         channel_enum,
         report_tx,
     );
     Ok(obj)
 }
+
+pub struct AudioDriver;
+
+impl DeviceDriver for AudioDriver {
+    type Config = AudioConfigPo;
+    const DEVICE_TYPE: &'static str = "audio";
+    const ACTIONS: &'static [&'static str] = &["play", "pause", "stop", "resume"];
+
+    fn make(&self, ctx: &DriverContext, config: AudioConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        let audio = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+        Ok(Some(DeviceRefEnum::Audio(Rc::new(RefCell::new(audio)))))
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::Audio(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::Audio(audio_ref) => command_device(audio_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::Audio(audio_ref) => Some(RefCell::borrow(audio_ref).get_state()),
+            _ => None,
+        }
+    }
+}
@@ -2,27 +2,69 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
 
-use crate::common::error::DriverError;
+use serde_json::Value;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
+use crate::common::error::{CommandError, DriverError};
 use crate::driver::dmx::dmx_bus::DmxBus;
 use crate::driver::dmx::dmx_channel_device::DmxChannelDevice;
-use crate::driver::dmx::prelude::DmxAddress;
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::util::json;
+use crate::entity::po::device_config_po::DmxCustomConfigPo;
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &DmxCustomConfigPo,
     dmx_bus_ref: Rc<RefCell<DmxBus>>,
     report_tx: Sender<StateToDeviceControllerDto>,
 ) -> Result<DmxChannelDevice, DriverError> {
-    let channel_num = json::get_config_int(&device_info.config, "channel_num")?;
-    let address = json::get_config_int(&device_info.config, "address")?;
     let obj = DmxChannelDevice::new(
         device_info.device_id.as_str(),
-        address as DmxAddress,
-        channel_num as DmxAddress,
+        config.address,
+        config.channels,
         dmx_bus_ref,
         report_tx
     );
     Ok(obj)
 }
+
+pub struct DmxChannelDriver;
+
+impl DeviceDriver for DmxChannelDriver {
+    type Config = DmxCustomConfigPo;
+    const DEVICE_TYPE: &'static str = "dmx_channel";
+    const MASTER_TYPES: &'static [&'static str] = &["dmx_bus"];
+    const ACTIONS: &'static [&'static str] = &["set", "set_all", "off"];
+
+    fn make(&self, ctx: &DriverContext, config: DmxCustomConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::DmxBus(dmx_bus_ref) => {
+                let dmx_channel = make(ctx.device_info, &config, dmx_bus_ref.clone(), ctx.report_tx.clone())?;
+                Ok(Some(DeviceRefEnum::DmxChannel(Rc::new(RefCell::new(dmx_channel)))))
+            }
+            _ => Err(device_mismatch("dmx_bus", ctx.device_info.master_device_id.as_deref().unwrap_or_default())),
+        }
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::DmxChannel(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::DmxChannel(dmx_channel_ref) => command_device(dmx_channel_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::DmxChannel(dmx_channel_ref) => Some(RefCell::borrow(dmx_channel_ref).get_state()),
+            _ => None,
+        }
+    }
+}
@@ -1,29 +1,50 @@
-use std::sync::mpsc::Sender;
+use std::sync::{mpsc::Sender, Arc, Mutex};
 
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{device_mismatch, DeviceDriver, DriverContext};
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::util::json;
+use crate::entity::po::device_config_po::ModbusDigitalInputControllerConfigPo;
 use crate::{common::error::DriverError, driver::modbus::modbus_di_controller_coil::ModbusDiControllerCoil};
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &ModbusDigitalInputControllerConfigPo,
     report_tx: Sender<StateToDeviceControllerDto>,
 ) -> Result<ModbusDiControllerCoil, DriverError> {
-    let unit = json::get_config_int(&device_info.config, "unit")?;
-    let input_num = json::get_config_int(&device_info.config, "num")?;
     let obj = ModbusDiControllerCoil::new(
         device_info.device_id.as_str(),
-        unit.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert unit to int, err: {e}"
-            ))
-        })?,
-        input_num.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert num to int, err: {e}"
-            ))
-        })?,
+        config.unit,
+        config.input_num,
         report_tx,
     );
     Ok(obj)
 }
+
+pub struct DiControllerDriver;
+
+impl DeviceDriver for DiControllerDriver {
+    type Config = ModbusDigitalInputControllerConfigPo;
+    const DEVICE_TYPE: &'static str = "modbus_di_controller";
+    const MASTER_TYPES: &'static [&'static str] = &["modbus_bus"];
+    // di controller reports on every poll
+    const STALE_TIMEOUT: Option<u64> = Some(5000);
+
+    /// di controller is polled by modbus bus, the same handle is kept for mounting di ports
+    fn make(
+        &self,
+        ctx: &DriverContext,
+        config: ModbusDigitalInputControllerConfigPo,
+    ) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::ModbusBus(modbus_ref) => {
+                let di_controller = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+                let unit = di_controller.get_unit();
+                let di_controller_ref = Arc::new(Mutex::new(di_controller));
+                modbus_ref.borrow_mut().add_di_controller(unit, di_controller_ref.clone());
+                Ok(Some(DeviceRefEnum::ModbusDiController(di_controller_ref)))
+            }
+            _ => Err(device_mismatch("modbus_bus", ctx.device_info.master_device_id.as_deref().unwrap_or_default())),
+        }
+    }
+}
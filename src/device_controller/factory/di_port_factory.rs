@@ -1,25 +1,52 @@
 use std::sync::mpsc;
 
-use crate::util::json;
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{device_mismatch, DeviceDriver, DriverContext};
 use crate::{
     common::error::DriverError,
-    driver::modbus::{modbus_bus::ModbusBus, modbus_di_port::ModbusDiPort},
+    driver::modbus::{
+        modbus_di_port::ModbusDiPort,
+        traits::{ModbusDiControllerListener, ModbusListener},
+    },
     entity::dto::{device_meta_info_dto::DeviceMetaInfoDto, device_state_dto::StateToDeviceControllerDto},
+    entity::po::device_config_po::DiConfigPo,
 };
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &DiConfigPo,
     report_tx: mpsc::Sender<StateToDeviceControllerDto>,
 ) -> Result<ModbusDiPort, DriverError> {
-    let address = json::get_config_int(&device_info.config, "address")?;
     let obj = ModbusDiPort::new(
         device_info.device_id.as_str(),
-        address.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert address to int, err: {e}"
-            ))
-        })?,
+        config.address,
         report_tx,
     );
     Ok(obj)
 }
+
+pub struct DiPortDriver;
+
+impl DeviceDriver for DiPortDriver {
+    type Config = DiConfigPo;
+    const DEVICE_TYPE: &'static str = "modbus_di_port";
+    const MASTER_TYPES: &'static [&'static str] = &["modbus_di_controller"];
+
+    /// di port is mounted to di controller
+    fn make(&self, ctx: &DriverContext, config: DiConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::ModbusDiController(di_controller_ref) => {
+                let di_port = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+                di_controller_ref
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .add_di_port(di_port.get_address(), Box::new(di_port))?;
+                Ok(None)
+            }
+            _ => Err(device_mismatch(
+                "modbus_di_controller",
+                ctx.device_info.master_device_id.as_deref().unwrap_or_default(),
+            )),
+        }
+    }
+}
@@ -1,16 +1,54 @@
 use std::sync::mpsc::Sender;
+use std::{cell::RefCell, rc::Rc};
 
+use serde_json::Value;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::{common::error::DriverError, driver::dmx::dmx_bus::DmxBus};
-use crate::util::json;
+use crate::{common::error::{CommandError, DriverError}, driver::dmx::dmx_bus::DmxBus};
+use crate::entity::po::device_config_po::DmxBusConfigPo;
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
-pub fn make(device_info: &DeviceMetaInfoDto, report_tx: Sender<StateToDeviceControllerDto>) -> Result<DmxBus, DriverError> {
-    let serial_port = json::get_str(&device_info.config, "serial_port")?;
+pub fn make(device_info: &DeviceMetaInfoDto, config: &DmxBusConfigPo, report_tx: Sender<StateToDeviceControllerDto>) -> Result<DmxBus, DriverError> {
     let obj = DmxBus::new(
         device_info.device_id.as_str(),
-        serial_port.as_str(),
+        config.serial_port.as_str(),
         report_tx
     ); 
     Ok(obj)
-}
\ No newline at end of file
+}
+
+pub struct DmxBusDriver;
+
+impl DeviceDriver for DmxBusDriver {
+    type Config = DmxBusConfigPo;
+    const DEVICE_TYPE: &'static str = "dmx_bus";
+    const ACTIONS: &'static [&'static str] = &["set_channels", "blackout"];
+
+    fn make(&self, ctx: &DriverContext, config: DmxBusConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        let dmx_bus = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+        Ok(Some(DeviceRefEnum::DmxBus(Rc::new(RefCell::new(dmx_bus)))))
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::DmxBus(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::DmxBus(dmx_bus_ref) => command_device(dmx_bus_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::DmxBus(dmx_bus_ref) => Some(RefCell::borrow(dmx_bus_ref).get_state()),
+            _ => None,
+        }
+    }
+}
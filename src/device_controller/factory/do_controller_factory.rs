@@ -1,33 +1,72 @@
 use std::{borrow::Borrow, cell::RefCell, rc::Rc, sync::mpsc::Sender};
 
-use crate::util::json;
+use serde_json::Value;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
 use crate::{
-    common::error::DriverError,
+    common::error::{CommandError, DriverError},
     driver::modbus::{modbus_bus::ModbusBus, modbus_do_controller_coil::ModbusDoControllerCoil},
     entity::dto::{device_meta_info_dto::DeviceMetaInfoDto, device_state_dto::StateToDeviceControllerDto},
+    entity::po::device_config_po::ModbusDigitalOutputControllerConfigPo,
 };
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &ModbusDigitalOutputControllerConfigPo,
     modbus_ref: &Rc<RefCell<ModbusBus>>,
     report_tx: Sender<StateToDeviceControllerDto>,
 ) -> Result<ModbusDoControllerCoil, DriverError> {
-    let unit = json::get_config_int(&device_info.config, "unit")?;
-    let output_num = json::get_config_int(&device_info.config, "num")?;
     let obj = ModbusDoControllerCoil::new(
         device_info.device_id.as_str(),
-        unit.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert unit to int, err: {e}"
-            ))
-        })?,
-        output_num.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert num to int, err: {e}"
-            ))
-        })?,
+        config.unit,
+        config.output_num,
         Rc::clone(modbus_ref),
         report_tx,
     );
     Ok(obj)
 }
+
+pub struct DoControllerDriver;
+
+impl DeviceDriver for DoControllerDriver {
+    type Config = ModbusDigitalOutputControllerConfigPo;
+    const DEVICE_TYPE: &'static str = "modbus_do_controller";
+    const MASTER_TYPES: &'static [&'static str] = &["modbus_bus"];
+    const ACTIONS: &'static [&'static str] = &["write", "write_multi"];
+
+    fn make(
+        &self,
+        ctx: &DriverContext,
+        config: ModbusDigitalOutputControllerConfigPo,
+    ) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::ModbusBus(modbus_ref) => {
+                let do_controller = make(ctx.device_info, &config, modbus_ref, ctx.report_tx.clone())?;
+                Ok(Some(DeviceRefEnum::ModbusDoController(Rc::new(RefCell::new(do_controller)))))
+            }
+            _ => Err(device_mismatch("modbus_bus", ctx.device_info.master_device_id.as_deref().unwrap_or_default())),
+        }
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::DoController(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::ModbusDoController(do_controller_ref) => command_device(do_controller_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::ModbusDoController(do_controller_ref) => Some(RefCell::borrow(do_controller_ref).get_state()),
+            _ => None,
+        }
+    }
+}
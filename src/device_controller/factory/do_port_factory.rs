@@ -1,27 +1,71 @@
 use std::{cell::RefCell, rc::Rc, sync::mpsc::Sender};
 
+use serde_json::Value;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
 use crate::{
-    common::error::DriverError,
+    common::error::{CommandError, DriverError},
     driver::modbus::{modbus_do_controller_coil::ModbusDoControllerCoil, modbus_do_port::ModbusDoPort},
     entity::dto::{device_meta_info_dto::DeviceMetaInfoDto, device_state_dto::StateToDeviceControllerDto},
-    util::json,
+    entity::po::device_config_po::DoConfigPo,
 };
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
 pub fn make(
     device_info: &DeviceMetaInfoDto,
+    config: &DoConfigPo,
     modbus_do_controller_ref: Rc<RefCell<ModbusDoControllerCoil>>,
     report_tx: Sender<StateToDeviceControllerDto>,
 ) -> Result<ModbusDoPort, DriverError> {
-    let address = json::get_config_int(&device_info.config, "address")?;
     let obj = ModbusDoPort::new(
         device_info.device_id.as_str(),
-        address.try_into().map_err(|e| {
-            DriverError(format!(
-                "device factory: cannot convert address to int, err: {e}"
-            ))
-        })?,
+        config.address,
+        config.pulse_time,
         modbus_do_controller_ref,
         report_tx
     );
     Ok(obj)
 }
+
+pub struct DoPortDriver;
+
+impl DeviceDriver for DoPortDriver {
+    type Config = DoConfigPo;
+    const DEVICE_TYPE: &'static str = "modbus_do_port";
+    const MASTER_TYPES: &'static [&'static str] = &["modbus_do_controller"];
+    const ACTIONS: &'static [&'static str] = &["on", "off", "toggle", "pulse", "on_for", "blink", "cancel"];
+
+    fn make(&self, ctx: &DriverContext, config: DoConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::ModbusDoController(do_controller_ref) => {
+                let do_port = make(ctx.device_info, &config, Rc::clone(do_controller_ref), ctx.report_tx.clone())?;
+                Ok(Some(DeviceRefEnum::ModbusDoPort(Rc::new(RefCell::new(do_port)))))
+            }
+            _ => Err(device_mismatch(
+                "modbus_do_controller",
+                ctx.device_info.master_device_id.as_deref().unwrap_or_default(),
+            )),
+        }
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::DoPort(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::ModbusDoPort(do_port_ref) => command_device(do_port_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::ModbusDoPort(do_port_ref) => Some(RefCell::borrow(do_port_ref).get_state()),
+            _ => None,
+        }
+    }
+}
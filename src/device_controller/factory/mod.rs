@@ -7,4 +7,24 @@ pub mod di_port_factory;
 pub mod do_port_factory;
 pub mod remote_factory;
 pub mod audio_factory;
-pub mod channel_device_factory;
\ No newline at end of file
+pub mod channel_device_factory;
+pub mod sequence_detector_factory;
+
+use super::traits::RegisteredDriver;
+
+/// all device drivers, register new device driver here
+pub fn driver_list() -> Vec<Box<dyn RegisteredDriver>> {
+    vec![
+        Box::new(modbus_bus_factory::ModbusBusDriver),
+        Box::new(serial_bus_factory::SerialBusDriver),
+        Box::new(dmx_bus_factory::DmxBusDriver),
+        Box::new(do_controller_factory::DoControllerDriver),
+        Box::new(do_port_factory::DoPortDriver),
+        Box::new(di_controller_factory::DiControllerDriver),
+        Box::new(di_port_factory::DiPortDriver),
+        Box::new(remote_factory::RemoteDriver),
+        Box::new(audio_factory::AudioDriver),
+        Box::new(channel_device_factory::DmxChannelDriver),
+        Box::new(sequence_detector_factory::SequenceDetectorDriver),
+    ]
+}
@@ -1,20 +1,31 @@
 use std::sync::mpsc::Sender;
+use std::{cell::RefCell, rc::Rc};
 
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{DeviceDriver, DriverContext};
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
 use crate::{common::error::DriverError, driver::modbus::modbus_bus::ModbusBus };
-use crate::util::json;
+use crate::entity::po::device_config_po::ModbusConfigPo;
 
-pub fn make(device_info: &DeviceMetaInfoDto, report_tx: Sender<StateToDeviceControllerDto>) -> Result<ModbusBus, DriverError> {
-    let serial_port = json::get_config_str(&device_info.config, "serial_port")?;
-    let baudrate = json::get_config_int(&device_info.config, "baudrate")?;
+pub fn make(device_info: &DeviceMetaInfoDto, config: &ModbusConfigPo, report_tx: Sender<StateToDeviceControllerDto>) -> Result<ModbusBus, DriverError> {
     let obj = ModbusBus::new(
         &device_info.device_id,
-        serial_port.as_str(), 
-        baudrate.try_into().map_err(
-        |e| DriverError(format!("device factory: cannot convert baudrate to int, err: {e}"))
-        )?,
+        config.serial_port.as_str(),
+        config.baudrate,
         report_tx
     ); 
     Ok(obj)
 }
+
+pub struct ModbusBusDriver;
+
+impl DeviceDriver for ModbusBusDriver {
+    type Config = ModbusConfigPo;
+    const DEVICE_TYPE: &'static str = "modbus_bus";
+
+    fn make(&self, ctx: &DriverContext, config: ModbusConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        let modbus_bus = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+        Ok(Some(DeviceRefEnum::ModbusBus(Rc::new(RefCell::new(modbus_bus)))))
+    }
+}
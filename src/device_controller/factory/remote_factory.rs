@@ -1,22 +1,38 @@
-
 use std::sync::mpsc::Sender;
 
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{device_mismatch, DeviceDriver, DriverContext};
 use crate::driver::serial::serial_remote_controller::SerialRemoteController;
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
 use crate::common::error::DriverError ;
-use crate::util::json;
-
+use crate::entity::po::device_config_po::RemoteConfigPo;
 
-pub fn make(device_info: &DeviceMetaInfoDto, report_tx: Sender<StateToDeviceControllerDto>) -> Result<SerialRemoteController, DriverError> {
-    let num_button_i64 = json::get_config_int(&device_info.config, "num_button")?;
-    let num_button: u8 = num_button_i64.try_into()
-        .map_err(|_| DriverError("init remote controller error, cannot transform num_button to u8".to_string()))?;
-    
+pub fn make(device_info: &DeviceMetaInfoDto, config: &RemoteConfigPo, report_tx: Sender<StateToDeviceControllerDto>) -> Result<SerialRemoteController, DriverError> {
     let obj = SerialRemoteController::new(
         device_info.device_id.as_str(), 
-        num_button,
+        config.num_button,
         report_tx
     ); 
     Ok(obj)
-}
\ No newline at end of file
+}
+
+pub struct RemoteDriver;
+
+impl DeviceDriver for RemoteDriver {
+    type Config = RemoteConfigPo;
+    const DEVICE_TYPE: &'static str = "remote";
+    const MASTER_TYPES: &'static [&'static str] = &["serial_bus"];
+
+    /// remote controller is mounted to serial bus as listener
+    fn make(&self, ctx: &DriverContext, config: RemoteConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        match ctx.master()? {
+            DeviceRefEnum::SerialBus(serial_bus_ref) => {
+                let remote_controller = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+                serial_bus_ref.borrow_mut().add_listener(Box::new(remote_controller));
+                Ok(None)
+            }
+            _ => Err(device_mismatch("serial_bus", ctx.device_info.master_device_id.as_deref().unwrap_or_default())),
+        }
+    }
+}
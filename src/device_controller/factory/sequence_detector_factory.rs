@@ -0,0 +1,160 @@
+use std::sync::mpsc::Sender;
+use std::{cell::RefCell, rc::Rc};
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{DeviceDriver, DriverContext};
+use super::di_port_factory::DiPortDriver;
+use super::remote_factory::RemoteDriver;
+use crate::common::error::DriverError;
+use crate::driver::device::sequence_detector::{SequenceDetector, SequenceModeEnum, SequenceStep};
+use crate::driver::traits::ReportUpward;
+use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
+use crate::entity::dto::device_state_dto::{StateDtoEnum, StateToDeviceControllerDto};
+use crate::entity::po::device_config_po::{self, SequenceDetectorConfigPo};
+
+pub fn make(
+    device_info: &DeviceMetaInfoDto,
+    config: &SequenceDetectorConfigPo,
+    report_tx: Sender<StateToDeviceControllerDto>,
+) -> Result<SequenceDetector, DriverError> {
+    let mode = match config.mode {
+        device_config_po::SequenceModeEnum::Sequence => SequenceModeEnum::Sequence,
+        device_config_po::SequenceModeEnum::Combination => SequenceModeEnum::Combination,
+    };
+    let steps = config
+        .steps
+        .iter()
+        .map(|step| SequenceStep {
+            device_id: step.device_id.clone(),
+            button: step.button,
+        })
+        .collect();
+    let obj = SequenceDetector::new(
+        device_info.device_id.as_str(),
+        mode,
+        steps,
+        config.timeout,
+        report_tx,
+    );
+    Ok(obj)
+}
+
+/// steps must be di ports without button or remotes with button
+fn check_steps(ctx: &DriverContext, config: &SequenceDetectorConfigPo) -> Result<(), DriverError> {
+    let device_id = &ctx.device_info.device_id;
+    if config.steps.is_empty() {
+        return Err(DriverError(format!("sequence detector has no steps, device_id: {}", device_id)));
+    }
+    if config.timeout == 0 {
+        return Err(DriverError(format!("sequence detector timeout must be positive, device_id: {}", device_id)));
+    }
+    for step in config.steps.iter() {
+        let step_type = ctx
+            .device_info_map
+            .get(&step.device_id)
+            .map(|step_info| step_info.device_type.as_str())
+            .unwrap_or_default();
+        let valid = match step.button {
+            None => step_type == DiPortDriver::DEVICE_TYPE,
+            Some(_) => step_type == RemoteDriver::DEVICE_TYPE,
+        };
+        if !valid {
+            return Err(DriverError(format!(
+                "invalid sequence step `{}` of type `{}`, expected {} without button or {} with button, device_id: {}",
+                step.device_id, step_type, DiPortDriver::DEVICE_TYPE, RemoteDriver::DEVICE_TYPE, device_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub struct SequenceDetectorDriver;
+
+impl DeviceDriver for SequenceDetectorDriver {
+    type Config = SequenceDetectorConfigPo;
+    const DEVICE_TYPE: &'static str = "sequence_detector";
+
+    /// virtual device without master, idle state is reported after made
+    fn make(&self, ctx: &DriverContext, config: SequenceDetectorConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        check_steps(ctx, &config)?;
+        let detector = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+        detector.report()?;
+        Ok(Some(DeviceRefEnum::SequenceDetector(Rc::new(RefCell::new(detector)))))
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::SequenceDetector(detector_ref) => Some(RefCell::borrow(detector_ref).get_state()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::device_controller::device_factory::DeviceInstanceFactory;
+    use crate::device_controller::device_info_maker_helper::make_device_info;
+    use crate::device_controller::entity::device_po::DevicePo;
+    use crate::device_controller::workers::device_thread::dispatch_input_event;
+    use crate::driver::modbus::traits::ModbusListener;
+    use crate::entity::dto::device_state_dto::{SequenceEventEnum, StateDtoEnum};
+    use crate::entity::dto::input_event_dto::{InputEventDto, InputEventEnum};
+
+    #[test]
+    fn test_sequence_of_di_ports() {
+        let device_po_list: Vec<DevicePo> = [
+            ("bus_1", "modbus_bus", json!({"serial_port": "/dev/ttyUSB0"})),
+            ("di_controller_1", "modbus_di_controller", json!({"master_device_id": "bus_1", "unit": 1, "num": 2})),
+            ("di_1", "modbus_di_port", json!({"master_device_id": "di_controller_1", "address": 0})),
+            ("di_2", "modbus_di_port", json!({"master_device_id": "di_controller_1", "address": 1})),
+            ("puzzle", "sequence_detector", json!({"steps": [{"device_id": "di_2"}, {"device_id": "di_1"}]})),
+        ]
+        .into_iter()
+        .map(|(device_id, device_type, config)| DevicePo {
+            device_id: device_id.to_string(),
+            device_class: "".to_string(),
+            device_type: device_type.to_string(),
+            name: "".to_string(),
+            description: "".to_string(),
+            room: "".to_string(),
+            config,
+        })
+        .collect();
+        let device_info_map = Arc::new(Mutex::new(make_device_info(device_po_list.clone()).unwrap()));
+        let (tx, rx) = mpsc::channel();
+        let mut device_factory = DeviceInstanceFactory::new(tx);
+        device_factory.make_devices(device_info_map, device_po_list).unwrap();
+        assert!(device_factory.take_failed_devices().is_empty());
+        let device_map = device_factory.get_device_map();
+        let Some(DeviceRefEnum::ModbusDiController(di_controller_ref)) = device_map.get("di_controller_1") else {
+            panic!("di controller is not created");
+        };
+
+        // edges of di port reports are dispatched to the detector, as in reporting thread
+        let mut last_state: HashMap<String, StateDtoEnum> = HashMap::new();
+        let mut detector_events = Vec::new();
+        for values in [vec![false, false], vec![false, true], vec![true, false]] {
+            di_controller_ref.lock().unwrap().notify_from_bus(0, values).unwrap();
+            while let Ok(dto) = rx.try_recv() {
+                if let StateDtoEnum::Sequence(state) = &dto.status.state {
+                    detector_events.push(state.event);
+                }
+                let previous = last_state.insert(dto.device_id.clone(), dto.status.state.clone()).unwrap_or(StateDtoEnum::Empty);
+                if let Some(event) = InputEventEnum::from_state(&previous, &dto.status.state) {
+                    let input = InputEventDto { device_id: dto.device_id, event };
+                    assert!(dispatch_input_event(&device_map, &input).is_empty());
+                }
+            }
+        }
+        assert_eq!(
+            detector_events,
+            vec![SequenceEventEnum::Idle, SequenceEventEnum::SequenceProgress, SequenceEventEnum::SequenceMatched]
+        );
+    }
+}
@@ -1,17 +1,55 @@
-use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
-use crate::{common::error::DriverError, driver::serial::serial_bus::SerialBus };
-use crate::util::json;
+use std::sync::mpsc::Sender;
+use std::{cell::RefCell, rc::Rc};
+
+use serde_json::Value;
 
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::super::traits::{command_device, device_mismatch, DeviceDriver, DriverContext};
+use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
+use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
+use crate::{common::error::{CommandError, DriverError}, driver::serial::serial_bus::SerialBus };
+use crate::entity::po::device_config_po::SerialBusConfigPo;
+use crate::driver::traits::Commandable;
+use crate::entity::dto::device_command_dto::{parse_action, CommandParamsEnum, DeviceCommandDto};
+use crate::entity::dto::device_state_dto::StateDtoEnum;
 
-pub fn make(device_info: &DeviceMetaInfoDto) -> Result<SerialBus, DriverError> {
-    let serial_port = json::get_config_str(&device_info.config, "serial_port")?;
-    let baudrate = json::get_config_int(&device_info.config, "baudrate")?;
+pub fn make(device_info: &DeviceMetaInfoDto, config: &SerialBusConfigPo, report_tx: Sender<StateToDeviceControllerDto>) -> Result<SerialBus, DriverError> {
     let obj = SerialBus::new(
         device_info.device_id.as_str(), 
-        serial_port.as_str(), 
-        baudrate.try_into().map_err(
-            |e| DriverError(format!("device factory: cannot convert baudrate to int, err: {e}"))
-        )?
+        config.serial_port.as_str(),
+        config.baudrate,
+        report_tx,
     ); 
     Ok(obj)
-}
\ No newline at end of file
+}
+
+pub struct SerialBusDriver;
+
+impl DeviceDriver for SerialBusDriver {
+    type Config = SerialBusConfigPo;
+    const DEVICE_TYPE: &'static str = "serial_bus";
+    const ACTIONS: &'static [&'static str] = &["send"];
+
+    fn make(&self, ctx: &DriverContext, config: SerialBusConfigPo) -> Result<Option<DeviceRefEnum>, DriverError> {
+        let serial_bus = make(ctx.device_info, &config, ctx.report_tx.clone())?;
+        Ok(Some(DeviceRefEnum::SerialBus(Rc::new(RefCell::new(serial_bus)))))
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Ok(CommandParamsEnum::SerialBus(parse_action(action, param)?))
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        match device_ref {
+            DeviceRefEnum::SerialBus(serial_bus_ref) => command_device(serial_bus_ref, dto),
+            _ => Err(device_mismatch(Self::DEVICE_TYPE, &dto.device_id).into()),
+        }
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        match device_ref {
+            DeviceRefEnum::SerialBus(serial_bus_ref) => Some(RefCell::borrow(serial_bus_ref).get_state()),
+            _ => None,
+        }
+    }
+}
@@ -4,11 +4,16 @@
 //! - 提供设备操作的接口
 //! - 定期检查设备状态
 
+pub mod config_loader;
 pub mod device_controller;
 pub mod device_dao;
 pub mod device_factory;
 pub mod device_info_maker_helper;
+pub mod driver_registry;
+pub mod state_snapshot_dao;
+pub mod schedule_dao;
+pub mod scene_dao;
 mod factory;
-mod traits;
+pub mod traits;
 pub mod entity;
 mod workers;
\ No newline at end of file
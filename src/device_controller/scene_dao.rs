@@ -0,0 +1,143 @@
+//! 场景 dao 对象
+use crate::common::dao::Dao;
+use std::error::Error;
+use std::result::Result;
+use rusqlite::{params, OptionalExtension};
+
+use crate::common::sqlite::SqliteConnection;
+use super::entity::scene_po::ScenePo;
+use async_trait::async_trait;
+use crate::debug;
+
+pub struct SceneDao {
+    table_name: &'static str,
+}
+
+const LOG_TAG: &str = "scene_dao";
+
+#[async_trait]
+impl Dao for SceneDao {
+    async fn drop_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+        let table_name_copy = self.table_name;
+
+        conn.call( move|conn|
+            conn.execute(format!("DROP TABLE {}", table_name_copy).as_str(), ())
+        ).await?;
+        Ok(())
+    }
+
+    async fn create_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(|conn| {
+            conn.execute(
+                "CREATE TABLE scene (
+                        scene_id        TEXT PRIMARY KEY,
+                        name            TEXT NOT NULL,
+                        targets         TEXT NOT NULL,
+                        update_time     INTEGER NOT NULL
+                    )",
+                (),
+            )
+        })
+        .await?;
+
+        debug!(LOG_TAG, "scene table init complete");
+
+        Ok(())
+    }
+}
+
+impl SceneDao {
+    pub fn new() -> Self {
+        SceneDao {
+            table_name: "scene",
+        }
+    }
+
+    pub async fn ensure_table_exist(&self) -> Result<(), Box<dyn Error>> {
+        if self.check_table(self.table_name).await? {
+            debug!(LOG_TAG, "scene table already exist");
+        } else {
+            self.create_table().await?;
+            debug!(LOG_TAG, "scene table init");
+        }
+        Ok(())
+    }
+
+    /// 保存场景，相同 scene_id 的记录被替换
+    pub async fn save(&self, scene: ScenePo) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO scene (scene_id, name, targets, update_time) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    scene.scene_id,
+                    scene.name,
+                    serde_json::to_string(&scene.targets).unwrap_or_default(),
+                    scene.update_time as i64
+                ],
+            )
+        }).await?;
+
+        Ok(())
+    }
+
+    /// 删除场景，返回是否存在
+    pub async fn delete(&self, scene_id: String) -> tokio_rusqlite::Result<bool> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let count = conn.call(move |conn| {
+            conn.execute("DELETE FROM scene WHERE scene_id = ?1", params![scene_id])
+        }).await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn get(&self, scene_id: String) -> tokio_rusqlite::Result<Option<ScenePo>> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let res = conn.call(move |conn| {
+            conn.query_row(
+                "SELECT scene_id, name, targets, update_time FROM scene WHERE scene_id = ?1",
+                params![scene_id],
+                row_to_scene,
+            ).optional()
+        }).await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_all(&self) -> tokio_rusqlite::Result<Vec<ScenePo>> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let res = conn.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT scene_id, name, targets, update_time FROM scene ORDER BY scene_id",
+            )?;
+            let scene_iter = stmt.query_map([], row_to_scene)?;
+
+            let mut ret = Vec::new();
+            for scene in scene_iter {
+                ret.push(scene?);
+            }
+
+            Ok(ret)
+        }).await?;
+
+        Ok(res)
+    }
+}
+
+fn row_to_scene(row: &rusqlite::Row) -> rusqlite::Result<ScenePo> {
+    let targets_str: String = row.get(2)?;
+    let update_time: i64 = row.get(3)?;
+    Ok(ScenePo {
+        scene_id: row.get(0)?,
+        name: row.get(1)?,
+        targets: serde_json::from_str(&targets_str).unwrap_or_default(),
+        update_time: update_time as u64,
+    })
+}
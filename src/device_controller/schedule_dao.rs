@@ -0,0 +1,156 @@
+//! 定时指令 dao 对象
+use crate::common::dao::Dao;
+use std::error::Error;
+use std::result::Result;
+use rusqlite::params;
+
+use crate::common::sqlite::SqliteConnection;
+use super::entity::schedule_po::SchedulePo;
+use async_trait::async_trait;
+use crate::{debug, info};
+
+pub struct ScheduleDao {
+    table_name: &'static str,
+}
+
+const LOG_TAG: &str = "schedule_dao";
+
+#[async_trait]
+impl Dao for ScheduleDao {
+    async fn drop_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+        let table_name_copy = self.table_name;
+
+        conn.call( move|conn|
+            conn.execute(format!("DROP TABLE {}", table_name_copy).as_str(), ())
+        ).await?;
+        Ok(())
+    }
+
+    async fn create_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(|conn| {
+            conn.execute(
+                "CREATE TABLE schedule (
+                        schedule_id     TEXT PRIMARY KEY,
+                        device_id       TEXT NOT NULL,
+                        action          TEXT NOT NULL,
+                        param           TEXT NOT NULL,
+                        at              INTEGER,
+                        cron            TEXT,
+                        create_time     INTEGER NOT NULL,
+                        next_fire_time  INTEGER
+                    )",
+                (),
+            )
+        })
+        .await?;
+
+        debug!(LOG_TAG, "schedule table init complete");
+
+        Ok(())
+    }
+}
+
+impl ScheduleDao {
+    pub fn new() -> Self {
+        ScheduleDao {
+            table_name: "schedule",
+        }
+    }
+
+    pub async fn ensure_table_exist(&self) -> Result<(), Box<dyn Error>> {
+        if self.check_table(self.table_name).await? {
+            debug!(LOG_TAG, "schedule table already exist");
+            // schedule table created by older version has no next_fire_time column
+            if !self.check_column(self.table_name, "next_fire_time").await? {
+                self.add_next_fire_time_column().await?;
+                info!(LOG_TAG, "schedule table migrated, next_fire_time column added");
+            }
+        } else {
+            self.create_table().await?;
+            debug!(LOG_TAG, "schedule table init");
+        }
+        Ok(())
+    }
+
+    async fn add_next_fire_time_column(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(|conn| {
+            conn.execute("ALTER TABLE schedule ADD COLUMN next_fire_time INTEGER", ())
+        }).await?;
+
+        Ok(())
+    }
+
+    /// 保存定时指令，相同 schedule_id 的记录被替换
+    pub async fn save(&self, schedule: SchedulePo) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO schedule (schedule_id, device_id, action, param, at, cron, create_time, next_fire_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    schedule.schedule_id,
+                    schedule.device_id,
+                    schedule.action,
+                    schedule.param.to_string(),
+                    schedule.at.map(|at| at as i64),
+                    schedule.cron,
+                    schedule.create_time as i64,
+                    schedule.next_fire_time.map(|time| time as i64)
+                ],
+            )
+        }).await?;
+
+        Ok(())
+    }
+
+    /// 删除定时指令
+    pub async fn delete(&self, schedule_id: String) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(move |conn| {
+            conn.execute("DELETE FROM schedule WHERE schedule_id = ?1", params![schedule_id])
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all(&self) -> tokio_rusqlite::Result<Vec<SchedulePo>> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let res = conn.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT schedule_id, device_id, action, param, at, cron, create_time, next_fire_time FROM schedule",
+            )?;
+            let schedule_iter = stmt.query_map([], |row| {
+                let param_str: String = row.get(3)?;
+                let at: Option<i64> = row.get(4)?;
+                let create_time: i64 = row.get(6)?;
+                let next_fire_time: Option<i64> = row.get(7)?;
+                Ok(SchedulePo {
+                    schedule_id: row.get(0)?,
+                    device_id: row.get(1)?,
+                    action: row.get(2)?,
+                    param: serde_json::from_str(&param_str).unwrap_or_default(),
+                    at: at.map(|at| at as u64),
+                    cron: row.get(5)?,
+                    create_time: create_time as u64,
+                    next_fire_time: next_fire_time.map(|time| time as u64),
+                })
+            })?;
+
+            let mut ret = Vec::new();
+            for schedule in schedule_iter {
+                ret.push(schedule?);
+            }
+
+            Ok(ret)
+        }).await?;
+
+        Ok(res)
+    }
+}
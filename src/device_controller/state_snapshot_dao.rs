@@ -0,0 +1,120 @@
+//! 设备状态快照 dao 对象
+use crate::common::dao::Dao;
+use std::error::Error;
+use std::result::Result;
+use rusqlite::params;
+
+use crate::common::sqlite::SqliteConnection;
+use super::entity::state_snapshot_po::StateSnapshotPo;
+use async_trait::async_trait;
+use crate::debug;
+
+pub struct StateSnapshotDao {
+    table_name: &'static str,
+}
+
+const LOG_TAG: &str = "state_snapshot_dao";
+
+#[async_trait]
+impl Dao for StateSnapshotDao {
+    async fn drop_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+        let table_name_copy = self.table_name;
+
+        conn.call( move|conn| 
+            conn.execute(format!("DROP TABLE {}", table_name_copy).as_str(), ())
+        ).await?;
+        Ok(())
+    }
+
+    async fn create_table(&self) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(|conn| {
+            conn.execute(
+                "CREATE TABLE device_state_snapshot (
+                        device_id       TEXT PRIMARY KEY,
+                        device_type     TEXT NOT NULL,
+                        state           TEXT NOT NULL,
+                        update_time     INTEGER NOT NULL
+                    )",
+                (),
+            )
+        })
+        .await?;
+
+        debug!(LOG_TAG, "state snapshot table init complete");
+
+        Ok(())
+    }
+}
+
+impl StateSnapshotDao {
+    pub fn new() -> Self {
+        StateSnapshotDao {
+            table_name: "device_state_snapshot",
+        }
+    }
+
+    pub async fn ensure_table_exist(&self) -> Result<(), Box<dyn Error>> {
+        if self.check_table(self.table_name).await? {
+            debug!(LOG_TAG, "state snapshot table already exist");
+        } else {
+            self.create_table().await?;
+            debug!(LOG_TAG, "state snapshot table init");
+        }
+        Ok(())
+    }
+
+    /// 保存设备状态，每个设备只保留最后一次的状态
+    pub async fn save_all(&self, snapshot_list: Vec<StateSnapshotPo>) -> tokio_rusqlite::Result<()> {
+        let conn = SqliteConnection::get().open().await?;
+
+        conn.call(move |conn| {
+            let tx = conn.transaction()?;
+            for snapshot in snapshot_list.iter() {
+                tx.execute(
+                    "INSERT OR REPLACE INTO device_state_snapshot (device_id, device_type, state, update_time) VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        snapshot.device_id,
+                        snapshot.device_type,
+                        snapshot.state.to_string(),
+                        snapshot.update_time as i64
+                    ],
+                )?;
+            }
+            tx.commit()
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all(&self) -> tokio_rusqlite::Result<Vec<StateSnapshotPo>> {
+        let conn = SqliteConnection::get().open().await?;
+
+        let res = conn.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT device_id, device_type, state, update_time FROM device_state_snapshot",
+            )?;
+            let snapshot_iter = stmt.query_map([], |row| {
+                let state_str: String = row.get(2)?;
+                let update_time: i64 = row.get(3)?;
+                Ok(StateSnapshotPo {
+                    device_id: row.get(0)?,
+                    device_type: row.get(1)?,
+                    state: serde_json::from_str(&state_str).unwrap_or_default(),
+                    update_time: update_time as u64,
+                })
+            })?;
+
+            let mut ret = Vec::new();
+            for snapshot in snapshot_iter {
+                ret.push(snapshot?);
+            }
+
+            Ok(ret)
+        }).await?;
+
+        Ok(res)
+    }
+}
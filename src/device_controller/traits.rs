@@ -0,0 +1,174 @@
+//! 设备驱动注册 trait
+//! 新增设备类型只需实现 DeviceDriver，并在 factory/mod.rs 的 driver_list 中注册
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::mpsc::Sender};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::entity::device_enum::DeviceRefEnum;
+use crate::common::error::{CommandError, DriverError};
+use crate::driver::traits::Commandable;
+use crate::entity::dto::{
+    device_command_dto::{CommandParamsEnum, DeviceCommandDto},
+    device_meta_info_dto::DeviceMetaInfoDto,
+    device_state_dto::{StateDtoEnum, StateToDeviceControllerDto},
+};
+use crate::entity::po::device_config_po::parse_config;
+
+/// everything a driver needs to make a device
+pub struct DriverContext<'a> {
+    pub device_info: &'a DeviceMetaInfoDto,
+    // devices already made, master device is always made before its slaves
+    pub device_enum_map: &'a HashMap<String, DeviceRefEnum>,
+    // device info of all devices
+    pub device_info_map: &'a HashMap<String, DeviceMetaInfoDto>,
+    pub report_tx: Sender<StateToDeviceControllerDto>,
+}
+
+impl<'a> DriverContext<'a> {
+    /// get the made master device
+    pub fn master(&self) -> Result<&'a DeviceRefEnum, DriverError> {
+        let master_device_id = self.device_info.master_device_id.as_deref().ok_or(DriverError(format!(
+            "device factory: no master_device_id, device_id: {}",
+            self.device_info.device_id
+        )))?;
+        self.device_enum_map.get(master_device_id).ok_or(DriverError(format!(
+            "device factory: cannot find master device: {}, device_id: {}",
+            master_device_id, self.device_info.device_id
+        )))
+    }
+}
+
+/// device driver of one device type
+pub trait DeviceDriver: Send + Sync {
+    /// typed config, deserialized from device config json
+    type Config: DeserializeOwned;
+    /// device_type in device config
+    const DEVICE_TYPE: &'static str;
+    /// allowed device_type of master device, empty if the device has no master device
+    const MASTER_TYPES: &'static [&'static str] = &[];
+    /// actions accepted by command, empty if the device is not commandable
+    const ACTIONS: &'static [&'static str] = &[];
+    /// default staleness window in millis, the device is marked offline if it reports nothing in the window
+    /// none for devices only reporting on change
+    const STALE_TIMEOUT: Option<u64> = None;
+
+    /// make the device, return none if the device is mounted to its master device
+    fn make(&self, ctx: &DriverContext, config: Self::Config) -> Result<Option<DeviceRefEnum>, DriverError>;
+
+    /// parse action and param into typed command
+    fn parse_command(&self, _action: &str, _param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        Err(serde::de::Error::custom(format!(
+            "device type `{}` does not accept command",
+            Self::DEVICE_TYPE
+        )))
+    }
+
+    /// send command to device, return the state after command
+    fn command(&self, _device_ref: &DeviceRefEnum, _dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        Err(CommandError::Param(format!(
+            "device type `{}` does not accept command",
+            Self::DEVICE_TYPE
+        )))
+    }
+
+    /// read current state from device, none if the device does not provide state on demand
+    fn read_state(&self, _device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        None
+    }
+}
+
+/// object safe form of DeviceDriver, used by driver registry
+pub trait RegisteredDriver: Send + Sync {
+    fn device_type(&self) -> &'static str;
+    fn master_types(&self) -> &'static [&'static str];
+    fn actions(&self) -> &'static [&'static str];
+    fn stale_timeout(&self) -> Option<u64>;
+    /// check config against the typed config of the driver
+    fn check_config(&self, device_id: &str, config: &Value) -> Result<(), DriverError>;
+    /// check master device type and config, then make the device
+    fn make(&self, ctx: &DriverContext) -> Result<Option<DeviceRefEnum>, DriverError>;
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error>;
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError>;
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum>;
+}
+
+impl<T: DeviceDriver> RegisteredDriver for T {
+    fn device_type(&self) -> &'static str {
+        T::DEVICE_TYPE
+    }
+
+    fn master_types(&self) -> &'static [&'static str] {
+        T::MASTER_TYPES
+    }
+
+    fn actions(&self) -> &'static [&'static str] {
+        T::ACTIONS
+    }
+
+    fn stale_timeout(&self) -> Option<u64> {
+        T::STALE_TIMEOUT
+    }
+
+    fn check_config(&self, device_id: &str, config: &Value) -> Result<(), DriverError> {
+        parse_config::<T::Config>(device_id, config).map(|_| ())
+    }
+
+    fn make(&self, ctx: &DriverContext) -> Result<Option<DeviceRefEnum>, DriverError> {
+        check_master_type(ctx, T::MASTER_TYPES)?;
+        let config: T::Config = parse_config(&ctx.device_info.device_id, &ctx.device_info.config)?;
+        DeviceDriver::make(self, ctx, config)
+    }
+
+    fn parse_command(&self, action: &str, param: Value) -> Result<CommandParamsEnum, serde_json::Error> {
+        DeviceDriver::parse_command(self, action, param)
+    }
+
+    fn command(&self, device_ref: &DeviceRefEnum, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+        DeviceDriver::command(self, device_ref, dto)
+    }
+
+    fn read_state(&self, device_ref: &DeviceRefEnum) -> Option<StateDtoEnum> {
+        DeviceDriver::read_state(self, device_ref)
+    }
+}
+
+/// master device is required and must be one of master types, if master types is not empty
+fn check_master_type(ctx: &DriverContext, master_types: &[&str]) -> Result<(), DriverError> {
+    if master_types.is_empty() {
+        return Ok(());
+    }
+    let device_id = &ctx.device_info.device_id;
+    let master_device_id = ctx.device_info.master_device_id.as_deref().ok_or(DriverError(format!(
+        "device factory: no master_device_id for {}, device_id: {}",
+        ctx.device_info.device_type, device_id
+    )))?;
+    let master_device_type = ctx
+        .device_info_map
+        .get(master_device_id)
+        .map(|master_info| master_info.device_type.as_str())
+        .unwrap_or_default();
+    if !master_types.contains(&master_device_type) {
+        return Err(DriverError(format!(
+            "device factory: master device `{}` is `{}`, expected {:?}, device_id: {}",
+            master_device_id, master_device_type, master_types, device_id
+        )));
+    }
+    Ok(())
+}
+
+/// send command to commandable device and get the state after command
+pub fn command_device<D: Commandable>(device: &Rc<RefCell<D>>, dto: DeviceCommandDto) -> Result<StateDtoEnum, CommandError> {
+    let mut ref_cell = RefCell::borrow_mut(device);
+    ref_cell.cmd(dto)?;
+    Ok(ref_cell.get_state())
+}
+
+/// error when device ref does not match the driver
+pub fn device_mismatch(device_type: &str, device_id: &str) -> DriverError {
+    DriverError(format!(
+        "device is not `{}`, device_id: {}",
+        device_type, device_id
+    ))
+}
@@ -0,0 +1,250 @@
+//! hot reload of device config
+//! the devices are grouped by root device (the bus without master device),
+//! a changed device makes its whole subtree rebuilt, the other subtrees keep running
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{mpsc, Arc, Mutex},
+};
+
+use crate::{
+    common::error::{DeviceServerError, ServerErrorCode},
+    entity::dto::{
+        command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR},
+        device_meta_info_dto::DeviceMetaInfoDto,
+        device_state_dto::StateToDeviceControllerDto,
+    },
+};
+
+use super::super::{
+    config_loader::DeviceConfigLoader,
+    device_factory::DeviceInstanceFactory,
+    device_info_maker_helper::make_device_info,
+    entity::{device_enum::DeviceRefEnum, device_po::DevicePo},
+};
+use super::device_restore::restore_devices;
+use super::device_shutdown::stop_device;
+use super::device_thread::{report_device_error, start_device};
+use crate::{error, info, warn};
+
+const LOG_TAG: &str = "device_reloader";
+
+/// re-fetch device config from flow server, and rebuild the changed subtrees
+/// nothing is changed if the config cannot be fetched
+pub fn reload_devices(
+    state_report_tx_dummy: &mpsc::Sender<StateToDeviceControllerDto>,
+    device_enum_map: &mut HashMap<String, DeviceRefEnum>,
+    device_po_map: &mut HashMap<String, DevicePo>,
+    device_config_map: &Arc<Mutex<HashMap<String, DevicePo>>>,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    session_id: String,
+) -> CommandReplyDto {
+    let mut reply = CommandReplyDto {
+        msg: String::new(),
+        ..CommandReplyDto::new(session_id, "reload_devices".to_string())
+    };
+
+    // 1. fetch config
+    let device_po_list = match fetch_device_config() {
+        Ok(device_po_list) => device_po_list,
+        Err(e) => {
+            error!(LOG_TAG, "cannot reload device config, error msg: {}", e);
+            reply.code = REPLY_CODE_ERROR;
+            reply.msg = e.msg;
+            return reply;
+        }
+    };
+    let new_po_map: HashMap<String, DevicePo> = device_po_list
+        .iter()
+        .map(|device_po| (device_po.device_id.clone(), device_po.clone()))
+        .collect();
+
+    // 2. find the subtrees to rebuild
+    let affected_root_set = diff_config(device_po_map, &new_po_map);
+    if affected_root_set.is_empty() {
+        reply.msg = "device config unchanged".to_string();
+        return reply;
+    }
+    info!(LOG_TAG, "device config changed, rebuilding subtrees: {:?}", affected_root_set);
+
+    // 3. stop and remove old devices
+    let old_po_map: &HashMap<String, DevicePo> = device_po_map;
+    let removed_id_list: Vec<String> = old_po_map
+        .keys()
+        .filter(|device_id| affected_root_set.contains(&find_root(device_id, old_po_map)))
+        .cloned()
+        .collect();
+    let mut error_msg_list: Vec<String> = Vec::new();
+    for device_id in removed_id_list.iter() {
+        if let Some(device_ref) = device_enum_map.remove(device_id) {
+            // bus threads are joined here, so the serial port is released before the new bus opens it
+            if let Err(e) = stop_device(&device_ref) {
+                warn!(LOG_TAG, "stop device error, device_id: {}, error msg: {}", device_id, e);
+                error_msg_list.push(format!("{}: {}", device_id, e));
+            }
+        }
+    }
+    {
+        let mut map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+        for device_id in removed_id_list.iter() {
+            map_guard.remove(device_id);
+        }
+    }
+
+    // 4. make and start new devices, the order of config list is kept so that master device is made first
+    let rebuilt_po_list: Vec<DevicePo> = device_po_list
+        .into_iter()
+        .filter(|device_po| affected_root_set.contains(&find_root(&device_po.device_id, &new_po_map)))
+        .collect();
+    let rebuilt_count = rebuilt_po_list.len();
+    match make_device_info(rebuilt_po_list.clone()) {
+        Ok(info_map) => device_info_map.lock().unwrap_or_else(|e| e.into_inner()).extend(info_map),
+        Err(e) => error_msg_list.push(e.msg),
+    }
+    let mut device_factory = DeviceInstanceFactory::new(state_report_tx_dummy.clone());
+    if let Err(e) = device_factory.make_devices(device_info_map.clone(), rebuilt_po_list) {
+        error_msg_list.push(e.0);
+    }
+    for (device_id, e) in device_factory.take_failed_devices() {
+        error_msg_list.push(format!("{}: {}", device_id, e));
+        report_device_error(state_report_tx_dummy, &new_po_map, &device_id, e.0);
+    }
+    let new_device_enum_map = device_factory.get_device_map();
+    for (device_id, e) in start_device(&new_device_enum_map) {
+        error!(LOG_TAG, "cannot start reloaded device, device_id: {}, error msg: {}", device_id, e);
+        error_msg_list.push(format!("{}: {}", device_id, e));
+        report_device_error(state_report_tx_dummy, &new_po_map, &device_id, e.0);
+    }
+    for (device_id, e) in restore_devices(&new_device_enum_map, device_info_map) {
+        error!(LOG_TAG, "cannot restore reloaded device state, device_id: {}, error msg: {}", device_id, e);
+        error_msg_list.push(format!("{}: {}", device_id, e));
+        report_device_error(state_report_tx_dummy, &new_po_map, &device_id, e.0);
+    }
+    device_enum_map.extend(new_device_enum_map);
+
+    // 5. replace device config
+    *device_po_map = new_po_map;
+    *device_config_map.lock().unwrap_or_else(|e| e.into_inner()) = device_po_map.clone();
+
+    if error_msg_list.is_empty() {
+        reply.msg = format!(
+            "success, {} devices removed, {} devices rebuilt",
+            removed_id_list.len(),
+            rebuilt_count
+        );
+    } else {
+        reply.code = REPLY_CODE_ERROR;
+        reply.msg = format!("reload devices error: {}", error_msg_list.join("; "));
+    }
+    reply
+}
+
+/// get config from flow server and update local cache
+fn fetch_device_config() -> Result<Vec<DevicePo>, DeviceServerError> {
+    let rt = tokio::runtime::Runtime::new().map_err(|e| DeviceServerError {
+        code: ServerErrorCode::UnknownError,
+        msg: format!("cannot create runtime for reloading device config, error msg: {}", e),
+    })?;
+    rt.block_on(async {
+        // flow server must be available when reloading, the local cache is not used
+        DeviceConfigLoader::new().load(false).await
+    })
+}
+
+/// get root device ids of the subtrees containing added, removed or changed devices
+fn diff_config(
+    old_po_map: &HashMap<String, DevicePo>,
+    new_po_map: &HashMap<String, DevicePo>,
+) -> HashSet<String> {
+    let mut root_set = HashSet::new();
+    for device_id in old_po_map.keys().chain(new_po_map.keys()) {
+        if old_po_map.get(device_id) == new_po_map.get(device_id) {
+            continue;
+        }
+        if old_po_map.contains_key(device_id) {
+            root_set.insert(find_root(device_id, old_po_map));
+        }
+        if new_po_map.contains_key(device_id) {
+            root_set.insert(find_root(device_id, new_po_map));
+        }
+    }
+    root_set
+}
+
+/// follow master_device_id to the root device
+/// stop at the last known device if master device is missing or there is a cycle
+fn find_root(device_id: &str, device_po_map: &HashMap<String, DevicePo>) -> String {
+    let mut current = device_id;
+    for _ in 0..device_po_map.len() {
+        match device_po_map
+            .get(current)
+            .and_then(|device_po| device_po.config["master_device_id"].as_str())
+        {
+            Some(master_device_id) if device_po_map.contains_key(master_device_id) => {
+                current = master_device_id;
+            }
+            _ => break,
+        }
+    }
+    current.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_po(device_id: &str, master_device_id: Option<&str>) -> DevicePo {
+        DevicePo {
+            device_id: device_id.to_string(),
+            device_class: "test".to_string(),
+            device_type: "test".to_string(),
+            name: device_id.to_string(),
+            description: String::new(),
+            room: String::new(),
+            config: match master_device_id {
+                Some(master_device_id) => json!({"master_device_id": master_device_id}),
+                None => json!({}),
+            },
+        }
+    }
+
+    fn make_map(po_list: Vec<DevicePo>) -> HashMap<String, DevicePo> {
+        po_list
+            .into_iter()
+            .map(|device_po| (device_po.device_id.clone(), device_po))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_root() {
+        let po_map = make_map(vec![
+            make_po("modbus", None),
+            make_po("do_controller", Some("modbus")),
+            make_po("do_port", Some("do_controller")),
+            make_po("orphan", Some("missing")),
+        ]);
+        assert_eq!(find_root("do_port", &po_map), "modbus");
+        assert_eq!(find_root("modbus", &po_map), "modbus");
+        assert_eq!(find_root("orphan", &po_map), "orphan");
+    }
+
+    #[test]
+    fn test_diff_config() {
+        let old_po_map = make_map(vec![
+            make_po("modbus", None),
+            make_po("do_controller", Some("modbus")),
+            make_po("dmx", None),
+            make_po("dmx_channel", Some("dmx")),
+            make_po("audio", None),
+        ]);
+        // unchanged
+        assert!(diff_config(&old_po_map, &old_po_map.clone()).is_empty());
+
+        let mut new_po_map = old_po_map.clone();
+        new_po_map.get_mut("dmx_channel").unwrap().name = "renamed".to_string();
+        new_po_map.remove("audio");
+        let root_set = diff_config(&old_po_map, &new_po_map);
+        assert_eq!(root_set, HashSet::from(["dmx".to_string(), "audio".to_string()]));
+    }
+}
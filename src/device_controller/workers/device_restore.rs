@@ -0,0 +1,138 @@
+//! restore output state after devices are made and started
+//! supported devices: modbus_do_port, dmx_bus, dmx_channel, audio (files playing are played again from the beginning)
+//! restore policy is read from "restore_policy" of device config:
+//! - upstream (default): keep the state after starting, wait for the command from flow server
+//! - safe: write the safe state ("safe_state" of device config, see device_shutdown)
+//! - last: write the last state saved in state snapshot, the safe state if there is no snapshot
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::common::error::DriverError;
+use crate::driver::traits::ReportUpward;
+use crate::entity::dto::{
+    device_meta_info_dto::DeviceMetaInfoDto,
+    device_state_dto::{AudioStateDto, ChannelStateDto, DmxBusStateDto, DoStateDto},
+};
+use crate::entity::po::device_config_po::RestorePolicyEnum;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use super::device_shutdown::{SAFE_STATE_KEEP, SAFE_STATE_OFF, SAFE_STATE_ON};
+use super::state_snapshot_thread::load_state_snapshots;
+use crate::info;
+
+const LOG_TAG: &str = "device_restore";
+
+/// restore state of the devices according to their restore policy
+/// return the devices failed to restore
+pub fn restore_devices(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+) -> Vec<(String, DriverError)> {
+    // 1. get restore policy and safe state, the lock is released before writing devices
+    let mut policy_list: Vec<(String, RestorePolicyEnum, String)> = Vec::new();
+    {
+        let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+        for device_id in device_enum_map.keys() {
+            if let Some(device_info) = map_guard.get(device_id) {
+                if device_info.restore_policy != RestorePolicyEnum::Upstream {
+                    let safe_state = device_info.config["safe_state"].as_str().unwrap_or(SAFE_STATE_OFF);
+                    policy_list.push((device_id.clone(), device_info.restore_policy, safe_state.to_string()));
+                }
+            }
+        }
+    }
+    if policy_list.is_empty() {
+        return Vec::new();
+    }
+
+    // 2. load snapshots only if there is device restoring last state
+    let snapshot_map = if policy_list.iter().any(|(_, policy, _)| *policy == RestorePolicyEnum::Last) {
+        load_state_snapshots()
+    } else {
+        HashMap::new()
+    };
+
+    // 3. write state to devices
+    let mut failed_list = Vec::new();
+    for (device_id, policy, safe_state) in policy_list {
+        let Some(device_ref) = device_enum_map.get(&device_id) else {
+            continue;
+        };
+        let snapshot = match policy {
+            RestorePolicyEnum::Last => snapshot_map.get(&device_id),
+            _ => None,
+        };
+        let result = match snapshot {
+            Some(snapshot) => {
+                info!(LOG_TAG, "restore last state, device_id: {}, state: {}", &device_id, &snapshot.state);
+                restore_state(device_ref, snapshot.state.clone())
+            }
+            None => {
+                info!(LOG_TAG, "restore safe state, device_id: {}, safe_state: {}", &device_id, &safe_state);
+                restore_safe_state(device_ref, &safe_state)
+            }
+        };
+        if let Err(e) = result {
+            failed_list.push((device_id, e));
+        }
+    }
+    failed_list
+}
+
+/// write saved state to device
+fn restore_state(device_ref: &DeviceRefEnum, state: Value) -> Result<(), DriverError> {
+    match device_ref {
+        DeviceRefEnum::ModbusDoPort(do_port_ref) => {
+            let state: DoStateDto = parse_state(state)?;
+            RefCell::borrow_mut(do_port_ref).sync_on(state.on)
+        }
+        DeviceRefEnum::DmxBus(dmx_bus_ref) => {
+            let state: DmxBusStateDto = parse_state(state)?;
+            RefCell::borrow_mut(dmx_bus_ref).set_channels(0, &state.channel)
+        }
+        DeviceRefEnum::DmxChannel(dmx_channel_ref) => {
+            let state: ChannelStateDto = parse_state(state)?;
+            RefCell::borrow_mut(dmx_channel_ref).set_channels(0, &state.channels)
+        }
+        DeviceRefEnum::Audio(audio_ref) => {
+            let state: AudioStateDto = parse_state(state)?;
+            let mut audio = RefCell::borrow_mut(audio_ref);
+            for stream in state.stream.into_iter().filter(|stream| stream.playing) {
+                audio.play(stream.file_id)?;
+            }
+            audio.report()
+        }
+        _ => Err(DriverError("device does not support restoring state".to_string())),
+    }
+}
+
+/// write safe state to device, the output is written even if the cached state is the same
+/// because the real state of the output is unknown after restarting
+fn restore_safe_state(device_ref: &DeviceRefEnum, safe_state: &str) -> Result<(), DriverError> {
+    if safe_state == SAFE_STATE_KEEP {
+        return Ok(());
+    }
+    match device_ref {
+        DeviceRefEnum::ModbusDoPort(do_port_ref) => match safe_state {
+            SAFE_STATE_ON => RefCell::borrow_mut(do_port_ref).sync_on(true),
+            SAFE_STATE_OFF => RefCell::borrow_mut(do_port_ref).sync_on(false),
+            _ => Err(DriverError(format!("unknown safe state: {}", safe_state))),
+        },
+        DeviceRefEnum::DmxBus(dmx_bus_ref) => RefCell::borrow_mut(dmx_bus_ref).blackout(),
+        DeviceRefEnum::DmxChannel(dmx_channel_ref) => RefCell::borrow_mut(dmx_channel_ref).blackout(),
+        // nothing is playing after starting
+        DeviceRefEnum::Audio(_) => Ok(()),
+        _ => Err(DriverError("device does not support restoring state".to_string())),
+    }
+}
+
+fn parse_state<T: DeserializeOwned>(state: Value) -> Result<T, DriverError> {
+    serde_json::from_value(state).map_err(|e| DriverError(format!("invalid state snapshot, error: {}", e)))
+}
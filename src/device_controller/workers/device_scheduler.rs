@@ -0,0 +1,65 @@
+//! run scheduled actions of devices in device thread
+//! devices implementing Scheduled (e.g. pulse and blink of modbus_do_port, dmx crossfade, sequence reset timeout) keep their own schedule,
+//! device thread waits for command until the nearest deadline, then runs the due actions
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::common::error::DriverError;
+use crate::driver::traits::Scheduled;
+
+use super::super::entity::device_enum::DeviceRefEnum;
+use crate::trace;
+
+const LOG_TAG: &str = "device_scheduler";
+
+/// the nearest deadline of all scheduled devices, None if nothing is scheduled
+pub fn next_deadline(device_enum_map: &HashMap<String, DeviceRefEnum>) -> Option<Instant> {
+    device_enum_map
+        .values()
+        .filter_map(|device_ref| match device_ref {
+            DeviceRefEnum::ModbusDoPort(do_port_ref) => RefCell::borrow(do_port_ref).next_deadline(),
+            DeviceRefEnum::DmxBus(dmx_bus_ref) => RefCell::borrow(dmx_bus_ref).next_deadline(),
+            DeviceRefEnum::DmxChannel(dmx_channel_ref) => RefCell::borrow(dmx_channel_ref).next_deadline(),
+            DeviceRefEnum::SequenceDetector(detector_ref) => RefCell::borrow(detector_ref).next_deadline(),
+            _ => None,
+        })
+        .min()
+}
+
+/// time to wait before the nearest deadline, None if nothing is scheduled
+pub fn wait_timeout(device_enum_map: &HashMap<String, DeviceRefEnum>) -> Option<Duration> {
+    next_deadline(device_enum_map).map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// run the due actions of all scheduled devices
+/// return the devices failed to run
+pub fn run_scheduled(device_enum_map: &HashMap<String, DeviceRefEnum>) -> Vec<(String, DriverError)> {
+    let now = Instant::now();
+    let mut failed_list = Vec::new();
+    for (device_id, device_ref) in device_enum_map {
+        let result = match device_ref {
+            DeviceRefEnum::ModbusDoPort(do_port_ref) => run_if_due(device_id, do_port_ref, now),
+            DeviceRefEnum::DmxBus(dmx_bus_ref) => run_if_due(device_id, dmx_bus_ref, now),
+            DeviceRefEnum::DmxChannel(dmx_channel_ref) => run_if_due(device_id, dmx_channel_ref, now),
+            DeviceRefEnum::SequenceDetector(detector_ref) => run_if_due(device_id, detector_ref, now),
+            _ => continue,
+        };
+        if let Err(e) = result {
+            failed_list.push((device_id.clone(), e));
+        }
+    }
+    failed_list
+}
+
+fn run_if_due<T: Scheduled>(device_id: &str, device_ref: &Rc<RefCell<T>>, now: Instant) -> Result<(), DriverError> {
+    if RefCell::borrow(device_ref).next_deadline().is_none_or(|deadline| deadline > now) {
+        return Ok(());
+    }
+    trace!(LOG_TAG, "run scheduled action, device_id: {}", device_id);
+    RefCell::borrow_mut(device_ref).run_due(now)
+}
@@ -0,0 +1,69 @@
+//! drive devices to safe state and stop bus threads before the process exits
+//! audio playing is always stopped
+//! safe state is read from "safe_state" of device config:
+//! - modbus_do_port: "off" (default), "on", "keep"
+//! - dmx_bus, dmx_channel: "off" (default, set to zero), "keep"
+
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::common::error::DriverError;
+
+use super::super::entity::{device_enum::DeviceRefEnum, device_po::DevicePo};
+use crate::{error, info, warn};
+
+const LOG_TAG: &str = "device_shutdown";
+
+pub const SAFE_STATE_OFF: &str = "off";
+pub const SAFE_STATE_ON: &str = "on";
+pub const SAFE_STATE_KEEP: &str = "keep";
+
+/// apply safe state of all devices first, then stop all buses
+/// commands to the same bus thread are handled in order, so the safe state is written before the thread stops
+pub fn shutdown_devices(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+) {
+    for (device_id, device_ref) in device_enum_map {
+        let safe_state = device_po_map
+            .get(device_id)
+            .and_then(|device_po| device_po.config["safe_state"].as_str())
+            .unwrap_or(SAFE_STATE_OFF);
+        if let Err(e) = apply_safe_state(device_ref, safe_state) {
+            error!(LOG_TAG, "cannot set device to safe state, device_id: {}, error msg: {}", device_id, e);
+        }
+    }
+    for (device_id, device_ref) in device_enum_map {
+        if let Err(e) = stop_device(device_ref) {
+            warn!(LOG_TAG, "stop device error, device_id: {}, error msg: {}", device_id, e);
+        }
+    }
+    info!(LOG_TAG, "all devices are set to safe state and stopped");
+}
+
+/// set output device to its safe state
+fn apply_safe_state(device_ref: &DeviceRefEnum, safe_state: &str) -> Result<(), DriverError> {
+    if safe_state == SAFE_STATE_KEEP {
+        return Ok(());
+    }
+    match device_ref {
+        DeviceRefEnum::ModbusDoPort(do_port_ref) => match safe_state {
+            SAFE_STATE_ON => RefCell::borrow_mut(do_port_ref).set_on(true),
+            SAFE_STATE_OFF => RefCell::borrow_mut(do_port_ref).set_on(false),
+            _ => Err(DriverError(format!("unknown safe state: {}", safe_state))),
+        },
+        DeviceRefEnum::DmxBus(dmx_bus_ref) => RefCell::borrow_mut(dmx_bus_ref).blackout(),
+        DeviceRefEnum::DmxChannel(dmx_channel_ref) => RefCell::borrow_mut(dmx_channel_ref).blackout(),
+        _ => Ok(()),
+    }
+}
+
+/// stop the threads of bus devices and audio playing
+pub fn stop_device(device_ref: &DeviceRefEnum) -> Result<(), DriverError> {
+    match device_ref {
+        DeviceRefEnum::ModbusBus(modbus_ref) => RefCell::borrow_mut(modbus_ref).stop(),
+        DeviceRefEnum::SerialBus(serial_ref) => RefCell::borrow_mut(serial_ref).stop(),
+        DeviceRefEnum::DmxBus(dmx_ref) => RefCell::borrow_mut(dmx_ref).stop(),
+        DeviceRefEnum::Audio(audio_ref) => RefCell::borrow_mut(audio_ref).stop_all(),
+        _ => Ok(()),
+    }
+}
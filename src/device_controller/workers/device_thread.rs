@@ -1,159 +1,630 @@
 use std::{
-    any::Any,
     borrow::{Borrow, BorrowMut},
     cell::RefCell,
     collections::HashMap,
     fmt::format,
-    process::exit,
     sync::{mpsc, Arc, Mutex},
     thread::{self},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    common::error::DriverError,
+    common::error::{CommandError, DriverError},
     entity::dto::{
+        command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR, REPLY_CODE_OK, REPLY_CODE_PARAM_FAIL},
         device_command_dto::DeviceCommandDto,
         device_state_dto::StateToDeviceControllerDto,
+        mqtt_dto::{DeviceToMqttEnum, MqttToDeviceEnum},
+        broadcast_command_dto::{BroadcastActionEnum, BroadcastCommandDto},
+        batch_command_dto::{BatchCommandDto, BatchCommandItemDto},
+        device_report_dto::DeviceReportDto,
+        get_state_command_dto::GetStateCommandDto,
+        input_event_dto::InputEventDto,
     },
 };
 
 use super::super::{
     device_factory::DeviceInstanceFactory,
+    driver_registry::DriverRegistry,
     entity::{device_enum::DeviceRefEnum, device_po::DevicePo},
 };
-use crate::driver::traits::Commandable;
+use super::device_reloader::reload_devices;
+use super::device_restore::restore_devices;
+use super::device_scheduler::{run_scheduled, wait_timeout};
+use super::scene_thread::SceneThreadCommandEnum;
+use super::device_shutdown::shutdown_devices;
+use super::scheduler_thread::SchedulerCommandEnum;
+use super::state_snapshot_thread::SnapshotThreadCommandEnum;
+use crate::common::supervisor::supervise;
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
-use crate::{debug, error, info, trace, warn};
+use crate::file_controller::file_controller::FileController;
+use crate::{debug, error, info, warn};
 
 const LOG_TAG: &'static str = "device_thread";
+// waiting time after devices are stopped, before notifying mqtt client to quit
+const SHUTDOWN_SETTLE_INTERVAL: u64 = 500;
+
+/// channels and shared maps used by device thread
+pub struct DeviceThreadCtx {
+    pub state_report_tx: mpsc::Sender<StateToDeviceControllerDto>,
+    pub command_rx: mpsc::Receiver<MqttToDeviceEnum>,
+    pub device_to_mqtt_tx: mpsc::Sender<DeviceToMqttEnum>,
+    pub device_config_map: Arc<Mutex<HashMap<String, DevicePo>>>,
+    pub device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    pub heartbeat_stop_tx: mpsc::Sender<()>,
+    pub snapshot_tx: mpsc::Sender<SnapshotThreadCommandEnum>,
+    pub scheduler_tx: mpsc::Sender<SchedulerCommandEnum>,
+    pub scene_tx: mpsc::Sender<SceneThreadCommandEnum>,
+}
 
 /// device thread, use config to create device object, and send command to them
-pub fn device_thread(
-    state_report_tx_dummy: mpsc::Sender<StateToDeviceControllerDto>,
-    command_rx: mpsc::Receiver<DeviceCommandDto>,
-    device_po_list: Vec<DevicePo>,
-    device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
-) -> thread::JoinHandle<()> {
+pub fn device_thread(ctx: DeviceThreadCtx, device_po_list: Vec<DevicePo>) -> thread::JoinHandle<()> {
     thread::spawn(move || {
+        let DeviceThreadCtx {
+            state_report_tx: state_report_tx_dummy,
+            command_rx,
+            device_to_mqtt_tx,
+            device_config_map,
+            device_info_map,
+            heartbeat_stop_tx,
+            snapshot_tx,
+            scheduler_tx,
+            scene_tx,
+        } = ctx;
+
+        // device config, used for matching broadcast filter
+        let mut device_po_map: HashMap<String, DevicePo> = device_po_list
+            .iter()
+            .map(|device_po| (device_po.device_id.clone(), device_po.clone()))
+            .collect();
+
         // 1. make devices according to config
-        let mut device_factory = DeviceInstanceFactory::new(state_report_tx_dummy);
-        device_factory
-            .make_devices(device_info_map, device_po_list)
-            .unwrap();
-        let device_enum_map = device_factory.get_device_map();
+        let mut device_factory = DeviceInstanceFactory::new(state_report_tx_dummy.clone());
+        if let Err(e) = device_factory.make_devices(device_info_map.clone(), device_po_list) {
+            error!(LOG_TAG, "make devices error, error msg: {}", e);
+        }
+        for (device_id, e) in device_factory.take_failed_devices() {
+            report_device_error(&state_report_tx_dummy, &device_po_map, &device_id, e.0);
+        }
+        let mut device_enum_map = device_factory.get_device_map();
         info!(
             LOG_TAG,
             "device enum map length: {}",
             device_enum_map.len()
         );
 
-        // 2. start running device
-        match start_device(&device_enum_map) {
-            Ok(_) => {}
-            Err(e) => {
-                error!(
-                    LOG_TAG,
-                    "cannot start device, error msg: {}", e
-                );
-                exit(1)
-            }
+        // 2. start running device, the failed device is marked error and the others keep running
+        for (device_id, e) in start_device(&device_enum_map) {
+            error!(
+                LOG_TAG,
+                "cannot start device, device_id: {}, error msg: {}", device_id, e
+            );
+            report_device_error(&state_report_tx_dummy, &device_po_map, &device_id, e.0);
+        }
+        info!(LOG_TAG, "bus devices started");
+
+        // 3. restore output state according to restore policy
+        for (device_id, e) in restore_devices(&device_enum_map, &device_info_map) {
+            error!(
+                LOG_TAG,
+                "cannot restore device state, device_id: {}, error msg: {}", device_id, e
+            );
+            report_device_error(&state_report_tx_dummy, &device_po_map, &device_id, e.0);
         }
-        info!(LOG_TAG, "successfully start all bus devices");
-
-        loop {
-            info!(LOG_TAG, "waitting for device command");
-
-            // listen on device command
-            let recv_message = command_rx.recv();
-            match recv_message {
-                Ok(dto) => {
-                    let device_id = &dto.device_id;
-                    info!(LOG_TAG, "got device command, dto: {:?}", dto);
-                    // get device enum from map, if is none then print error msg
-                    match device_enum_map.get(device_id) {
-                        Some(device_enum) => {
-                            info!(LOG_TAG, "sending command to device {:?}", dto);
-                            // send command to device
-                            match send_command_to_device(device_enum, dto) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    error!(LOG_TAG, "command device error, error msg: {}", e);
-                                    continue;
+
+        // 4. handle device command, restart on panic
+        supervise("device_thread", |_| {}, || -> Result<(), DriverError> {
+            loop {
+                // run scheduled actions which are due
+                for (device_id, e) in run_scheduled(&device_enum_map) {
+                    error!(
+                        LOG_TAG,
+                        "scheduled action failed, device_id: {}, error msg: {}", device_id, e
+                    );
+                    report_device_error(&state_report_tx_dummy, &device_po_map, &device_id, e.0);
+                }
+
+                // listen on device command, wake up at the nearest deadline of scheduled actions
+                let recv_message = match wait_timeout(&device_enum_map) {
+                    Some(timeout) => match command_rx.recv_timeout(timeout) {
+                        Ok(message) => Ok(message),
+                        Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+                    },
+                    None => {
+                        info!(LOG_TAG, "waitting for device command");
+                        command_rx.recv()
+                    }
+                };
+                match recv_message {
+                    Ok(MqttToDeviceEnum::DeviceCommand(dto)) => {
+                        info!(LOG_TAG, "got device command, dto: {:?}", dto);
+                        let reply = command_device(&device_enum_map, &device_po_map, dto);
+                        send_reply(&device_to_mqtt_tx, reply);
+                    }
+                    Ok(MqttToDeviceEnum::LocalCommand(dto)) => {
+                        info!(LOG_TAG, "got local command, dto: {:?}", dto);
+                        let reply = command_device(&device_enum_map, &device_po_map, dto);
+                        log_local_reply(&reply);
+                    }
+                    Ok(MqttToDeviceEnum::Batch(dto)) => {
+                        info!(LOG_TAG, "got batch command, dto: {:?}", dto);
+                        let reply = batch_command_devices(&device_enum_map, &device_po_map, dto);
+                        send_reply(&device_to_mqtt_tx, reply);
+                    }
+                    Ok(MqttToDeviceEnum::GetState(dto)) => {
+                        info!(LOG_TAG, "got get state command, dto: {:?}", dto);
+                        let reply = get_device_status(&device_enum_map, &device_info_map, dto);
+                        send_reply(&device_to_mqtt_tx, reply);
+                    }
+                    Ok(MqttToDeviceEnum::Broadcast(dto)) => {
+                        info!(LOG_TAG, "got broadcast command, dto: {:?}", dto);
+                        if dto.action == BroadcastActionEnum::ReloadFiles {
+                            reload_files(&device_enum_map, &device_po_map, dto, &device_to_mqtt_tx);
+                        } else {
+                            let reply = broadcast_to_devices(&device_enum_map, &device_po_map, dto);
+                            send_reply(&device_to_mqtt_tx, reply);
+                        }
+                    }
+                    Ok(MqttToDeviceEnum::ReloadDevices(dto)) => {
+                        info!(LOG_TAG, "got reload devices command, dto: {:?}", dto);
+                        let reply = reload_devices(
+                            &state_report_tx_dummy,
+                            &mut device_enum_map,
+                            &mut device_po_map,
+                            &device_config_map,
+                            &device_info_map,
+                            dto.session_id,
+                        );
+                        match dto.reply_tx {
+                            Some(reply_tx) => {
+                                let _ = reply_tx.send(reply);
+                            }
+                            None => send_reply(&device_to_mqtt_tx, reply),
+                        }
+                    }
+                    Ok(MqttToDeviceEnum::Scene(dto)) => {
+                        info!(LOG_TAG, "got scene command, dto: {:?}", dto);
+                        let reply_tx = dto.reply_tx.clone();
+                        let reply = CommandReplyDto {
+                            code: REPLY_CODE_ERROR,
+                            msg: "scene thread is not running".to_string(),
+                            ..CommandReplyDto::new(dto.session_id.clone(), dto.request.action().as_str().to_string())
+                        };
+                        if let Err(e) = scene_tx.send(SceneThreadCommandEnum::Request(dto)) {
+                            error!(LOG_TAG, "cannot send scene command to scene thread, error msg: {}", e);
+                            match reply_tx {
+                                Some(reply_tx) => {
+                                    let _ = reply_tx.send(reply);
                                 }
+                                None => send_reply(&device_to_mqtt_tx, reply),
+                            }
+                        }
+                    }
+                    Ok(MqttToDeviceEnum::SceneRecall(dto)) => {
+                        info!(LOG_TAG, "got scene recall, dto: {:?}", dto.batch);
+                        let mut reply = batch_command_devices(&device_enum_map, &device_po_map, dto.batch);
+                        reply.action = "recall_scene".to_string();
+                        match dto.reply_tx {
+                            Some(reply_tx) => {
+                                let _ = reply_tx.send(reply);
                             }
+                            None => send_reply(&device_to_mqtt_tx, reply),
                         }
-                        None => {
+                    }
+                    Ok(MqttToDeviceEnum::Schedule(dto)) => {
+                        info!(LOG_TAG, "got schedule command, dto: {:?}", dto);
+                        let session_id = dto.session_id.clone();
+                        let action = dto.request.action().as_str().to_string();
+                        if let Err(e) = scheduler_tx.send(SchedulerCommandEnum::Request(dto)) {
+                            error!(LOG_TAG, "cannot send schedule command to scheduler thread, error msg: {}", e);
+                            send_reply(&device_to_mqtt_tx, CommandReplyDto {
+                                code: REPLY_CODE_ERROR,
+                                msg: "scheduler is not running".to_string(),
+                                ..CommandReplyDto::new(session_id, action)
+                            });
+                        }
+                    }
+                    Ok(MqttToDeviceEnum::InputEvent(dto)) => {
+                        debug!(LOG_TAG, "got input event, dto: {:?}", dto);
+                        for (device_id, e) in dispatch_input_event(&device_enum_map, &dto) {
                             error!(
                                 LOG_TAG,
-                                "cannot send command to device, unable to find device_id: {} in device_enum_map", device_id
+                                "cannot dispatch input event, device_id: {}, error msg: {}", device_id, e
                             );
-                            continue;
                         }
                     }
-                }
-                Err(e) => {
-                    warn!(
-                        LOG_TAG,
-                        "device worker thread exiting: device command downward channel closing, error msg: {}", e
-                    );
-                    return;
+                    Ok(MqttToDeviceEnum::Shutdown) => {
+                        info!(LOG_TAG, "got shutdown command, stopping all devices");
+                        // no scheduled command is fired after devices are in safe state
+                        let _ = scheduler_tx.send(SchedulerCommandEnum::Stop);
+                        let _ = scene_tx.send(SceneThreadCommandEnum::Stop);
+                        // the safe state is not saved, so that the last state is restored after restarting
+                        let _ = snapshot_tx.send(SnapshotThreadCommandEnum::Stop);
+                        shutdown_devices(&device_enum_map, &device_po_map);
+                        let _ = heartbeat_stop_tx.send(());
+                        // wait for state reports of safe state reaching mqtt client
+                        thread::sleep(Duration::from_millis(SHUTDOWN_SETTLE_INTERVAL));
+                        if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::Shutdown) {
+                            error!(LOG_TAG, "cannot send shutdown message to mqtt client, error msg: {}", e);
+                        }
+                        info!(LOG_TAG, "device worker thread exiting: shutdown");
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        warn!(
+                            LOG_TAG,
+                            "device worker thread exiting: device command downward channel closing, error msg: {}", e
+                        );
+                        return Ok(());
+                    }
                 }
             }
-        }
+        })
     })
 }
 
 /// check all devices and run the threads if device has one
-pub fn start_device(device_enum_map: &HashMap<String, DeviceRefEnum>) -> Result<(), DriverError> {
+/// return the devices failed to start
+pub fn start_device(device_enum_map: &HashMap<String, DeviceRefEnum>) -> Vec<(String, DriverError)> {
+    let mut failed_list = Vec::new();
     for (device_id, device_ref) in device_enum_map {
-        match device_ref {
+        let result = match device_ref {
             // run modbus
-            DeviceRefEnum::ModbusBus(master_modbus_ref) => {
-                let mut ref_cell = RefCell::borrow_mut(master_modbus_ref);
-                ref_cell.start()?;
-            }
+            DeviceRefEnum::ModbusBus(master_modbus_ref) => RefCell::borrow_mut(master_modbus_ref).start(),
             // run serial bus
-            DeviceRefEnum::SerialBus(master_serial_ref) => {
-                let mut ref_cell = RefCell::borrow_mut(master_serial_ref);
-                ref_cell.start()?;
-            }
-            DeviceRefEnum::DmxBus(master_dmx_ref) => {
-                let mut ref_cell = RefCell::borrow_mut(master_dmx_ref);
-                ref_cell.start()?;
-            }
+            DeviceRefEnum::SerialBus(master_serial_ref) => RefCell::borrow_mut(master_serial_ref).start(),
             // run dmx bus
-            _ => {}
+            DeviceRefEnum::DmxBus(master_dmx_ref) => RefCell::borrow_mut(master_dmx_ref).start(),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            failed_list.push((device_id.clone(), e));
         }
     }
-    Ok(())
+    failed_list
 }
 
-/// command device method
-/// only limited type of device can be called.
-/// selected type of device can only be called by specific params in device command dto
-pub fn send_command_to_device(
-    device_ref: &DeviceRefEnum,
-    command_dto: DeviceCommandDto,
-) -> Result<(), DriverError> {
-    match device_ref {
-        // do device
-        DeviceRefEnum::ModbusDoPort(do_port_ref_cell) => {
-            let mut ref_cell = RefCell::borrow_mut(do_port_ref_cell);
-            ref_cell.cmd(command_dto)?;
-            Ok(())
-        },
-        // audio device
-        DeviceRefEnum::Audio(audio_ref_cell) => {
-            let mut ref_cell = RefCell::borrow_mut(audio_ref_cell);
-            ref_cell.cmd(command_dto)?;
-            Ok(())
+/// dispatch input event to all sequence detectors
+/// return the detectors failed to report
+pub fn dispatch_input_event(device_enum_map: &HashMap<String, DeviceRefEnum>, dto: &InputEventDto) -> Vec<(String, DriverError)> {
+    let now = Instant::now();
+    let mut failed_list = Vec::new();
+    for (device_id, device_ref) in device_enum_map {
+        if let DeviceRefEnum::SequenceDetector(detector_ref) = device_ref {
+            if let Err(e) = RefCell::borrow_mut(detector_ref).notify_input(dto, now) {
+                failed_list.push((device_id.clone(), e));
+            }
+        }
+    }
+    failed_list
+}
+
+/// report device error to reporting thread, the device is marked error and reported upstream
+pub fn report_device_error(
+    state_report_tx: &mpsc::Sender<StateToDeviceControllerDto>,
+    device_po_map: &HashMap<String, DevicePo>,
+    device_id: &str,
+    error_msg: String,
+) {
+    let (device_class, device_type) = match device_po_map.get(device_id) {
+        Some(device_po) => (device_po.device_class.as_str(), device_po.device_type.as_str()),
+        None => ("", ""),
+    };
+    let dto = StateToDeviceControllerDto::error(device_id, device_class, device_type, error_msg);
+    if let Err(e) = state_report_tx.send(dto) {
+        error!(LOG_TAG, "cannot report device error, device_id: {}, error msg: {}", device_id, e);
+    }
+}
+
+/// send command to single device and make the reply
+fn command_device(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+    dto: DeviceCommandDto,
+) -> CommandReplyDto {
+    let device_id = dto.device_id.clone();
+    let mut reply = CommandReplyDto {
+        device_type: Some(dto.device_type.clone()),
+        device_id: Some(device_id.clone()),
+        ..CommandReplyDto::new(dto.session_id.clone(), dto.action.clone())
+    };
+    // command is parsed according to the device type in topic, it should be the same as config
+    if let Some(device_po) = device_po_map.get(&device_id) {
+        if device_po.device_type != dto.device_type {
+            reply.code = REPLY_CODE_PARAM_FAIL;
+            reply.msg = format!(
+                "device type mismatch, device_id: {}, expected: {}, got: {}",
+                device_id, device_po.device_type, dto.device_type
+            );
+            return reply;
+        }
+    }
+    // get device enum from map, if is none then print error msg
+    match device_enum_map.get(&device_id) {
+        Some(device_enum) => {
+            info!(LOG_TAG, "sending command to device {:?}", dto);
+            // send command to device
+            match DriverRegistry::get().command(device_enum, dto) {
+                Ok(state) => {
+                    reply.state = Some(state);
+                }
+                Err(e) => {
+                    error!(LOG_TAG, "command device error, error msg: {}", e);
+                    reply.code = match e {
+                        CommandError::Param(_) => REPLY_CODE_PARAM_FAIL,
+                        CommandError::Device(_) => REPLY_CODE_ERROR,
+                    };
+                    reply.msg = e.msg().to_string();
+                }
+            }
         }
+        None => {
+            error!(
+                LOG_TAG,
+                "cannot send command to device, unable to find device_id: {} in device_enum_map", device_id
+            );
+            reply.code = REPLY_CODE_PARAM_FAIL;
+            reply.msg = format!("cannot find device: {}", device_id);
+        }
+    }
+    reply
+}
+
+/// apply commands of batch command in order, and make one aggregated reply
+/// atomic: validate all commands first (device exists, accepts command, param is valid),
+/// nothing is executed if any of them is invalid, and stop at the first failed command
+pub fn batch_command_devices(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+    dto: BatchCommandDto,
+) -> CommandReplyDto {
+    let total = dto.commands.len();
+    let command_list: Vec<Result<DeviceCommandDto, Box<CommandReplyDto>>> = dto
+        .commands
+        .into_iter()
+        .map(|item| make_batch_item_command(device_enum_map, device_po_map, &dto.server_id, &dto.session_id, item))
+        .collect();
+    let is_valid = command_list.iter().all(|command| command.is_ok());
+
+    let mut results: Vec<CommandReplyDto> = Vec::new();
+    let mut stopped = dto.atomic && !is_valid;
+    for command in command_list {
+        let reply = match command {
+            Err(reply) => *reply,
+            Ok(command_dto) if stopped => CommandReplyDto {
+                code: REPLY_CODE_PARAM_FAIL,
+                msg: "not executed, batch command aborted".to_string(),
+                device_type: Some(command_dto.device_type),
+                device_id: Some(command_dto.device_id),
+                ..CommandReplyDto::new(command_dto.session_id, command_dto.action)
+            },
+            Ok(command_dto) => {
+                let reply = command_device(device_enum_map, device_po_map, command_dto);
+                if dto.atomic && reply.code != REPLY_CODE_OK {
+                    stopped = true;
+                }
+                reply
+            }
+        };
+        results.push(reply);
+    }
+
+    let success_count = results.iter().filter(|reply| reply.code == REPLY_CODE_OK).count();
+    let code = if success_count == total {
+        REPLY_CODE_OK
+    } else if dto.atomic && !is_valid {
+        REPLY_CODE_PARAM_FAIL
+    } else {
+        REPLY_CODE_ERROR
+    };
+    CommandReplyDto {
+        code,
+        msg: format!("{} of {} commands succeeded", success_count, total),
+        results: Some(results),
+        ..CommandReplyDto::new(dto.session_id, "batch".to_string())
+    }
+}
+
+/// make device command from batch item, device type is taken from device config
+fn make_batch_item_command(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+    server_id: &str,
+    session_id: &str,
+    item: BatchCommandItemDto,
+) -> Result<DeviceCommandDto, Box<CommandReplyDto>> {
+    let mut reply = CommandReplyDto {
+        code: REPLY_CODE_PARAM_FAIL,
+        msg: String::new(),
+        device_id: Some(item.device_id.clone()),
+        ..CommandReplyDto::new(session_id.to_string(), item.action.clone())
+    };
+    let device_po = match (device_po_map.get(&item.device_id), device_enum_map.get(&item.device_id)) {
+        (Some(device_po), Some(_)) => device_po,
         _ => {
-            // do nothing
-            Err(DriverError(format!(
-                "not support device type, device type={:?}",
-                device_ref.type_id()
-            )))
+            reply.msg = format!("cannot find device: {}", item.device_id);
+            return Err(Box::new(reply));
         }
+    };
+    reply.device_type = Some(device_po.device_type.clone());
+    if !DriverRegistry::get().is_commandable(&device_po.device_type) {
+        reply.msg = format!("device does not accept command: {}", item.device_id);
+        return Err(Box::new(reply));
     }
-}
\ No newline at end of file
+    let params = match DriverRegistry::get().parse_command(&device_po.device_type, &item.action, item.param) {
+        Ok(params) => params,
+        Err(e) => {
+            reply.msg = format!("invalid {} command `{}`: {e}", device_po.device_type, item.action);
+            return Err(Box::new(reply));
+        }
+    };
+    Ok(DeviceCommandDto {
+        server_id: server_id.to_string(),
+        device_id: item.device_id,
+        device_type: device_po.device_type.clone(),
+        session_id: session_id.to_string(),
+        action: item.action,
+        params,
+    })
+}
+
+/// read device status from device info map, and make the reply of get command
+/// fresh: read state from commandable drivers directly and update device info map
+fn get_device_status(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    dto: GetStateCommandDto,
+) -> CommandReplyDto {
+    let mut reply = CommandReplyDto {
+        device_id: dto.device_id.clone(),
+        ..CommandReplyDto::new(dto.session_id, "get".to_string())
+    };
+
+    let mut map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+    let device_id_list: Vec<String> = match &dto.device_id {
+        Some(device_id) => {
+            if !map_guard.contains_key(device_id) {
+                reply.code = REPLY_CODE_PARAM_FAIL;
+                reply.msg = format!("cannot find device: {}", device_id);
+                return reply;
+            }
+            vec![device_id.clone()]
+        }
+        None => map_guard.keys().cloned().collect(),
+    };
+
+    let mut status_map: HashMap<String, DeviceReportDto> = HashMap::new();
+    for device_id in device_id_list {
+        if let Some(device_info) = map_guard.get_mut(&device_id) {
+            if dto.fresh {
+                if let Some(state) = device_enum_map
+                    .get(&device_id)
+                    .and_then(|device_ref| DriverRegistry::get().read_state(&device_info.device_type, device_ref))
+                {
+                    device_info.state = state;
+                }
+            }
+            if dto.device_id.is_some() {
+                reply.device_type = Some(device_info.device_type.clone());
+                reply.state = Some(device_info.state.clone());
+            }
+            status_map.insert(device_id, DeviceReportDto::from_device_meta_info(device_info));
+        }
+    }
+    reply.status = Some(status_map);
+    reply
+}
+
+/// send broadcast command to every device matching the filter
+/// the reply is successful only if all matching devices succeed
+fn broadcast_to_devices(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+    dto: BroadcastCommandDto,
+) -> CommandReplyDto {
+    let mut count = 0;
+    let mut error_msg_list: Vec<String> = Vec::new();
+    for (device_id, device_ref) in device_enum_map {
+        let is_match = match device_po_map.get(device_id) {
+            Some(device_po) => dto.filter.is_match(&device_po.device_type, &device_po.room),
+            None => false,
+        };
+        if !is_match {
+            continue;
+        }
+        let result = match (dto.action, device_ref) {
+            (BroadcastActionEnum::AllOff, DeviceRefEnum::ModbusDoPort(do_port_ref_cell)) => {
+                RefCell::borrow_mut(do_port_ref_cell).set_on(false)
+            }
+            (BroadcastActionEnum::Blackout, DeviceRefEnum::DmxBus(dmx_bus_ref_cell)) => {
+                RefCell::borrow_mut(dmx_bus_ref_cell).blackout()
+            }
+            (BroadcastActionEnum::Blackout, DeviceRefEnum::DmxChannel(dmx_channel_ref_cell)) => {
+                RefCell::borrow_mut(dmx_channel_ref_cell).blackout()
+            }
+            (BroadcastActionEnum::StopAudio, DeviceRefEnum::Audio(audio_ref_cell)) => {
+                RefCell::borrow_mut(audio_ref_cell).stop_all()
+            }
+            _ => continue,
+        };
+        count += 1;
+        if let Err(e) = result {
+            error!(LOG_TAG, "broadcast command device error, device_id: {}, error msg: {}", device_id, e);
+            error_msg_list.push(format!("{}: {}", device_id, e));
+        }
+    }
+
+    let action = serde_json::to_value(dto.action)
+        .ok()
+        .and_then(|action| action.as_str().map(|action| action.to_string()))
+        .unwrap_or_default();
+    let (code, msg) = if error_msg_list.is_empty() {
+        (REPLY_CODE_OK, format!("success, {} devices", count))
+    } else {
+        (
+            REPLY_CODE_ERROR,
+            format!("{} of {} devices failed: {}", error_msg_list.len(), count, error_msg_list.join("; ")),
+        )
+    };
+    CommandReplyDto {
+        code,
+        msg,
+        ..CommandReplyDto::new(dto.session_id, action)
+    }
+}
+
+/// reload file list if any audio device matches the filter
+/// downloading may take a long time, it is done in another thread which sends the reply
+fn reload_files(
+    device_enum_map: &HashMap<String, DeviceRefEnum>,
+    device_po_map: &HashMap<String, DevicePo>,
+    dto: BroadcastCommandDto,
+    device_to_mqtt_tx: &mpsc::Sender<DeviceToMqttEnum>,
+) {
+    let count = device_enum_map
+        .iter()
+        .filter(|(_, device_ref)| matches!(device_ref, DeviceRefEnum::Audio(_)))
+        .filter(|(device_id, _)| match device_po_map.get(*device_id) {
+            Some(device_po) => dto.filter.is_match(&device_po.device_type, &device_po.room),
+            None => false,
+        })
+        .count();
+    let reply = CommandReplyDto::new(dto.session_id, "reload_files".to_string());
+    if count == 0 {
+        send_reply(device_to_mqtt_tx, CommandReplyDto { msg: "success, 0 devices".to_string(), ..reply });
+        return;
+    }
+
+    let device_to_mqtt_tx = device_to_mqtt_tx.clone();
+    thread::spawn(move || {
+        let reply = match FileController::get().update() {
+            Ok(_) => CommandReplyDto { msg: format!("success, {} devices", count), ..reply },
+            Err(e) => {
+                error!(LOG_TAG, "reload files error, error msg: {}", e);
+                CommandReplyDto { code: REPLY_CODE_ERROR, msg: e.msg, ..reply }
+            }
+        };
+        send_reply(&device_to_mqtt_tx, reply);
+    });
+}
+
+/// local commands have no one waiting for the reply, only the result is logged
+fn log_local_reply(reply: &CommandReplyDto) {
+    if reply.code == REPLY_CODE_OK {
+        debug!(LOG_TAG, "local command done, session_id: {}, device_id: {:?}", reply.session_id, reply.device_id);
+    } else {
+        warn!(
+            LOG_TAG,
+            "local command failed, session_id: {}, device_id: {:?}, code: {}, error msg: {}",
+            reply.session_id, reply.device_id, reply.code, reply.msg
+        );
+    }
+}
+
+/// send command result back to mqtt client
+fn send_reply(device_to_mqtt_tx: &mpsc::Sender<DeviceToMqttEnum>, reply: CommandReplyDto) {
+    if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::CommandReply(reply)) {
+        error!(LOG_TAG, "cannot send command reply to mqtt client, error msg: {}", e);
+    }
+}
@@ -6,11 +6,13 @@ use std::{
 
 use crate::entity::dto::{
     device_report_dto::DeviceReportDto, mqtt_dto::DeviceToMqttEnum,
-    server_state_dto::ServerStateDto,
+    server_state_dto::{ServerStateDto, ServerSummaryDto},
 };
 
+use super::super::driver_registry::DriverRegistry;
 use super::super::entity::device_po::DevicePo;
 use crate::entity::dto::device_meta_info_dto::DeviceMetaInfoDto;
+use crate::common::{error::DriverError, supervisor::{supervise, Supervisor}};
 use crate::{debug, error, info, trace, warn};
 
 const LOG_TAG: &'static str = "device_manager_threads";
@@ -20,36 +22,55 @@ const LOG_TAG: &'static str = "device_manager_threads";
 pub fn heartbeating_thread(
     beat_interval_millis: u64,
     device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
-    device_config_map: HashMap<String, DevicePo>,
+    device_config_map: Arc<Mutex<HashMap<String, DevicePo>>>,
     device_to_mqtt_tx: mpsc::Sender<DeviceToMqttEnum>,
+    stop_rx: mpsc::Receiver<()>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
         info!(LOG_TAG, "heartbeating thread starting");
-        loop {
-            // 1. make device report message
-            let mut report_dto_map: HashMap<String, DeviceReportDto> = HashMap::new();
-            {
-                let map_guard = device_info_map.lock().unwrap();
-                for (device_id, device_info) in map_guard.iter() {
-                    let report_dto = DeviceReportDto::from_device_meta_info(device_info);
-                    report_dto_map.insert(device_id.clone(), report_dto);
+        // restart on panic, exit when stopped or mqtt channel is closed
+        supervise("heartbeating_thread", |_| {}, || -> Result<(), DriverError> {
+            loop {
+                // 1. make device report message
+                let mut report_dto_map: HashMap<String, DeviceReportDto> = HashMap::new();
+                let workers = Supervisor::get().get_worker_status();
+                let summary;
+                {
+                    let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+                    for (device_id, device_info) in map_guard.iter() {
+                        let report_dto = DeviceReportDto::from_device_meta_info(device_info);
+                        report_dto_map.insert(device_id.clone(), report_dto);
+                    }
+                    summary = ServerSummaryDto::from_state(&map_guard, &workers);
                 }
-            }
 
-            // 2. make server state, and send heartbeating message
-            let server_state = ServerStateDto {
-                device_config: device_config_map.clone(),
-                device_status: report_dto_map,
-            };
-            info!(
-                LOG_TAG,
-                "heartbeating thread: send server state, msg len: {}",
-                server_state.device_status.len()
-            );
-            device_to_mqtt_tx.send(DeviceToMqttEnum::ServerState(server_state)).expect("send server state failed");
+                // 2. make server state, and send heartbeating message
+                let server_state = ServerStateDto {
+                    device_config: device_config_map.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+                    device_status: report_dto_map,
+                    workers,
+                    summary,
+                    drivers: DriverRegistry::get().get_capabilities(),
+                };
+                info!(
+                    LOG_TAG,
+                    "heartbeating thread: send server state, msg len: {}",
+                    server_state.device_status.len()
+                );
+                if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::ServerState(server_state)) {
+                    warn!(LOG_TAG, "heartbeating thread exiting: send server state failed, error msg: {}", e);
+                    return Ok(());
+                }
 
-            // 3. sleep for beat_interval
-            thread::sleep(std::time::Duration::from_millis(beat_interval_millis));
-        }
+                // 3. sleep for beat_interval, quit if stop signal is received
+                match stop_rx.recv_timeout(std::time::Duration::from_millis(beat_interval_millis)) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    _ => {
+                        info!(LOG_TAG, "heartbeating thread exiting: stopped");
+                        return Ok(());
+                    }
+                }
+            }
+        })
     })
 }
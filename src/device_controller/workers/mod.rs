@@ -1,3 +1,11 @@
 pub mod reporting_thread;
 pub mod device_thread;
-pub mod heartbeating_thread;
\ No newline at end of file
+pub mod heartbeating_thread;
+pub mod device_reloader;
+pub mod device_shutdown;
+pub mod device_restore;
+pub mod state_snapshot_thread;
+pub mod device_scheduler;
+pub mod scheduler_thread;
+pub mod scene_thread;
+pub mod rule_engine;
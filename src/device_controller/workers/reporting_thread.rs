@@ -3,66 +3,374 @@ use std::{
     collections::HashMap,
     sync::{mpsc, Arc, Mutex},
     thread,
+    time::Duration,
 };
 
 use crate::entity::dto::{
     device_meta_info_dto::{DeviceMetaInfoDto, DeviceStatusEnum},
-    device_state_dto::StateToDeviceControllerDto,
-    mqtt_dto::DeviceToMqttEnum,
+    device_report_dto::DeviceReportDto,
+    device_state_dto::{StateDtoEnum, StateToDeviceControllerDto},
+    input_event_dto::{InputEventDto, InputEventEnum},
+    mqtt_dto::{DeviceToMqttEnum, MqttToDeviceEnum},
 };
 
+use crate::entity::po::device_config_po::RestorePolicyEnum;
+
+use super::super::entity::state_snapshot_po::StateSnapshotPo;
+use super::rule_engine::make_rule_commands;
+use super::state_snapshot_thread::SnapshotThreadCommandEnum;
+use crate::common::{error::DriverError, setting::Settings, supervisor::supervise};
+use crate::util::time::get_timestamp_millis;
 use crate::{debug, error, info, trace, warn};
 
 const LOG_TAG: &'static str = "reporting_thread";
+// interval of checking stale devices
+const STALE_CHECK_INTERVAL_MILLIS: u64 = 1000;
+// error message prefix of the devices under a failed master device
+const MASTER_ERROR_PREFIX: &str = "master device error";
 
 /// upward reporting thread
-/// used to report device state to upward controllers
+/// used to report device state to upward controllers, and send local rules and input events of input devices into device thread
 /// the thread using tokio runtime because mqtt client is async
 pub fn reporting_thread(
     state_report_rx: mpsc::Receiver<StateToDeviceControllerDto>,
     device_to_mqtt_tx: mpsc::Sender<DeviceToMqttEnum>,
     device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    snapshot_tx: mpsc::Sender<SnapshotThreadCommandEnum>,
+    device_command_tx: mpsc::Sender<MqttToDeviceEnum>,
 ) -> thread::JoinHandle<()> {
-    thread::spawn(move || loop {
-        info!(LOG_TAG, "waiting for device reporting message");
-        let message = state_report_rx.recv();
-        match message {
-            Ok(dto) => {
-                info!(LOG_TAG, "report message to mqtt: {:?}", &dto);
-                let device_id = dto.device_id.clone();
-                // 1 update device state and mark device status to "active"
-                {
-                    let mut map_guard = device_info_map.lock().unwrap();
-                    if let Some(device_info) = map_guard.borrow_mut().get_mut(device_id.as_str()) {
-                        device_info.state = dto.status.state.clone();
-                        // conditionally update when data is not none
-                        if !dto.status.error_msg.is_none() {
-                            device_info.error_msg = dto.status.error_msg.clone();
+    thread::spawn(move || {
+        let server_id = Settings::get().server.server_id.clone();
+        // restart on panic, exit when any channel is closed
+        supervise("reporting_thread", |_| {}, || -> Result<(), DriverError> {
+            // device class of reported devices, used when publishing offline transition
+            let mut device_class_map: HashMap<String, String> = HashMap::new();
+            let mut last_check = get_timestamp_millis();
+            loop {
+                trace!(LOG_TAG, "waiting for device reporting message");
+                let message = state_report_rx.recv_timeout(Duration::from_millis(STALE_CHECK_INTERVAL_MILLIS));
+                match message {
+                    Ok(mut dto) => {
+                        // every report carries the time it is received
+                        if dto.status.last_update.is_none() {
+                            dto.status.last_update = Some(get_timestamp_millis());
+                        }
+                        info!(LOG_TAG, "report message to mqtt: {:?}", &dto);
+                        let device_id = dto.device_id.clone();
+                        device_class_map.insert(device_id.clone(), dto.device_class.clone());
+                        // 1 evaluate local rules and input event against the last known state, update device state and mark device status to "active"
+                        let (rule_commands, input_event, cascade_list) = {
+                            let mut map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+                            let rule_commands = match map_guard.get(device_id.as_str()) {
+                                Some(device_info) if !device_info.rules.is_empty() => {
+                                    make_rule_commands(&map_guard, device_info, &dto.status.state, &server_id)
+                                }
+                                _ => Vec::new(),
+                            };
+                            let input_event = map_guard
+                                .get(device_id.as_str())
+                                .and_then(|device_info| InputEventEnum::from_state(&device_info.state, &dto.status.state));
+                            let mut was_active = false;
+                            if let Some(device_info) = map_guard.borrow_mut().get_mut(device_id.as_str()) {
+                                was_active = device_info.device_status == DeviceStatusEnum::ACTIVE;
+                                // error and recovered reports carry no state, keep the last known state
+                                if !matches!(dto.status.state, StateDtoEnum::Empty) {
+                                    device_info.state = dto.status.state.clone();
+                                    // save state for restoring after restarting, stopped snapshot thread is ignored
+                                    if device_info.restore_policy == RestorePolicyEnum::Last {
+                                        let _ = snapshot_tx.send(SnapshotThreadCommandEnum::Save(StateSnapshotPo {
+                                            device_id: device_id.clone(),
+                                            device_type: device_info.device_type.clone(),
+                                            state: serde_json::to_value(&dto.status.state).unwrap_or_default(),
+                                            update_time: get_timestamp_millis(),
+                                        }));
+                                    }
+                                }
+                                // active report clears the error, error report updates the error when data is not none
+                                if dto.status.active || !dto.status.error_msg.is_none() {
+                                    device_info.error_msg = dto.status.error_msg.clone();
+                                }
+                                if dto.status.active || !dto.status.error_timestamp.is_none() {
+                                    device_info.error_timestamp = dto.status.error_timestamp.clone();
+                                }
+                                if !dto.status.last_update.is_none() {
+                                    device_info.last_update = dto.status.last_update.clone();
+                                }
+                                // make device status, according to device reporting dto
+                                if dto.status.active == true {
+                                    if device_info.device_status == DeviceStatusEnum::OFFLINE {
+                                        info!(LOG_TAG, "device is back online, device_id: {}", &device_id);
+                                    }
+                                    device_info.device_status = DeviceStatusEnum::ACTIVE;
+                                } else {
+                                    device_info.device_status = DeviceStatusEnum::ERROR;
+                                }
+                            }
+                            // errors of master device are cascaded to the devices under it, and cleared on recovery
+                            let cascade_list = match (&dto.status.error_msg, dto.status.active) {
+                                (Some(error_msg), false) => cascade_master_error(
+                                    &mut map_guard,
+                                    &device_class_map,
+                                    &device_id,
+                                    error_msg,
+                                    dto.status.error_timestamp,
+                                ),
+                                (_, true) if !was_active => clear_master_error(&mut map_guard, &device_class_map, &device_id),
+                                _ => Vec::new(),
+                            };
+                            (rule_commands, input_event, cascade_list)
+                        };
+                        // 2 fire local rules and dispatch input event to sequence detectors before reporting, stopped device thread is ignored
+                        for command in rule_commands {
+                            info!(LOG_TAG, "fire local rule, device_id: {}, command: {:?}", &device_id, &command);
+                            let _ = device_command_tx.send(MqttToDeviceEnum::LocalCommand(command));
                         }
-                        if !dto.status.error_timestamp.is_none() {
-                            device_info.error_timestamp = dto.status.error_timestamp.clone();
+                        if let Some(event) = input_event {
+                            let _ = device_command_tx.send(MqttToDeviceEnum::InputEvent(InputEventDto {
+                                device_id: device_id.clone(),
+                                event,
+                            }));
                         }
-                        if !dto.status.last_update.is_none() {
-                            device_info.last_update = dto.status.last_update.clone();
+                        // 3 send out mqtt message, then the state of the devices under it
+                        if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::DeviceState(dto)) {
+                            warn!(LOG_TAG, "report thread exiting: send mqtt message error, msg: {}", e);
+                            return Ok(());
                         }
-                        // make device status, according to device reporting dto
-                        if dto.status.active == true {
-                            device_info.device_status = DeviceStatusEnum::ACTIVE;
-                        } else {
-                            device_info.device_status = DeviceStatusEnum::ERROR;
+                        for dto in cascade_list {
+                            info!(LOG_TAG, "master device status cascaded, device_id: {}, msg: {:?}", &dto.device_id, &dto.status.error_msg);
+                            if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::DeviceState(dto)) {
+                                warn!(LOG_TAG, "report thread exiting: send mqtt message error, msg: {}", e);
+                                return Ok(());
+                            }
                         }
                     }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(e) => {
+                        warn!(
+                            LOG_TAG,
+                            "report thread exiting: device report channel error, msg: {}", e
+                        );
+                        return Ok(());
+                    }
+                }
+                // 4 mark stale devices offline, at most once per check interval
+                let now = get_timestamp_millis();
+                if now.saturating_sub(last_check) < STALE_CHECK_INTERVAL_MILLIS {
+                    continue;
+                }
+                last_check = now;
+                let offline_list = mark_stale_devices(&mut device_info_map.lock().unwrap_or_else(|e| e.into_inner()), &device_class_map, now);
+                for dto in offline_list {
+                    warn!(LOG_TAG, "device is offline, device_id: {}, msg: {:?}", &dto.device_id, &dto.status.error_msg);
+                    if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::DeviceState(dto)) {
+                        warn!(LOG_TAG, "report thread exiting: send mqtt message error, msg: {}", e);
+                        return Ok(());
+                    }
                 }
-                // 2 send out mqtt message
-                device_to_mqtt_tx.send(DeviceToMqttEnum::DeviceState(dto)).expect("send mqtt message error");
-            }
-            Err(e) => {
-                warn!(
-                    LOG_TAG,
-                    "report thread exiting: device report channel error, msg: {}", e
-                );
-                return;
             }
-        }
+        })
     })
 }
+
+/// whether the device is under the master device, directly or through other master devices
+fn is_under(device_info_map: &HashMap<String, DeviceMetaInfoDto>, device_id: &str, master_device_id: &str) -> bool {
+    let mut current = device_id;
+    // bounded by map size in case of misconfigured cycle
+    for _ in 0..device_info_map.len() {
+        match device_info_map.get(current).and_then(|device_info| device_info.master_device_id.as_deref()) {
+            Some(master) if master == master_device_id => return true,
+            Some(master) => current = master,
+            None => return false,
+        }
+    }
+    false
+}
+
+fn make_cascade_report(device_info: &DeviceMetaInfoDto, device_class_map: &HashMap<String, String>) -> StateToDeviceControllerDto {
+    StateToDeviceControllerDto {
+        device_id: device_info.device_id.clone(),
+        device_class: device_class_map.get(&device_info.device_id).cloned().unwrap_or_default(),
+        device_type: device_info.device_type.clone(),
+        status: DeviceReportDto {
+            active: device_info.device_status == DeviceStatusEnum::ACTIVE,
+            error_msg: device_info.error_msg.clone(),
+            error_timestamp: device_info.error_timestamp,
+            last_update: device_info.last_update,
+            state: device_info.state.clone(),
+        },
+    }
+}
+
+/// mark the devices under the failed master device as error
+/// the devices already failed by their own error are kept
+/// return the error reports to publish
+fn cascade_master_error(
+    device_info_map: &mut HashMap<String, DeviceMetaInfoDto>,
+    device_class_map: &HashMap<String, String>,
+    master_device_id: &str,
+    error_msg: &str,
+    error_timestamp: Option<u64>,
+) -> Vec<StateToDeviceControllerDto> {
+    let slave_list: Vec<String> = device_info_map
+        .keys()
+        .filter(|device_id| is_under(device_info_map, device_id, master_device_id))
+        .cloned()
+        .collect();
+    let mut report_list = Vec::new();
+    for device_id in slave_list {
+        let Some(device_info) = device_info_map.get_mut(&device_id) else {
+            continue;
+        };
+        let is_cascaded = device_info.error_msg.as_deref().is_some_and(|msg| msg.starts_with(MASTER_ERROR_PREFIX));
+        if device_info.device_status == DeviceStatusEnum::ERROR && !is_cascaded {
+            continue;
+        }
+        device_info.device_status = DeviceStatusEnum::ERROR;
+        device_info.error_msg = Some(format!("{}: {}: {}", MASTER_ERROR_PREFIX, master_device_id, error_msg));
+        device_info.error_timestamp = error_timestamp;
+        report_list.push(make_cascade_report(device_info, device_class_map));
+    }
+    report_list
+}
+
+/// clear the errors cascaded from the recovered master device
+/// return the recovered reports to publish, the last known state is kept
+fn clear_master_error(
+    device_info_map: &mut HashMap<String, DeviceMetaInfoDto>,
+    device_class_map: &HashMap<String, String>,
+    master_device_id: &str,
+) -> Vec<StateToDeviceControllerDto> {
+    let prefix = format!("{}: {}: ", MASTER_ERROR_PREFIX, master_device_id);
+    let mut report_list = Vec::new();
+    for device_info in device_info_map.values_mut() {
+        if device_info.device_status != DeviceStatusEnum::ERROR
+            || !device_info.error_msg.as_deref().is_some_and(|msg| msg.starts_with(&prefix))
+        {
+            continue;
+        }
+        device_info.device_status = DeviceStatusEnum::ACTIVE;
+        device_info.error_msg = None;
+        device_info.error_timestamp = None;
+        report_list.push(make_cascade_report(device_info, device_class_map));
+    }
+    report_list
+}
+
+/// mark active devices without any report in their staleness window as offline
+/// return the offline transitions to publish, the last known state is kept
+fn mark_stale_devices(
+    device_info_map: &mut HashMap<String, DeviceMetaInfoDto>,
+    device_class_map: &HashMap<String, String>,
+    now: u64,
+) -> Vec<StateToDeviceControllerDto> {
+    let mut offline_list = Vec::new();
+    for device_info in device_info_map.values_mut() {
+        if device_info.device_status != DeviceStatusEnum::ACTIVE {
+            continue;
+        }
+        let (Some(stale_timeout), Some(last_update)) = (device_info.stale_timeout, device_info.last_update) else {
+            continue;
+        };
+        if now.saturating_sub(last_update) <= stale_timeout {
+            continue;
+        }
+        device_info.device_status = DeviceStatusEnum::OFFLINE;
+        device_info.error_msg = Some(format!("device offline: no report in {} ms", stale_timeout));
+        device_info.error_timestamp = Some(now);
+        offline_list.push(StateToDeviceControllerDto {
+            device_id: device_info.device_id.clone(),
+            device_class: device_class_map.get(&device_info.device_id).cloned().unwrap_or_default(),
+            device_type: device_info.device_type.clone(),
+            status: DeviceReportDto {
+                active: false,
+                error_msg: device_info.error_msg.clone(),
+                error_timestamp: device_info.error_timestamp,
+                last_update: device_info.last_update,
+                state: device_info.state.clone(),
+            },
+        });
+    }
+    offline_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_device_info(device_id: &str, status: DeviceStatusEnum, stale_timeout: Option<u64>, last_update: Option<u64>) -> DeviceMetaInfoDto {
+        DeviceMetaInfoDto {
+            device_id: device_id.to_string(),
+            master_device_id: None,
+            device_type: "modbus_di_controller".to_string(),
+            config: json!({}),
+            device_status: status,
+            stale_timeout,
+            restore_policy: RestorePolicyEnum::Upstream,
+            rules: Vec::new(),
+            error_msg: None,
+            error_timestamp: None,
+            last_update,
+            state: StateDtoEnum::Empty,
+        }
+    }
+
+    #[test]
+    fn test_mark_stale_devices() {
+        let mut device_info_map = HashMap::new();
+        for device_info in [
+            make_device_info("stale", DeviceStatusEnum::ACTIVE, Some(5000), Some(10_000)),
+            make_device_info("fresh", DeviceStatusEnum::ACTIVE, Some(5000), Some(12_000)),
+            make_device_info("no_window", DeviceStatusEnum::ACTIVE, None, Some(10_000)),
+            make_device_info("error", DeviceStatusEnum::ERROR, Some(5000), Some(10_000)),
+        ] {
+            device_info_map.insert(device_info.device_id.clone(), device_info);
+        }
+        let device_class_map = HashMap::from([("stale".to_string(), "operable".to_string())]);
+
+        let offline_list = mark_stale_devices(&mut device_info_map, &device_class_map, 16_000);
+        assert_eq!(offline_list.len(), 1);
+        assert_eq!(offline_list[0].device_id, "stale");
+        assert_eq!(offline_list[0].device_class, "operable");
+        assert!(!offline_list[0].status.active);
+        assert_eq!(device_info_map["stale"].device_status, DeviceStatusEnum::OFFLINE);
+        assert_eq!(device_info_map["fresh"].device_status, DeviceStatusEnum::ACTIVE);
+        assert_eq!(device_info_map["no_window"].device_status, DeviceStatusEnum::ACTIVE);
+        assert_eq!(device_info_map["error"].device_status, DeviceStatusEnum::ERROR);
+
+        // offline transition is published only once
+        assert!(mark_stale_devices(&mut device_info_map, &device_class_map, 17_000).is_empty());
+    }
+
+    #[test]
+    fn test_cascade_master_error() {
+        let mut device_info_map = HashMap::new();
+        for (device_id, master_device_id, status) in [
+            ("bus", None, DeviceStatusEnum::ERROR),
+            ("controller", Some("bus"), DeviceStatusEnum::ACTIVE),
+            ("port", Some("controller"), DeviceStatusEnum::ACTIVE),
+            ("failed_port", Some("controller"), DeviceStatusEnum::ERROR),
+            ("other", None, DeviceStatusEnum::ACTIVE),
+        ] {
+            let mut device_info = make_device_info(device_id, status, None, None);
+            device_info.master_device_id = master_device_id.map(|s| s.to_string());
+            device_info_map.insert(device_id.to_string(), device_info);
+        }
+        device_info_map.get_mut("failed_port").unwrap().error_msg = Some("write failed".to_string());
+
+        let mut report_list = cascade_master_error(&mut device_info_map, &HashMap::new(), "bus", "cannot open port", Some(100));
+        report_list.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        assert_eq!(report_list.iter().map(|dto| dto.device_id.as_str()).collect::<Vec<_>>(), vec!["controller", "port"]);
+        assert!(!report_list[1].status.active);
+        assert_eq!(device_info_map["port"].error_msg.as_deref(), Some("master device error: bus: cannot open port"));
+        assert_eq!(device_info_map["port"].error_timestamp, Some(100));
+        assert_eq!(device_info_map["failed_port"].error_msg.as_deref(), Some("write failed"));
+        assert_eq!(device_info_map["other"].device_status, DeviceStatusEnum::ACTIVE);
+
+        let report_list = clear_master_error(&mut device_info_map, &HashMap::new(), "bus");
+        assert_eq!(report_list.len(), 2);
+        assert!(report_list.iter().all(|dto| dto.status.active && dto.status.error_msg.is_none()));
+        assert_eq!(device_info_map["port"].device_status, DeviceStatusEnum::ACTIVE);
+        assert_eq!(device_info_map["failed_port"].device_status, DeviceStatusEnum::ERROR);
+    }
+}
@@ -0,0 +1,212 @@
+//! local rules linking input devices to output devices
+//! rules are read from "rules" of input device config and evaluated on the reports in reporting thread,
+//! the triggered commands are sent into device thread directly as local commands without reply,
+//! so basic interactions keep working without flow server
+
+use std::collections::HashMap;
+
+use super::super::driver_registry::DriverRegistry;
+use crate::entity::dto::{
+    device_command_dto::DeviceCommandDto,
+    device_meta_info_dto::DeviceMetaInfoDto,
+    device_state_dto::StateDtoEnum,
+    input_event_dto::InputEventEnum,
+};
+use crate::entity::po::device_config_po::{RulePo, RuleTriggerEnum};
+use crate::{debug, warn};
+
+const LOG_TAG: &str = "rule_engine";
+
+/// whether the report triggers the rule
+/// edges of di need the last known state, the first report after starting triggers nothing
+pub fn is_triggered(rule: &RulePo, previous: &StateDtoEnum, current: &StateDtoEnum) -> bool {
+    match (rule.trigger, InputEventEnum::from_state(previous, current)) {
+        (RuleTriggerEnum::Rise | RuleTriggerEnum::Change, Some(InputEventEnum::Rise)) => true,
+        (RuleTriggerEnum::Fall | RuleTriggerEnum::Change, Some(InputEventEnum::Fall)) => true,
+        (RuleTriggerEnum::Press, Some(InputEventEnum::Press(pressed))) => rule.button.is_none_or(|button| button == pressed),
+        _ => false,
+    }
+}
+
+/// make commands of the rules triggered by the report of device, before the state of device info is updated
+/// invalid rules are logged and skipped
+pub fn make_rule_commands(
+    device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+    device_info: &DeviceMetaInfoDto,
+    current: &StateDtoEnum,
+    server_id: &str,
+) -> Vec<DeviceCommandDto> {
+    let mut ret = Vec::new();
+    for rule in device_info.rules.iter() {
+        if !is_triggered(rule, &device_info.state, current) {
+            continue;
+        }
+        match make_device_command(device_info_map, rule, &device_info.device_id, server_id) {
+            Ok(command) => {
+                debug!(LOG_TAG, "rule triggered, device_id: {}, rule: {:?}", &device_info.device_id, rule);
+                ret.push(command);
+            }
+            Err(e) => warn!(LOG_TAG, "skip rule of device: {}, error msg: {}", &device_info.device_id, e),
+        }
+    }
+    ret
+}
+
+fn make_device_command(
+    device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+    rule: &RulePo,
+    device_id: &str,
+    server_id: &str,
+) -> Result<DeviceCommandDto, String> {
+    let device_type = device_info_map
+        .get(&rule.target)
+        .map(|device_info| device_info.device_type.clone())
+        .ok_or(format!("cannot find device: {}", rule.target))?;
+    if !DriverRegistry::get().is_commandable(&device_type) {
+        return Err(format!("device does not accept command: {}", rule.target));
+    }
+    let params = DriverRegistry::get().parse_command(&device_type, &rule.action, rule.param.clone())
+        .map_err(|e| format!("invalid {} command `{}`: {e}", device_type, rule.action))?;
+    Ok(DeviceCommandDto {
+        server_id: server_id.to_string(),
+        device_id: rule.target.clone(),
+        device_type,
+        session_id: format!("rule-{}", device_id),
+        action: rule.action.clone(),
+        params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_controller::device_factory::DeviceInstanceFactory;
+    use crate::device_controller::device_info_maker_helper;
+    use crate::device_controller::entity::{device_enum::DeviceRefEnum, device_po::DevicePo};
+    use crate::driver::modbus::traits::ModbusListener;
+    use crate::entity::dto::device_meta_info_dto::DeviceStatusEnum;
+    use crate::entity::dto::device_state_dto::{DiStateDto, RemoteStateDto};
+    use crate::entity::po::device_config_po::RestorePolicyEnum;
+    use serde_json::{json, Value};
+    use std::sync::{mpsc, Arc, Mutex};
+
+    fn make_device_info(device_id: &str, device_type: &str, config: Value, state: StateDtoEnum) -> DeviceMetaInfoDto {
+        DeviceMetaInfoDto {
+            device_id: device_id.to_string(),
+            master_device_id: None,
+            device_type: device_type.to_string(),
+            rules: RulePo::from_config(device_id, &config).unwrap(),
+            config,
+            device_status: DeviceStatusEnum::ACTIVE,
+            stale_timeout: None,
+            restore_policy: RestorePolicyEnum::Upstream,
+            error_msg: None,
+            error_timestamp: None,
+            last_update: None,
+            state,
+        }
+    }
+
+    fn di(on: bool) -> StateDtoEnum {
+        StateDtoEnum::Di(DiStateDto { on })
+    }
+
+    #[test]
+    fn test_is_triggered() {
+        let rules = RulePo::from_config("di-1", &json!({"rules": [
+            {"trigger": "rise", "target": "do-1", "action": "on"},
+            {"trigger": "fall", "target": "do-1", "action": "off"},
+            {"trigger": "change", "target": "do-1", "action": "toggle"},
+            {"trigger": "press", "button": 3, "target": "do-1", "action": "on"},
+        ]}))
+        .unwrap();
+        let (rise, fall, change, press) = (&rules[0], &rules[1], &rules[2], &rules[3]);
+        assert!(is_triggered(rise, &di(false), &di(true)));
+        assert!(!is_triggered(rise, &di(true), &di(true)));
+        assert!(!is_triggered(rise, &StateDtoEnum::Empty, &di(true)));
+        assert!(is_triggered(fall, &di(true), &di(false)));
+        assert!(!is_triggered(fall, &di(false), &di(true)));
+        assert!(is_triggered(change, &di(true), &di(false)));
+        assert!(!is_triggered(change, &di(false), &di(false)));
+
+        let pressed = |button| StateDtoEnum::Remote(RemoteStateDto { pressed: button });
+        assert!(is_triggered(press, &pressed(3), &pressed(3)));
+        assert!(!is_triggered(press, &StateDtoEnum::Empty, &pressed(2)));
+        assert!(!is_triggered(press, &pressed(3), &StateDtoEnum::Empty));
+        assert!(!is_triggered(rise, &pressed(3), &pressed(3)));
+    }
+
+    #[test]
+    fn test_make_rule_commands() {
+        let input = make_device_info(
+            "di-1",
+            "modbus_di_port",
+            json!({"rules": [
+                {"trigger": "rise", "target": "do-1", "action": "pulse", "param": {"duration": 500}},
+                {"trigger": "rise", "target": "missing", "action": "on"},
+                {"trigger": "rise", "target": "do-1", "action": "jump"},
+                {"trigger": "fall", "target": "do-1", "action": "off"},
+            ]}),
+            di(false),
+        );
+        let mut map = HashMap::new();
+        map.insert("do-1".to_string(), make_device_info("do-1", "modbus_do_port", json!({}), StateDtoEnum::Empty));
+
+        let commands = make_rule_commands(&map, &input, &di(true), "server-1");
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].device_id, "do-1");
+        assert_eq!(commands[0].device_type, "modbus_do_port");
+        assert_eq!(commands[0].action, "pulse");
+        assert_eq!(commands[0].session_id, "rule-di-1");
+        assert_eq!(commands[0].server_id, "server-1");
+
+        assert!(make_rule_commands(&map, &input, &di(false), "server-1").is_empty());
+    }
+
+    #[test]
+    fn test_rule_of_di_port() {
+        let device_po_list: Vec<DevicePo> = [
+            ("bus_1", "modbus_bus", json!({"serial_port": "/dev/ttyUSB0"})),
+            ("di_controller_1", "modbus_di_controller", json!({"master_device_id": "bus_1", "unit": 1, "num": 8})),
+            ("di_1", "modbus_di_port", json!({"master_device_id": "di_controller_1", "address": 0, "rules": [
+                {"trigger": "fall", "target": "do_1", "action": "off"},
+            ]})),
+            ("do_controller_1", "modbus_do_controller", json!({"master_device_id": "bus_1", "unit": 2, "num": 8})),
+            ("do_1", "modbus_do_port", json!({"master_device_id": "do_controller_1", "address": 0})),
+        ]
+        .into_iter()
+        .map(|(device_id, device_type, config)| DevicePo {
+            device_id: device_id.to_string(),
+            device_class: "".to_string(),
+            device_type: device_type.to_string(),
+            name: "".to_string(),
+            description: "".to_string(),
+            room: "".to_string(),
+            config,
+        })
+        .collect();
+        let device_info_map = Arc::new(Mutex::new(device_info_maker_helper::make_device_info(device_po_list.clone()).unwrap()));
+        let (tx, rx) = mpsc::channel();
+        let mut device_factory = DeviceInstanceFactory::new(tx);
+        device_factory.make_devices(device_info_map.clone(), device_po_list).unwrap();
+        assert!(device_factory.take_failed_devices().is_empty());
+        let device_map = device_factory.get_device_map();
+        let Some(DeviceRefEnum::ModbusDiController(di_controller_ref)) = device_map.get("di_controller_1") else {
+            panic!("di controller is not created");
+        };
+
+        // reports of di port are evaluated against the last known state, as in reporting thread
+        let mut map = device_info_map.lock().unwrap().clone();
+        let mut commands = Vec::new();
+        for on in [true, false] {
+            di_controller_ref.lock().unwrap().notify_from_bus(0, vec![on; 8]).unwrap();
+            for dto in rx.try_iter().filter(|dto| dto.device_id == "di_1") {
+                commands.extend(make_rule_commands(&map, &map["di_1"], &dto.status.state, "server-1"));
+                map.get_mut("di_1").unwrap().state = dto.status.state;
+            }
+        }
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].device_id, "do_1");
+        assert_eq!(commands[0].action, "off");
+    }
+}
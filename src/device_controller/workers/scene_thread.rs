@@ -0,0 +1,353 @@
+//! scenes: named sets of device commands saved in sqlite
+//! scene commands are forwarded by device thread and handled in scene thread, so that sqlite is not accessed in device thread
+//! supported devices of capturing: modbus_do_port, dmx_bus, dmx_channel, audio (files playing)
+//! - scene_save: define a scene by device commands
+//! - scene_capture: make device commands from the current state in device info map
+//! - recall_scene: the device commands are resolved here and sent back to device thread,
+//!   applied in order as a non-atomic batch command, dmx commands fade if fade is given
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use serde_json::json;
+
+use super::super::{driver_registry::DriverRegistry, entity::scene_po::ScenePo, scene_dao::SceneDao};
+use crate::common::{error::DriverError, setting::Settings, supervisor::supervise};
+use crate::entity::dto::{
+    batch_command_dto::{BatchCommandDto, BatchCommandItemDto},
+    command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR, REPLY_CODE_PARAM_FAIL},
+    device_meta_info_dto::DeviceMetaInfoDto,
+    device_state_dto::StateDtoEnum,
+    mqtt_dto::{DeviceToMqttEnum, MqttToDeviceEnum},
+    scene_command_dto::{
+        RecallSceneParamDto, SceneCaptureParamDto, SceneCommandDto, SceneDto, SceneRecallDto, SceneRequestEnum,
+    },
+};
+use crate::file_controller::file_controller::FileController;
+use crate::util::time::get_timestamp_millis;
+use crate::{debug, error, info, warn};
+
+const LOG_TAG: &str = "scene_thread";
+
+pub enum SceneThreadCommandEnum {
+    // scene command forwarded by device thread
+    Request(SceneCommandDto),
+    // stop handling scene commands, the saved scenes are kept
+    Stop,
+}
+
+pub fn scene_thread(
+    scene_rx: mpsc::Receiver<SceneThreadCommandEnum>,
+    device_command_tx: mpsc::Sender<MqttToDeviceEnum>,
+    device_to_mqtt_tx: mpsc::Sender<DeviceToMqttEnum>,
+    device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dao = SceneDao::new();
+        if let Err(e) = rt.block_on(dao.ensure_table_exist()) {
+            error!(LOG_TAG, "cannot create scene table, scenes are not saved, error msg: {}", e);
+        }
+        let server_id = Settings::get().server.server_id.clone();
+
+        // restart on panic, exit when stopped or channel is closed
+        supervise("scene_thread", |_| {}, || -> Result<(), DriverError> {
+            loop {
+                match scene_rx.recv() {
+                    Ok(SceneThreadCommandEnum::Request(dto)) => {
+                        debug!(LOG_TAG, "got scene command, dto: {:?}", dto);
+                        let reply_tx = dto.reply_tx.clone();
+                        // the reply of recalling is made by device thread after applying the commands
+                        let Some(reply) = handle_scene_command(&rt, &dao, &device_info_map, &device_command_tx, &server_id, dto)
+                        else {
+                            continue;
+                        };
+                        match reply_tx {
+                            Some(reply_tx) => {
+                                let _ = reply_tx.send(reply);
+                            }
+                            None => {
+                                if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::CommandReply(reply)) {
+                                    error!(LOG_TAG, "cannot send scene command reply to mqtt client, error msg: {}", e);
+                                }
+                            }
+                        }
+                    }
+                    Ok(SceneThreadCommandEnum::Stop) | Err(_) => {
+                        info!(LOG_TAG, "scene thread exiting: stopped");
+                        return Ok(());
+                    }
+                }
+            }
+        })
+    })
+}
+
+/// handle scene command and make the reply, none if the recall commands are sent to device thread
+fn handle_scene_command(
+    rt: &tokio::runtime::Runtime,
+    dao: &SceneDao,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    device_command_tx: &mpsc::Sender<MqttToDeviceEnum>,
+    server_id: &str,
+    dto: SceneCommandDto,
+) -> Option<CommandReplyDto> {
+    let action = dto.request.action().as_str().to_string();
+    let mut reply = CommandReplyDto::new(dto.session_id.clone(), action.clone());
+    let result = match dto.request {
+        SceneRequestEnum::Save(param) => {
+            let scene = ScenePo {
+                scene_id: param.scene_id,
+                name: param.name,
+                targets: param.targets,
+                update_time: get_timestamp_millis(),
+            };
+            validate_targets(device_info_map, &scene.targets).and_then(|_| save_scene(rt, dao, scene))
+        }
+        SceneRequestEnum::Capture(param) => capture_scene(device_info_map, param).and_then(|scene| save_scene(rt, dao, scene)),
+        SceneRequestEnum::Delete(param) => match rt.block_on(dao.delete(param.scene_id.clone())) {
+            Ok(true) => Ok(Vec::new()),
+            Ok(false) => Err((REPLY_CODE_PARAM_FAIL, "cannot find scene".to_string())),
+            Err(e) => Err((REPLY_CODE_ERROR, e.to_string())),
+        },
+        SceneRequestEnum::List => rt
+            .block_on(dao.get_all())
+            .map(|scene_list| scene_list.into_iter().map(to_scene_dto).collect())
+            .map_err(|e| (REPLY_CODE_ERROR, e.to_string())),
+        SceneRequestEnum::Recall(param) => {
+            let reply_tx = dto.reply_tx;
+            let sent = make_recall_batch(rt, dao, device_info_map, server_id, &dto.session_id, param).and_then(|batch| {
+                device_command_tx
+                    .send(MqttToDeviceEnum::SceneRecall(SceneRecallDto { batch, reply_tx }))
+                    .map_err(|e| (REPLY_CODE_ERROR, format!("cannot send recall commands to device thread: {}", e)))
+            });
+            match sent {
+                Ok(()) => return None,
+                Err(e) => Err(e),
+            }
+        }
+    };
+    match result {
+        Ok(scene_list) => {
+            if !scene_list.is_empty() {
+                reply.scenes = Some(scene_list);
+            }
+        }
+        Err((code, msg)) => {
+            error!(LOG_TAG, "{} failed, error msg: {}", action, msg);
+            reply.code = code;
+            reply.msg = msg;
+        }
+    }
+    Some(reply)
+}
+
+/// resolve the device commands of scene into a non-atomic batch command
+fn make_recall_batch(
+    rt: &tokio::runtime::Runtime,
+    dao: &SceneDao,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    server_id: &str,
+    session_id: &str,
+    param: RecallSceneParamDto,
+) -> Result<BatchCommandDto, (i32, String)> {
+    let scene = match rt.block_on(dao.get(param.scene_id.clone())) {
+        Ok(Some(scene)) => scene,
+        Ok(None) => return Err((REPLY_CODE_PARAM_FAIL, format!("cannot find scene: {}", param.scene_id))),
+        Err(e) => return Err((REPLY_CODE_ERROR, e.to_string())),
+    };
+    info!(LOG_TAG, "recall scene, scene_id: {}, fade: {:?}", scene.scene_id, param.fade);
+    let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+    let commands = scene
+        .targets
+        .into_iter()
+        .map(|target| with_fade(&map_guard, target, param.fade))
+        .collect();
+    Ok(BatchCommandDto {
+        server_id: server_id.to_string(),
+        session_id: session_id.to_string(),
+        atomic: false,
+        commands,
+    })
+}
+
+/// add fade to the param of dmx commands
+fn with_fade(
+    device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+    mut target: BatchCommandItemDto,
+    fade: Option<u64>,
+) -> BatchCommandItemDto {
+    let Some(fade) = fade else {
+        return target;
+    };
+    let device_type = device_info_map.get(&target.device_id).map(|device_info| device_info.device_type.as_str());
+    let is_dmx_set = matches!(
+        (device_type, target.action.as_str()),
+        (Some("dmx_bus"), "set_channels") | (Some("dmx_channel"), "set")
+    );
+    if is_dmx_set && target.param.is_object() {
+        target.param["fade"] = json!(fade);
+    }
+    target
+}
+
+/// every device exists, accepts command and the param is valid
+fn validate_targets(
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    targets: &[BatchCommandItemDto],
+) -> Result<(), (i32, String)> {
+    if targets.is_empty() {
+        return Err((REPLY_CODE_PARAM_FAIL, "scene has no target".to_string()));
+    }
+    let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+    for target in targets {
+        let device_type = match map_guard.get(&target.device_id) {
+            Some(device_info) => device_info.device_type.as_str(),
+            None => return Err((REPLY_CODE_PARAM_FAIL, format!("cannot find device: {}", target.device_id))),
+        };
+        if !DriverRegistry::get().is_commandable(device_type) {
+            return Err((REPLY_CODE_PARAM_FAIL, format!("device does not accept command: {}", target.device_id)));
+        }
+        if let Err(e) = DriverRegistry::get().parse_command(device_type, &target.action, target.param.clone()) {
+            return Err((
+                REPLY_CODE_PARAM_FAIL,
+                format!("invalid {} command `{}` of device {}: {e}", device_type, target.action, target.device_id),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// make scene from the current state of devices
+/// devices listed in param must support capturing, otherwise all devices supporting capturing are captured
+fn capture_scene(
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    param: SceneCaptureParamDto,
+) -> Result<ScenePo, (i32, String)> {
+    let map_guard = device_info_map.lock().unwrap_or_else(|e| e.into_inner());
+    let mut device_ids = param.device_ids.clone();
+    if device_ids.is_empty() {
+        device_ids = map_guard.keys().cloned().collect();
+        device_ids.sort();
+    }
+    let mut targets = Vec::new();
+    for device_id in device_ids.iter() {
+        let captured = map_guard.get(device_id).map(capture_device).unwrap_or_default();
+        if captured.is_empty() && !param.device_ids.is_empty() {
+            return Err((REPLY_CODE_PARAM_FAIL, format!("cannot capture state of device: {}", device_id)));
+        }
+        targets.extend(captured);
+    }
+    if targets.is_empty() {
+        return Err((REPLY_CODE_PARAM_FAIL, "no device state to capture".to_string()));
+    }
+    debug!(LOG_TAG, "scene captured, scene_id: {}, targets: {:?}", param.scene_id, targets);
+    Ok(ScenePo {
+        scene_id: param.scene_id,
+        name: param.name,
+        targets,
+        update_time: get_timestamp_millis(),
+    })
+}
+
+/// make device commands which set the device to its current state
+fn capture_device(device_info: &DeviceMetaInfoDto) -> Vec<BatchCommandItemDto> {
+    let make_item = |action: &str, param| BatchCommandItemDto {
+        device_id: device_info.device_id.clone(),
+        action: action.to_string(),
+        param,
+    };
+    match &device_info.state {
+        StateDtoEnum::Do(state) => vec![make_item(if state.on { "on" } else { "off" }, json!(null))],
+        StateDtoEnum::DmxBus(state) => vec![make_item("set_channels", json!({"offset": 0, "values": state.channel}))],
+        StateDtoEnum::Channel(state) => vec![make_item("set", json!({"channel": 0, "values": state.channels}))],
+        StateDtoEnum::Audio(state) => state
+            .stream
+            .iter()
+            .filter(|stream| stream.playing)
+            .filter_map(|stream| match FileController::get().get_hash_by_path(&stream.file_id) {
+                Some(hash) => Some(make_item("play", json!({ "hash": hash }))),
+                None => {
+                    warn!(LOG_TAG, "cannot capture audio file, file not found: {}", stream.file_id);
+                    None
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn save_scene(rt: &tokio::runtime::Runtime, dao: &SceneDao, scene: ScenePo) -> Result<Vec<SceneDto>, (i32, String)> {
+    let scene_dto = to_scene_dto(scene.clone());
+    rt.block_on(dao.save(scene)).map_err(|e| (REPLY_CODE_ERROR, e.to_string()))?;
+    info!(LOG_TAG, "scene saved, scene_id: {}, target count: {}", scene_dto.scene_id, scene_dto.targets.len());
+    Ok(vec![scene_dto])
+}
+
+fn to_scene_dto(scene: ScenePo) -> SceneDto {
+    SceneDto {
+        scene_id: scene.scene_id,
+        name: scene.name,
+        targets: scene.targets,
+        update_time: scene.update_time,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::dto::device_meta_info_dto::DeviceStatusEnum;
+    use crate::entity::dto::device_state_dto::{ChannelStateDto, DoStateDto};
+    use crate::entity::po::device_config_po::RestorePolicyEnum;
+    use serde_json::Value;
+
+    fn make_device_info(device_id: &str, device_type: &str, state: StateDtoEnum) -> DeviceMetaInfoDto {
+        DeviceMetaInfoDto {
+            device_id: device_id.to_string(),
+            master_device_id: None,
+            device_type: device_type.to_string(),
+            config: Value::Null,
+            device_status: DeviceStatusEnum::ACTIVE,
+            stale_timeout: None,
+            restore_policy: RestorePolicyEnum::Upstream,
+            rules: Vec::new(),
+            error_msg: None,
+            error_timestamp: None,
+            last_update: None,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_capture_scene() {
+        let mut map = HashMap::new();
+        map.insert("do-1".to_string(), make_device_info("do-1", "modbus_do_port", StateDtoEnum::Do(DoStateDto { on: true })));
+        map.insert(
+            "light-1".to_string(),
+            make_device_info("light-1", "dmx_channel", StateDtoEnum::Channel(ChannelStateDto { address: 1, channels: vec![255, 0] })),
+        );
+        map.insert("di-1".to_string(), make_device_info("di-1", "modbus_di_port", StateDtoEnum::Empty));
+        let device_info_map = Arc::new(Mutex::new(map));
+
+        let param = SceneCaptureParamDto {
+            scene_id: "open".to_string(),
+            name: "".to_string(),
+            device_ids: Vec::new(),
+        };
+        let scene = capture_scene(&device_info_map, param.clone()).unwrap();
+        assert_eq!(scene.targets.len(), 2);
+        assert_eq!(scene.targets[0].device_id, "do-1");
+        assert_eq!(scene.targets[0].action, "on");
+        assert_eq!(scene.targets[1].param, json!({"channel": 0, "values": [255, 0]}));
+
+        // listed device should support capturing
+        let param = SceneCaptureParamDto {
+            device_ids: vec!["di-1".to_string()],
+            ..param
+        };
+        assert!(capture_scene(&device_info_map, param).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! local scheduler, fire device commands later even if the flow server is not available
+//! schedules are saved in sqlite and loaded after restarting,
+//! the fired command is sent to device thread as a local command, the result is logged and no reply is published
+//! - one-shot schedule: fired once after delay or at the given time, then removed
+//! - cron schedule: fired repeatedly, cron expression is matched against local time (timezone in settings)
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::super::driver_registry::DriverRegistry;
+use super::super::entity::schedule_po::SchedulePo;
+use super::super::schedule_dao::ScheduleDao;
+use crate::common::{error::DriverError, setting::Settings, supervisor::supervise};
+use crate::entity::dto::{
+    command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR, REPLY_CODE_PARAM_FAIL},
+    device_command_dto::DeviceCommandDto,
+    device_meta_info_dto::DeviceMetaInfoDto,
+    mqtt_dto::{DeviceToMqttEnum, MqttToDeviceEnum},
+    schedule_command_dto::{ScheduleAddParamDto, ScheduleCommandDto, ScheduleDto, ScheduleRequestEnum},
+};
+use crate::util::{cron::{CronExpr, CronTimezone}, gen_id::generate_uuid, time::get_timestamp_millis};
+use crate::{debug, error, info, warn};
+
+const LOG_TAG: &str = "scheduler_thread";
+// the longest waiting time between checks, so that changing of system time is noticed
+const SCHEDULER_MAX_WAIT_MILLIS: u64 = 1000;
+
+pub enum SchedulerCommandEnum {
+    // schedule command from mqtt
+    Request(ScheduleCommandDto),
+    // stop firing schedules, the saved schedules are kept
+    Stop,
+}
+
+struct ScheduleEntry {
+    schedule: SchedulePo,
+    cron: Option<CronExpr>,
+    next_fire_time: Option<u64>,
+}
+
+/// schedules in memory, times are in epoch millis
+pub struct Scheduler {
+    entry_map: HashMap<String, ScheduleEntry>,
+    // timezone of cron expressions
+    timezone: CronTimezone,
+    // one-shot schedule late by more than this is discarded
+    misfire_grace: u64,
+}
+
+impl Scheduler {
+    pub fn new(timezone: CronTimezone, misfire_grace: u64) -> Self {
+        Scheduler {
+            entry_map: HashMap::new(),
+            timezone,
+            misfire_grace,
+        }
+    }
+
+    /// add schedule, the one with the same schedule_id is replaced
+    /// the saved next fire time of loaded cron schedule is kept if it is missed within misfire grace
+    pub fn add(&mut self, mut schedule: SchedulePo, now: u64) -> Result<ScheduleDto, String> {
+        let (cron, next_fire_time) = match (&schedule.cron, schedule.at) {
+            (Some(cron_str), None) => {
+                let cron = CronExpr::parse(cron_str)?;
+                let next_fire_time = match schedule.next_fire_time {
+                    Some(fire_time) if fire_time.saturating_add(self.misfire_grace) >= now => fire_time,
+                    _ => cron
+                        .next_after(now, self.timezone)
+                        .ok_or(format!("cron expression never matches: `{}`", cron_str))?,
+                };
+                schedule.next_fire_time = Some(next_fire_time);
+                (Some(cron), next_fire_time)
+            }
+            (None, Some(at)) => {
+                if at.saturating_add(self.misfire_grace) < now {
+                    return Err(format!("schedule time has passed: {}", at));
+                }
+                (None, at)
+            }
+            _ => return Err("one of delay, at and cron should be set".to_string()),
+        };
+        let entry = ScheduleEntry {
+            schedule,
+            cron,
+            next_fire_time: Some(next_fire_time),
+        };
+        let dto = entry.to_dto();
+        self.entry_map.insert(entry.schedule.schedule_id.clone(), entry);
+        Ok(dto)
+    }
+
+    /// remove schedule, return false if it does not exist
+    pub fn cancel(&mut self, schedule_id: &str) -> bool {
+        self.entry_map.remove(schedule_id).is_some()
+    }
+
+    /// all schedules ordered by schedule_id
+    pub fn list(&self) -> Vec<ScheduleDto> {
+        let mut list: Vec<ScheduleDto> = self.entry_map.values().map(|entry| entry.to_dto()).collect();
+        list.sort_by(|a, b| a.schedule_id.cmp(&b.schedule_id));
+        list
+    }
+
+    /// the nearest firing time of all schedules
+    pub fn next_fire_time(&self) -> Option<u64> {
+        self.entry_map.values().filter_map(|entry| entry.next_fire_time).min()
+    }
+
+    /// take the schedules to fire, the cron schedules rescheduled and the finished schedules which are removed
+    /// the schedule late by more than misfire grace is not fired
+    pub fn take_due(&mut self, now: u64) -> (Vec<SchedulePo>, Vec<SchedulePo>, Vec<String>) {
+        let mut fired_list = Vec::new();
+        let mut rescheduled_list = Vec::new();
+        let mut finished_list = Vec::new();
+        for (schedule_id, entry) in self.entry_map.iter_mut() {
+            let Some(fire_time) = entry.next_fire_time else {
+                continue;
+            };
+            if fire_time > now {
+                continue;
+            }
+            if now - fire_time > self.misfire_grace {
+                warn!(LOG_TAG, "schedule missed, schedule_id: {}, late: {} ms", schedule_id, now - fire_time);
+            } else {
+                fired_list.push(entry.schedule.clone());
+            }
+            entry.next_fire_time = entry.cron.as_ref().and_then(|cron| cron.next_after(now, self.timezone));
+            if entry.next_fire_time.is_none() {
+                finished_list.push(schedule_id.clone());
+            } else {
+                entry.schedule.next_fire_time = entry.next_fire_time;
+                rescheduled_list.push(entry.schedule.clone());
+            }
+        }
+        for schedule_id in finished_list.iter() {
+            self.entry_map.remove(schedule_id);
+        }
+        (fired_list, rescheduled_list, finished_list)
+    }
+}
+
+impl ScheduleEntry {
+    fn to_dto(&self) -> ScheduleDto {
+        ScheduleDto {
+            schedule_id: self.schedule.schedule_id.clone(),
+            device_id: self.schedule.device_id.clone(),
+            action: self.schedule.action.clone(),
+            param: self.schedule.param.clone(),
+            at: self.schedule.at,
+            cron: self.schedule.cron.clone(),
+            next_fire_time: self.next_fire_time,
+        }
+    }
+}
+
+/// make schedule from the param of schedule_add, delay is converted to time
+fn make_schedule_po(param: ScheduleAddParamDto, now: u64) -> Result<SchedulePo, String> {
+    let at = match (param.delay, param.at, &param.cron) {
+        (Some(delay), None, None) => Some(now + delay),
+        (None, Some(at), None) => Some(at),
+        (None, None, Some(_)) => None,
+        _ => return Err("exactly one of delay, at and cron should be set".to_string()),
+    };
+    Ok(SchedulePo {
+        schedule_id: param.schedule_id.unwrap_or_else(generate_uuid),
+        device_id: param.device_id,
+        action: param.action,
+        param: param.param,
+        at,
+        cron: param.cron,
+        create_time: now,
+        next_fire_time: None,
+    })
+}
+
+/// make device command of schedule, device type is taken from device info
+fn make_device_command(
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    schedule: &SchedulePo,
+) -> Result<DeviceCommandDto, String> {
+    let device_type = device_info_map
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&schedule.device_id)
+        .map(|device_info| device_info.device_type.clone())
+        .ok_or(format!("cannot find device: {}", schedule.device_id))?;
+    if !DriverRegistry::get().is_commandable(&device_type) {
+        return Err(format!("device does not accept command: {}", schedule.device_id));
+    }
+    let params = DriverRegistry::get().parse_command(&device_type, &schedule.action, schedule.param.clone())
+        .map_err(|e| format!("invalid {} command `{}`: {e}", device_type, schedule.action))?;
+    Ok(DeviceCommandDto {
+        server_id: Settings::get().server.server_id.clone(),
+        device_id: schedule.device_id.clone(),
+        device_type,
+        session_id: format!("schedule-{}", schedule.schedule_id),
+        action: schedule.action.clone(),
+        params,
+    })
+}
+
+pub fn scheduler_thread(
+    scheduler_rx: mpsc::Receiver<SchedulerCommandEnum>,
+    device_command_tx: mpsc::Sender<MqttToDeviceEnum>,
+    device_to_mqtt_tx: mpsc::Sender<DeviceToMqttEnum>,
+    device_info_map: Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dao = ScheduleDao::new();
+        let setting = &Settings::get().scheduler;
+        let timezone = CronTimezone::parse(setting.timezone.as_deref()).unwrap_or_else(|e| {
+            error!(LOG_TAG, "{}, cron schedules use system timezone", e);
+            CronTimezone::Local
+        });
+        let mut scheduler = Scheduler::new(timezone, setting.misfire_grace);
+
+        // 1. load saved schedules, the expired ones are removed
+        let loaded = rt.block_on(async {
+            dao.ensure_table_exist().await.map_err(|e| e.to_string())?;
+            dao.get_all().await.map_err(|e| e.to_string())
+        });
+        match loaded {
+            Ok(schedule_list) => {
+                let now = get_timestamp_millis();
+                for schedule in schedule_list {
+                    let schedule_id = schedule.schedule_id.clone();
+                    if let Err(e) = scheduler.add(schedule, now) {
+                        warn!(LOG_TAG, "remove invalid schedule, schedule_id: {}, error msg: {}", schedule_id, e);
+                        if let Err(e) = rt.block_on(dao.delete(schedule_id)) {
+                            error!(LOG_TAG, "cannot delete schedule, error msg: {}", e);
+                        }
+                    }
+                }
+                info!(LOG_TAG, "schedules loaded, count: {}", scheduler.entry_map.len());
+            }
+            Err(e) => {
+                error!(LOG_TAG, "cannot load schedules, new schedules are not saved, error msg: {}", e);
+            }
+        }
+
+        // 2. fire schedules and handle schedule commands, restart on panic
+        supervise("scheduler_thread", |_| {}, || -> Result<(), DriverError> {
+            loop {
+                let now = get_timestamp_millis();
+                let (fired_list, rescheduled_list, finished_list) = scheduler.take_due(now);
+                for schedule in fired_list {
+                    match make_device_command(&device_info_map, &schedule) {
+                        Ok(dto) => {
+                            info!(LOG_TAG, "fire schedule, schedule_id: {}, dto: {:?}", schedule.schedule_id, dto);
+                            if let Err(e) = device_command_tx.send(MqttToDeviceEnum::LocalCommand(dto)) {
+                                error!(LOG_TAG, "cannot send scheduled command to device thread, error msg: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            error!(LOG_TAG, "cannot fire schedule, schedule_id: {}, error msg: {}", schedule.schedule_id, e);
+                        }
+                    }
+                }
+                // next fire time is saved, so that the firing missed during restarting is still in misfire grace
+                for schedule in rescheduled_list {
+                    if let Err(e) = rt.block_on(dao.save(schedule)) {
+                        error!(LOG_TAG, "cannot save rescheduled schedule, error msg: {}", e);
+                    }
+                }
+                for schedule_id in finished_list {
+                    if let Err(e) = rt.block_on(dao.delete(schedule_id)) {
+                        error!(LOG_TAG, "cannot delete finished schedule, error msg: {}", e);
+                    }
+                }
+
+                let timeout = scheduler
+                    .next_fire_time()
+                    .map_or(SCHEDULER_MAX_WAIT_MILLIS, |fire_time| fire_time.saturating_sub(now).min(SCHEDULER_MAX_WAIT_MILLIS));
+                match scheduler_rx.recv_timeout(Duration::from_millis(timeout)) {
+                    Ok(SchedulerCommandEnum::Request(dto)) => {
+                        debug!(LOG_TAG, "got schedule command, dto: {:?}", dto);
+                        let reply = handle_schedule_command(&mut scheduler, &rt, &dao, &device_info_map, dto);
+                        if let Err(e) = device_to_mqtt_tx.send(DeviceToMqttEnum::CommandReply(reply)) {
+                            error!(LOG_TAG, "cannot send schedule command reply to mqtt client, error msg: {}", e);
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Ok(SchedulerCommandEnum::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        info!(LOG_TAG, "scheduler thread exiting: stopped");
+                        return Ok(());
+                    }
+                }
+            }
+        })
+    })
+}
+
+/// add, cancel or list schedules, and make the reply
+fn handle_schedule_command(
+    scheduler: &mut Scheduler,
+    rt: &tokio::runtime::Runtime,
+    dao: &ScheduleDao,
+    device_info_map: &Arc<Mutex<HashMap<String, DeviceMetaInfoDto>>>,
+    dto: ScheduleCommandDto,
+) -> CommandReplyDto {
+    let mut reply = CommandReplyDto::new(dto.session_id, dto.request.action().as_str().to_string());
+    let now = get_timestamp_millis();
+    match dto.request {
+        ScheduleRequestEnum::Add(param) => {
+            let result = make_schedule_po(param, now).and_then(|schedule| {
+                // the command is validated when adding, and made again when firing
+                make_device_command(device_info_map, &schedule)?;
+                let schedule_dto = scheduler.add(schedule.clone(), now)?;
+                let schedule = SchedulePo { next_fire_time: schedule.cron.as_ref().and(schedule_dto.next_fire_time), ..schedule };
+                Ok((schedule, schedule_dto))
+            });
+            match result {
+                Ok((schedule, schedule_dto)) => {
+                    let schedule_id = schedule.schedule_id.clone();
+                    if let Err(e) = rt.block_on(dao.save(schedule)) {
+                        scheduler.cancel(&schedule_id);
+                        reply.code = REPLY_CODE_ERROR;
+                        reply.msg = format!("cannot save schedule: {}", e);
+                    } else {
+                        info!(LOG_TAG, "schedule added, schedule: {:?}", schedule_dto);
+                        reply.schedules = Some(vec![schedule_dto]);
+                    }
+                }
+                Err(e) => {
+                    reply.code = REPLY_CODE_PARAM_FAIL;
+                    reply.msg = e;
+                }
+            }
+        }
+        ScheduleRequestEnum::Cancel(param) => {
+            if scheduler.cancel(&param.schedule_id) {
+                info!(LOG_TAG, "schedule canceled, schedule_id: {}", param.schedule_id);
+                if let Err(e) = rt.block_on(dao.delete(param.schedule_id)) {
+                    reply.code = REPLY_CODE_ERROR;
+                    reply.msg = format!("cannot delete schedule: {}", e);
+                }
+            } else {
+                reply.code = REPLY_CODE_PARAM_FAIL;
+                reply.msg = format!("cannot find schedule: {}", param.schedule_id);
+            }
+        }
+        ScheduleRequestEnum::List => {
+            reply.schedules = Some(scheduler.list());
+        }
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    // 2024-01-01 00:00 UTC
+    const NOW: u64 = 19723 * 86400000;
+
+    fn make_schedule(schedule_id: &str, at: Option<u64>, cron: Option<&str>) -> SchedulePo {
+        SchedulePo {
+            schedule_id: schedule_id.to_string(),
+            device_id: "do-1".to_string(),
+            action: "on".to_string(),
+            param: Value::Null,
+            at,
+            cron: cron.map(|cron| cron.to_string()),
+            create_time: NOW,
+            next_fire_time: None,
+        }
+    }
+
+    #[test]
+    fn test_make_schedule_po() {
+        let param: ScheduleAddParamDto =
+            serde_json::from_str(r#"{"device_id": "do-1", "action": "on", "delay": 5000}"#).unwrap();
+        let schedule = make_schedule_po(param, NOW).unwrap();
+        assert_eq!(schedule.at, Some(NOW + 5000));
+        assert!(!schedule.schedule_id.is_empty());
+        let param: ScheduleAddParamDto =
+            serde_json::from_str(r#"{"device_id": "do-1", "action": "on", "delay": 5000, "cron": "0 9 * * *"}"#).unwrap();
+        assert!(make_schedule_po(param, NOW).is_err());
+    }
+
+    #[test]
+    fn test_add() {
+        let mut scheduler = Scheduler::new(CronTimezone::Named(chrono_tz::UTC), 1000);
+        let dto = scheduler.add(make_schedule("open", None, Some("0 9 * * *")), NOW).unwrap();
+        assert_eq!(dto.next_fire_time, Some(NOW + 9 * 3600000));
+        assert!(scheduler.add(make_schedule("bad", None, Some("0 25 * * *")), NOW).is_err());
+        assert!(scheduler.add(make_schedule("passed", Some(NOW - 2000), None), NOW).is_err());
+        assert!(scheduler.add(make_schedule("late", Some(NOW - 500), None), NOW).is_ok());
+        assert_eq!(scheduler.list().len(), 2);
+        assert_eq!(scheduler.next_fire_time(), Some(NOW - 500));
+        assert!(scheduler.cancel("late"));
+        assert!(!scheduler.cancel("late"));
+    }
+
+    #[test]
+    fn test_take_due() {
+        let mut scheduler = Scheduler::new(CronTimezone::Named(chrono_tz::UTC), 1000);
+        scheduler.add(make_schedule("once", Some(NOW + 100), None), NOW).unwrap();
+        scheduler.add(make_schedule("hourly", None, Some("0 * * * *")), NOW).unwrap();
+        let (fired, rescheduled, finished) = scheduler.take_due(NOW + 50);
+        assert!(fired.is_empty() && rescheduled.is_empty() && finished.is_empty());
+        let (fired, _, finished) = scheduler.take_due(NOW + 100);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(finished, vec!["once".to_string()]);
+        // cron is rescheduled after firing
+        let (fired, rescheduled, finished) = scheduler.take_due(NOW + 3600000);
+        assert_eq!(fired[0].schedule_id, "hourly");
+        assert_eq!(rescheduled[0].next_fire_time, Some(NOW + 2 * 3600000));
+        assert!(finished.is_empty());
+        assert_eq!(scheduler.next_fire_time(), Some(NOW + 2 * 3600000));
+        // missed firing is skipped
+        let (fired, _, _) = scheduler.take_due(NOW + 2 * 3600000 + 5000);
+        assert!(fired.is_empty());
+        assert_eq!(scheduler.next_fire_time(), Some(NOW + 3 * 3600000));
+    }
+
+    #[test]
+    fn test_load_missed_cron() {
+        let mut scheduler = Scheduler::new(CronTimezone::Named(chrono_tz::UTC), 1000);
+        // saved next fire time missed within misfire grace during restarting is still fired
+        let schedule = SchedulePo { next_fire_time: Some(NOW), ..make_schedule("hourly", None, Some("0 * * * *")) };
+        assert_eq!(scheduler.add(schedule.clone(), NOW + 500).unwrap().next_fire_time, Some(NOW));
+        let (fired, _, _) = scheduler.take_due(NOW + 500);
+        assert_eq!(fired[0].schedule_id, "hourly");
+        // missed by more than misfire grace
+        assert_eq!(scheduler.add(schedule, NOW + 5000).unwrap().next_fire_time, Some(NOW + 3600000));
+    }
+}
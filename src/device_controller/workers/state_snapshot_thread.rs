@@ -0,0 +1,90 @@
+//! save state snapshot of output devices into sqlite
+//! only the devices with restore_policy "last" are saved, the state is used for restoring after restarting
+//! the latest state of each device is kept in memory and written at a regular interval,
+//! so that fast changing devices (e.g. dmx fading) do not write sqlite on every report
+
+use std::{
+    collections::HashMap,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::super::entity::state_snapshot_po::StateSnapshotPo;
+use super::super::state_snapshot_dao::StateSnapshotDao;
+use crate::common::{error::DriverError, supervisor::supervise};
+use crate::{debug, error, info};
+
+const LOG_TAG: &str = "state_snapshot_thread";
+// interval of writing snapshots into sqlite
+const SNAPSHOT_FLUSH_INTERVAL_MILLIS: u64 = 1000;
+
+pub enum SnapshotThreadCommandEnum {
+    // save the latest state of device
+    Save(StateSnapshotPo),
+    // write the pending snapshots and stop, the states after stopping (e.g. safe state on shutdown) are not saved
+    Stop,
+}
+
+pub fn state_snapshot_thread(snapshot_rx: mpsc::Receiver<SnapshotThreadCommandEnum>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dao = StateSnapshotDao::new();
+        if let Err(e) = rt.block_on(dao.ensure_table_exist()) {
+            error!(LOG_TAG, "state snapshot thread exiting: cannot create table, error msg: {}", e);
+            return;
+        }
+        // restart on panic, exit when stopped or channel is closed
+        supervise("state_snapshot_thread", |_| {}, || -> Result<(), DriverError> {
+            let mut pending_map: HashMap<String, StateSnapshotPo> = HashMap::new();
+            let mut last_flush = Instant::now();
+            loop {
+                let stopped = match snapshot_rx.recv_timeout(Duration::from_millis(SNAPSHOT_FLUSH_INTERVAL_MILLIS)) {
+                    Ok(SnapshotThreadCommandEnum::Save(snapshot)) => {
+                        pending_map.insert(snapshot.device_id.clone(), snapshot);
+                        false
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => false,
+                    Ok(SnapshotThreadCommandEnum::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => true,
+                };
+                if stopped || last_flush.elapsed() >= Duration::from_millis(SNAPSHOT_FLUSH_INTERVAL_MILLIS) {
+                    last_flush = Instant::now();
+                    if !pending_map.is_empty() {
+                        let snapshot_list: Vec<StateSnapshotPo> = pending_map.drain().map(|(_, snapshot)| snapshot).collect();
+                        debug!(LOG_TAG, "save state snapshots, count: {}", snapshot_list.len());
+                        if let Err(e) = rt.block_on(dao.save_all(snapshot_list)) {
+                            error!(LOG_TAG, "cannot save state snapshots, error msg: {}", e);
+                        }
+                    }
+                }
+                if stopped {
+                    info!(LOG_TAG, "state snapshot thread exiting: stopped");
+                    return Ok(());
+                }
+            }
+        })
+    })
+}
+
+/// read all saved state snapshots, empty if they cannot be read
+pub fn load_state_snapshots() -> HashMap<String, StateSnapshotPo> {
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|e| e.to_string())
+        .and_then(|rt| {
+            rt.block_on(async {
+                let dao = StateSnapshotDao::new();
+                dao.ensure_table_exist().await.map_err(|e| e.to_string())?;
+                dao.get_all().await.map_err(|e| e.to_string())
+            })
+        });
+    match result {
+        Ok(snapshot_list) => snapshot_list
+            .into_iter()
+            .map(|snapshot| (snapshot.device_id.clone(), snapshot))
+            .collect(),
+        Err(e) => {
+            error!(LOG_TAG, "cannot load state snapshots, error msg: {}", e);
+            HashMap::new()
+        }
+    }
+}
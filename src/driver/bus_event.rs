@@ -0,0 +1,131 @@
+//! 总线事件
+//! - 总线线程读写失败时，向所属的总线对象和控制器对象推送错误事件
+//! - 读写恢复正常后推送恢复事件，错误信息自动清除
+//! - 同一个错误只上报一次，避免轮询失败时重复上报
+
+use std::sync::{mpsc::Sender, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::common::error::DriverError;
+use crate::common::supervisor::join_timeout;
+use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
+use crate::util::time::get_timestamp_millis;
+
+// max time waiting for the bus thread to exit after stop command (millis)
+const BUS_STOP_TIMEOUT: u64 = 5000;
+
+/// event pushed from bus thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum BusEventEnum {
+    // reading or writing failed, with error message
+    Error(String),
+    // reading or writing succeeded
+    Recovered,
+}
+
+/// error fields of a device, updated by bus events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BusErrorState {
+    pub error_msg: Option<String>,
+    pub error_timestamp: Option<u64>,
+}
+
+impl BusErrorState {
+    /// apply bus event, return true if the state is changed and should be reported
+    pub fn apply(&mut self, event: &BusEventEnum) -> bool {
+        match event {
+            BusEventEnum::Error(error_msg) => {
+                if self.error_msg.as_ref() == Some(error_msg) {
+                    return false;
+                }
+                self.error_msg = Some(error_msg.clone());
+                self.error_timestamp = Some(get_timestamp_millis());
+                true
+            }
+            BusEventEnum::Recovered => {
+                if self.error_msg.is_none() {
+                    return false;
+                }
+                self.error_msg = None;
+                self.error_timestamp = None;
+                true
+            }
+        }
+    }
+
+    /// device is active when there is no error
+    pub fn is_active(&self) -> bool {
+        self.error_msg.is_none()
+    }
+}
+
+/// apply bus event to the bus error state, report to device controller if the state is changed
+pub fn report_bus_event(
+    error_state: &Mutex<BusErrorState>,
+    report_tx: &Sender<StateToDeviceControllerDto>,
+    device_id: &str,
+    device_class: &str,
+    device_type: &str,
+    event: BusEventEnum,
+) {
+    if !error_state.lock().unwrap_or_else(|e| e.into_inner()).apply(&event) {
+        return;
+    }
+    let dto = match event {
+        BusEventEnum::Error(error_msg) => StateToDeviceControllerDto::error(device_id, device_class, device_type, error_msg),
+        BusEventEnum::Recovered => StateToDeviceControllerDto::recovered(device_id, device_class, device_type),
+    };
+    let _ = report_tx.send(dto);
+}
+
+/// wait for the bus thread to exit after stop command
+/// the serial port is opened exclusively, so it must be released before the bus is made again
+pub fn join_bus_thread(handle: Option<JoinHandle<()>>, device_id: &str) -> Result<(), DriverError> {
+    let Some(handle) = handle else {
+        return Ok(());
+    };
+    if !join_timeout(handle, Duration::from_millis(BUS_STOP_TIMEOUT)) {
+        return Err(DriverError(format!(
+            "bus thread does not exit in {} ms, device_id: {}",
+            BUS_STOP_TIMEOUT, device_id
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_apply() {
+        let mut state = BusErrorState::default();
+        // recovered without error is not reported
+        assert!(!state.apply(&BusEventEnum::Recovered));
+        assert!(state.apply(&BusEventEnum::Error("read failed".to_string())));
+        assert!(!state.is_active());
+        assert!(state.error_timestamp.is_some());
+        // the same error is reported once
+        assert!(!state.apply(&BusEventEnum::Error("read failed".to_string())));
+        assert!(state.apply(&BusEventEnum::Error("timeout".to_string())));
+        assert!(state.apply(&BusEventEnum::Recovered));
+        assert_eq!(state, BusErrorState::default());
+    }
+
+    #[test]
+    fn test_report_bus_event() {
+        let (tx, rx) = mpsc::channel();
+        let state = Mutex::new(BusErrorState::default());
+        report_bus_event(&state, &tx, "bus", "bus", "dmx_bus", BusEventEnum::Error("send failed".to_string()));
+        report_bus_event(&state, &tx, "bus", "bus", "dmx_bus", BusEventEnum::Error("send failed".to_string()));
+        report_bus_event(&state, &tx, "bus", "bus", "dmx_bus", BusEventEnum::Recovered);
+        let reports: Vec<StateToDeviceControllerDto> = rx.try_iter().collect();
+        assert_eq!(reports.len(), 2);
+        assert!(!reports[0].status.active);
+        assert_eq!(reports[0].status.error_msg.as_deref(), Some("send failed"));
+        assert!(reports[1].status.active);
+        assert!(reports[1].status.error_msg.is_none());
+    }
+}
@@ -7,9 +7,9 @@
 //! - provide interface for audio pause, stop and resume
 //! - mix audio files in one audio device
 
-use crate::common::error::DriverError;
-use crate::driver::traits::ReportUpward;
-use crate::entity::dto::device_command_dto::{AudioParamsDto, CommandParamsEnum, DeviceCommandDto};
+use crate::common::error::{CommandError, DriverError};
+use crate::driver::traits::{Commandable, ReportUpward};
+use crate::entity::dto::device_command_dto::{AudioCommandEnum, CommandParamsEnum, DeviceCommandDto};
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{
     AudioFilePlayingDto, AudioStateDto, StateToDeviceControllerDto, StateDtoEnum,
@@ -56,24 +56,13 @@ impl ReportUpward for AudioOutput {
     }
 
     fn report(&self) -> Result<(), DriverError> {
-        let mut playing_dto_list: Vec<AudioFilePlayingDto> = Vec::new();
-        for filename in self.sink_map.keys() {
-            let playing_dto = AudioFilePlayingDto {
-                file_id: filename.clone(),
-               playing: true,
-            };
-            playing_dto_list.push(playing_dto);
-        }
-        let state_dto = AudioStateDto {
-            stream: playing_dto_list
-        };
         self.report_tx
             .send(StateToDeviceControllerDto {
                 device_class: DEVICE_CLASS.to_string(),
                 device_type: DEVICE_TYPE.to_string(),
                 device_id: self.device_id.clone(),
                 status: DeviceReportDto {
-                    state: StateDtoEnum::Audio(state_dto),
+                    state: self.get_state(),
                     error_msg: self.error_msg.clone(),
                     error_timestamp: self.error_timestamp,
                     last_update: self.last_update,
@@ -86,6 +75,51 @@ impl ReportUpward for AudioOutput {
     }
 }
 
+impl Commandable for AudioOutput {
+    /// receive and process of audio command
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        let audio_command = match dto.params {
+            CommandParamsEnum::Audio(audio_command) => audio_command,
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for audio device: {:?}", dto)));
+            }
+        };
+        // 1. get filename from hash
+        let file_controller = FileController::get();
+        let file_hash = match &audio_command {
+            AudioCommandEnum::Play(audio_params)
+            | AudioCommandEnum::Pause(audio_params)
+            | AudioCommandEnum::Stop(audio_params)
+            | AudioCommandEnum::Resume(audio_params) => audio_params.hash.clone(),
+        };
+        let filename = file_controller.get_path_by_hash(file_hash.as_str())
+            .ok_or_else(|| CommandError::Param(format!("cannot find file by hash: {}", file_hash)))?;
+        // 2. use filename to play the file
+        match audio_command {
+            AudioCommandEnum::Play(_) => self.play(filename)?,
+            AudioCommandEnum::Pause(_) => self.pause(filename)?,
+            AudioCommandEnum::Stop(_) => self.stop(filename)?,
+            AudioCommandEnum::Resume(_) => self.resume(filename)?,
+        }
+        self.report()?;
+        Ok(())
+    }
+
+    fn get_state(&self) -> StateDtoEnum {
+        let mut playing_dto_list: Vec<AudioFilePlayingDto> = Vec::new();
+        for filename in self.sink_map.keys() {
+            let playing_dto = AudioFilePlayingDto {
+                file_id: filename.clone(),
+               playing: true,
+            };
+            playing_dto_list.push(playing_dto);
+        }
+        StateDtoEnum::Audio(AudioStateDto {
+            stream: playing_dto_list
+        })
+    }
+}
+
 impl AudioOutput {
     pub fn new(
         device_id: &str,
@@ -119,37 +153,6 @@ impl AudioOutput {
         None
     }
 
-    /// receive and process of audio command
-    pub fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), DriverError> {
-        // 1. get filename from hash
-        let file_controller = FileController::get();
-        // 2. use filename to play the file
-        match dto.params {
-            CommandParamsEnum::Audio(audio_params) => {
-                let action = dto.action;
-                let file_hash = audio_params.hash;
-                let filename = file_controller.get_path_by_hash(file_hash.as_str())
-                    .ok_or_else(|| DriverError(format!("cannot find file by hash: {}", file_hash)))?;
-                if action == "play" {
-                    self.play(filename)?;
-                } else if action == "pause" {
-                    self.pause(filename)?;
-                } else if action == "stop" {
-                    self.stop(filename)?;
-                } else if action == "resume" {
-                    self.resume(filename)?;
-                } else {
-                    return Err(DriverError(format!("invalid action for audio device: {}", action)));
-                }
-            },
-            _ => {
-                return Err(DriverError(format!("invalid command data for audio device: {:?}", dto)));
-            }
-        }
-        self.report()?;
-        Ok(())
-    }
-
     /// play audio according to file path
     /// playing files will be managed by sink
     /// CAUTION: there should be only one playing instance at the same time, if replaying the same file, previous instance should be stopped
@@ -243,6 +246,16 @@ impl AudioOutput {
         Ok(())
     }
 
+    /// stop all playing audio
+    pub fn stop_all(&mut self) -> Result<(), DriverError> {
+        for (_, sink) in self.sink_map.drain() {
+            sink.stop();
+        }
+        self.stream_map.clear();
+        self.report()?;
+        Ok(())
+    }
+
     pub fn resume(&self, filename: String) -> Result<(), DriverError> {
         let sink = self
             .sink_map
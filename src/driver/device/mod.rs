@@ -1 +1,2 @@
 pub mod audio_output;
+pub mod sequence_detector;
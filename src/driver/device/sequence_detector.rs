@@ -0,0 +1,320 @@
+//! sequence detector, virtual device matching input events of di ports and remote buttons
+//!
+//! function:
+//! - sequence mode: steps are triggered in order, the wrong input fails the attempt
+//! - combination mode: all steps are active at the same time in any order, di steps are active while di is on
+//! - the attempt fails when it is not matched within the reset timeout since the first step
+//! - every matched step, match and failure is reported upward as device state
+
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::common::error::DriverError;
+use crate::driver::traits::{ReportUpward, Scheduled};
+use crate::entity::dto::device_report_dto::DeviceReportDto;
+use crate::entity::dto::device_state_dto::{
+    SequenceEventEnum, SequenceFailReasonEnum, SequenceStateDto, StateDtoEnum, StateToDeviceControllerDto,
+};
+use crate::entity::dto::input_event_dto::{InputEventDto, InputEventEnum};
+use crate::util::time::get_timestamp_millis;
+use crate::{debug, info};
+
+const LOG_TAG: &str = "sequence_detector";
+const DEVICE_TYPE: &str = "sequence_detector";
+const DEVICE_CLASS: &str = "operable";
+
+pub enum SequenceModeEnum {
+    Sequence,
+    Combination,
+}
+
+/// rise of di port, or press of remote button when button is set
+pub struct SequenceStep {
+    pub device_id: String,
+    pub button: Option<u8>,
+}
+
+impl SequenceStep {
+    fn is_matched(&self, event: InputEventEnum) -> bool {
+        match (self.button, event) {
+            (None, InputEventEnum::Rise) => true,
+            (Some(button), InputEventEnum::Press(pressed)) => button == pressed,
+            _ => false,
+        }
+    }
+}
+
+pub struct SequenceDetector {
+    device_id: String,
+    mode: SequenceModeEnum,
+    steps: Vec<SequenceStep>,
+    timeout: Duration,
+    report_tx: Sender<StateToDeviceControllerDto>,
+    // matched steps of current attempt, leading steps in sequence mode
+    matched: Vec<bool>,
+    // reset time of current attempt, none when idle
+    deadline: Option<Instant>,
+    last_event: SequenceEventEnum,
+    last_reason: Option<SequenceFailReasonEnum>,
+    last_update: Option<u64>,
+}
+
+impl SequenceDetector {
+    pub fn new(
+        device_id: &str,
+        mode: SequenceModeEnum,
+        steps: Vec<SequenceStep>,
+        timeout: u64,
+        report_tx: Sender<StateToDeviceControllerDto>,
+    ) -> Self {
+        let total = steps.len();
+        SequenceDetector {
+            device_id: device_id.to_string(),
+            mode,
+            steps,
+            timeout: Duration::from_millis(timeout),
+            report_tx,
+            matched: vec![false; total],
+            deadline: None,
+            last_event: SequenceEventEnum::Idle,
+            last_reason: None,
+            last_update: None,
+        }
+    }
+
+    /// match the input event, report when the progress changes
+    pub fn notify_input(&mut self, dto: &InputEventDto, now: Instant) -> Result<(), DriverError> {
+        // events of other devices are ignored
+        if !self.steps.iter().any(|step| step.device_id == dto.device_id) {
+            return Ok(());
+        }
+        let changed = match self.mode {
+            SequenceModeEnum::Sequence => self.input_sequence(dto, now),
+            SequenceModeEnum::Combination => self.input_combination(dto, now),
+        };
+        if !changed {
+            return Ok(());
+        }
+        debug!(LOG_TAG, "sequence progress, device_id: {}, event: {:?}, progress: {}", &self.device_id, self.last_event, self.progress());
+        self.last_update = Some(get_timestamp_millis());
+        self.report()
+    }
+
+    pub fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::Sequence(SequenceStateDto {
+            event: self.last_event,
+            reason: self.last_reason,
+            progress: self.progress(),
+            total: self.steps.len(),
+        })
+    }
+
+    fn progress(&self) -> usize {
+        self.matched.iter().filter(|matched| **matched).count()
+    }
+
+    /// the expected step is matched, other rises and presses of input devices fail the started attempt
+    fn input_sequence(&mut self, dto: &InputEventDto, now: Instant) -> bool {
+        let progress = self.progress();
+        let step = &self.steps[progress];
+        if step.device_id == dto.device_id && step.is_matched(dto.event) {
+            self.matched[progress] = true;
+            self.step_matched(now);
+            return true;
+        }
+        if dto.event == InputEventEnum::Fall || progress == 0 {
+            return false;
+        }
+        self.fail(SequenceFailReasonEnum::WrongInput);
+        true
+    }
+
+    /// di steps are released when di turns off, presses not in the steps fail the started attempt
+    fn input_combination(&mut self, dto: &InputEventDto, now: Instant) -> bool {
+        if dto.event == InputEventEnum::Fall {
+            let mut changed = false;
+            for (step, matched) in self.steps.iter().zip(self.matched.iter_mut()) {
+                if step.device_id == dto.device_id && step.button.is_none() && *matched {
+                    *matched = false;
+                    changed = true;
+                }
+            }
+            if changed {
+                self.last_event = if self.progress() == 0 { SequenceEventEnum::Idle } else { SequenceEventEnum::SequenceProgress };
+                self.last_reason = None;
+                if self.progress() == 0 {
+                    self.deadline = None;
+                }
+            }
+            return changed;
+        }
+        let index = self
+            .steps
+            .iter()
+            .position(|step| step.device_id == dto.device_id && step.is_matched(dto.event));
+        match index {
+            Some(index) => {
+                self.matched[index] = true;
+                self.step_matched(now);
+                true
+            }
+            None if self.progress() > 0 => {
+                self.fail(SequenceFailReasonEnum::WrongInput);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// start the reset timeout on the first step, reset when all steps are matched
+    fn step_matched(&mut self, now: Instant) {
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.timeout);
+        }
+        self.last_reason = None;
+        if self.matched.iter().all(|matched| *matched) {
+            info!(LOG_TAG, "sequence matched, device_id: {}", &self.device_id);
+            self.reset();
+            self.last_event = SequenceEventEnum::SequenceMatched;
+        } else {
+            self.last_event = SequenceEventEnum::SequenceProgress;
+        }
+    }
+
+    fn fail(&mut self, reason: SequenceFailReasonEnum) {
+        info!(LOG_TAG, "sequence failed, device_id: {}, reason: {:?}", &self.device_id, reason);
+        self.reset();
+        self.last_event = SequenceEventEnum::SequenceFailed;
+        self.last_reason = Some(reason);
+    }
+
+    fn reset(&mut self) {
+        self.matched.iter_mut().for_each(|matched| *matched = false);
+        self.deadline = None;
+    }
+}
+
+impl ReportUpward for SequenceDetector {
+    fn get_upward_channel(&self) -> &Sender<StateToDeviceControllerDto> {
+        &self.report_tx
+    }
+
+    fn report(&self) -> Result<(), DriverError> {
+        self.notify_upward(StateToDeviceControllerDto {
+            device_id: self.device_id.clone(),
+            device_class: DEVICE_CLASS.to_string(),
+            device_type: DEVICE_TYPE.to_string(),
+            status: DeviceReportDto {
+                active: true,
+                error_msg: None,
+                error_timestamp: None,
+                last_update: self.last_update,
+                state: self.get_state(),
+            },
+        })
+    }
+}
+
+impl Scheduled for SequenceDetector {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// the attempt fails when reset timeout is reached
+    fn run_due(&mut self, now: Instant) -> Result<(), DriverError> {
+        if self.deadline.is_none_or(|deadline| deadline > now) {
+            return Ok(());
+        }
+        self.fail(SequenceFailReasonEnum::Timeout);
+        self.last_update = Some(get_timestamp_millis());
+        self.report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn make_detector(mode: SequenceModeEnum, steps: &[(&str, Option<u8>)]) -> (SequenceDetector, mpsc::Receiver<StateToDeviceControllerDto>) {
+        let (tx, rx) = mpsc::channel();
+        let steps = steps
+            .iter()
+            .map(|(device_id, button)| SequenceStep { device_id: device_id.to_string(), button: *button })
+            .collect();
+        (SequenceDetector::new("puzzle", mode, steps, 10000, tx), rx)
+    }
+
+    fn input(device_id: &str, event: InputEventEnum) -> InputEventDto {
+        InputEventDto { device_id: device_id.to_string(), event }
+    }
+
+    fn last_state(rx: &mpsc::Receiver<StateToDeviceControllerDto>) -> (SequenceEventEnum, Option<SequenceFailReasonEnum>, usize) {
+        match rx.try_iter().last().expect("no report").status.state {
+            StateDtoEnum::Sequence(state) => (state.event, state.reason, state.progress),
+            state => panic!("wrong state: {:?}", state),
+        }
+    }
+
+    #[test]
+    fn test_sequence() {
+        let (mut detector, rx) = make_detector(SequenceModeEnum::Sequence, &[("di-3", None), ("di-1", None), ("di-4", None)]);
+        let now = Instant::now();
+
+        // the attempt starts with the first step, other inputs are ignored
+        detector.notify_input(&input("di-1", InputEventEnum::Rise), now).unwrap();
+        detector.notify_input(&input("di-9", InputEventEnum::Rise), now).unwrap();
+        assert!(rx.try_recv().is_err());
+        detector.notify_input(&input("di-3", InputEventEnum::Rise), now).unwrap();
+        detector.notify_input(&input("di-3", InputEventEnum::Fall), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceProgress, None, 1));
+        assert_eq!(detector.next_deadline(), Some(now + Duration::from_millis(10000)));
+
+        // wrong input
+        detector.notify_input(&input("di-4", InputEventEnum::Rise), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceFailed, Some(SequenceFailReasonEnum::WrongInput), 0));
+        assert_eq!(detector.next_deadline(), None);
+
+        for device_id in ["di-3", "di-1", "di-4"] {
+            detector.notify_input(&input(device_id, InputEventEnum::Rise), now).unwrap();
+        }
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceMatched, None, 0));
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_sequence_timeout() {
+        let (mut detector, rx) = make_detector(SequenceModeEnum::Sequence, &[("remote", Some(1)), ("remote", Some(2))]);
+        let now = Instant::now();
+        detector.notify_input(&input("remote", InputEventEnum::Press(1)), now).unwrap();
+        detector.run_due(now + Duration::from_millis(5000)).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceProgress, None, 1));
+
+        detector.run_due(now + Duration::from_millis(10000)).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceFailed, Some(SequenceFailReasonEnum::Timeout), 0));
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_combination() {
+        let (mut detector, rx) = make_detector(SequenceModeEnum::Combination, &[("di-1", None), ("di-2", None), ("remote", Some(5))]);
+        let now = Instant::now();
+
+        // di step is released when di turns off
+        detector.notify_input(&input("di-2", InputEventEnum::Rise), now).unwrap();
+        detector.notify_input(&input("di-2", InputEventEnum::Fall), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::Idle, None, 0));
+        assert_eq!(detector.next_deadline(), None);
+
+        // wrong button
+        detector.notify_input(&input("di-2", InputEventEnum::Rise), now).unwrap();
+        detector.notify_input(&input("remote", InputEventEnum::Press(4)), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceFailed, Some(SequenceFailReasonEnum::WrongInput), 0));
+
+        detector.notify_input(&input("remote", InputEventEnum::Press(5)), now).unwrap();
+        detector.notify_input(&input("di-2", InputEventEnum::Rise), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceProgress, None, 2));
+        detector.notify_input(&input("di-1", InputEventEnum::Rise), now).unwrap();
+        assert_eq!(last_state(&rx), (SequenceEventEnum::SequenceMatched, None, 0));
+    }
+}
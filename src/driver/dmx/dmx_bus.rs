@@ -8,15 +8,21 @@
 //! - dmx 仅支持写而不支持读，所以只有下行数据而无上行数据
 
 use dmx::{self, DmxTransmitter};
-use crate::driver::traits::ReportUpward;
+use crate::driver::traits::{Commandable, ReportUpward, Scheduled};
+use crate::entity::dto::device_command_dto::{CommandParamsEnum, DeviceCommandDto, DmxBusCommandEnum};
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use std::sync::mpsc::Sender;
 use std::sync::{mpsc, Arc, Mutex};
 use std::{thread, time, error::Error};
-use crate::common::error::DriverError;
+use std::thread::JoinHandle;
+use std::time::Instant;
+use crate::common::error::{CommandError, DriverError};
+use crate::common::supervisor::supervise;
+use crate::driver::bus_event::{join_bus_thread, report_bus_event, BusErrorState, BusEventEnum};
 use crate::{info, warn, error, trace, debug};
 use crate::entity::dto::device_state_dto::{StateDtoEnum, StateToDeviceControllerDto, DmxBusStateDto};
 use super::prelude::{DmxValue, DMX_CHANNEL_LEN};
+use super::dmx_fade::DmxFade;
 use super::dmx_thread::*;
 use super::entity::*;
 
@@ -31,10 +37,14 @@ pub struct DmxBus {
     data: [DmxValue; DMX_CHANNEL_LEN],
     // thread command sending channel
     thread_tx: Option<mpsc::Sender<DmxThreadCommandEnum>>,
+    // sending thread handle, joined on stopping
+    thread_handle: Option<JoinHandle<()>>,
     report_tx: Sender<StateToDeviceControllerDto>,
-    error_msg: Option<String>,
-    error_timestamp: Option<u64>,
+    // error_msg and error_timestamp, shared with the sending thread
+    error_state: Arc<Mutex<BusErrorState>>,
     last_update: Option<u64>,
+    // crossfade in progress, canceled by setting channels
+    fade: Option<DmxFade>,
 }
 
 impl ReportUpward for DmxBus {
@@ -44,25 +54,73 @@ impl ReportUpward for DmxBus {
 
     // report dmx channel state change to report channel
     fn report(&self) -> Result<(), DriverError> {
-        let state = DmxBusStateDto {
-            channel: Vec::from(self.data.clone())
-        };
+        let error_state = self.error_state.lock().unwrap_or_else(|e| e.into_inner()).clone();
         self.notify_upward(StateToDeviceControllerDto {
             device_id: self.device_id.clone(),
             device_class: DEVICE_CLASS.to_string(),
             device_type: DEVICE_TYPE.to_string(),
             status: DeviceReportDto {
-                error_msg: self.error_msg.clone(),
-                error_timestamp: self.error_timestamp,
+                active: error_state.is_active(),
+                error_msg: error_state.error_msg,
+                error_timestamp: error_state.error_timestamp,
                 last_update: self.last_update,
-                active: true,
-                state: StateDtoEnum::DmxBus(state)
+                state: self.get_state()
             }
         })?;
         Ok(())
     }
 }
 
+impl Commandable for DmxBus {
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        match dto.params {
+            CommandParamsEnum::DmxBus(DmxBusCommandEnum::SetChannels(params)) => {
+                check_range(params.offset, params.values.len()).map_err(CommandError::Param)?;
+                match params.fade {
+                    Some(fade) if fade > 0 => self.fade_channels(params.offset, params.values, fade)?,
+                    _ => self.set_channels(params.offset, &params.values)?,
+                }
+            }
+            CommandParamsEnum::DmxBus(DmxBusCommandEnum::Blackout) => {
+                self.blackout()?;
+            }
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for DmxBus: {:?}", dto)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::DmxBus(DmxBusStateDto {
+            channel: Vec::from(self.data.clone())
+        })
+    }
+}
+
+impl Scheduled for DmxBus {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.fade.as_ref().map(|fade| fade.next_deadline())
+    }
+
+    fn run_due(&mut self, now: Instant) -> Result<(), DriverError> {
+        let Some(fade) = self.fade.as_mut() else {
+            return Ok(());
+        };
+        if fade.next_deadline() > now {
+            return Ok(());
+        }
+        let channel = fade.channel;
+        let (values, finished) = fade.step(now);
+        if finished {
+            // the final values are reported
+            self.set_channels(channel, &values)
+        } else {
+            self.write_channels(channel, &values)
+        }
+    }
+}
+
 impl DmxBus {
 
     /// create a new dmx bus device
@@ -72,10 +130,11 @@ impl DmxBus {
             serial_port: serial_port.to_string(),
             data: [0; 512],
             thread_tx: None,
+            thread_handle: None,
             report_tx,
-            error_msg: None,
-            error_timestamp: None,
+            error_state: Arc::new(Mutex::new(BusErrorState::default())),
             last_update: None,
+            fade: None,
         }
     }
 
@@ -90,11 +149,22 @@ impl DmxBus {
         self.thread_tx = Some(tx);
 
         let serial_port_str = self.serial_port.clone();
+        let device_id = self.device_id.clone();
+        let report_tx = self.report_tx.clone();
+        let error_state = self.error_state.clone();
 
-        // create a thread loop
-        let handle = thread::spawn(move || {
-            let _ = run_loop(serial_port_str.as_str(), thread_data, rx);
-        });
+        // create a thread loop, restart on error
+        self.thread_handle = Some(thread::spawn(move || {
+            let mut thread_data = thread_data;
+            let on_bus_event = |event: BusEventEnum| {
+                report_bus_event(&error_state, &report_tx, &device_id, DEVICE_CLASS, DEVICE_TYPE, event);
+            };
+            supervise(
+                &format!("dmx_bus:{}", device_id),
+                |error_msg| on_bus_event(BusEventEnum::Error(error_msg.to_string())),
+                || run_loop(serial_port_str.as_str(), &mut thread_data, &rx, &on_bus_event),
+            );
+        }));
 
         info!(
             LOG_TAG,
@@ -113,17 +183,40 @@ impl DmxBus {
         Ok(())
     }
 
-    /// set multiple channel on modbus bus
+    /// set multiple channel on modbus bus, the crossfade in progress is canceled
     pub fn set_channels(&mut self, address: u8, values: &[u8]) -> Result<(), DriverError> {
+        self.fade = None;
+        self.write_channels(address, values)?;
+        self.report()?;
+        Ok(())
+    }
+
+    /// fade multiple channels from the current values to the values in duration (millis)
+    pub fn fade_channels(&mut self, address: u8, values: Vec<u8>, duration: u64) -> Result<(), DriverError> {
+        check_range(address, values.len()).map_err(DriverError)?;
+        let from = self.data[address as usize..address as usize + values.len()].to_vec();
+        self.fade = Some(DmxFade::new(address, from, values, duration, Instant::now()));
+        Ok(())
+    }
+
+    /// write multiple channels to the sending thread without reporting, used by fading frames
+    pub fn write_channels(&mut self, address: u8, values: &[u8]) -> Result<(), DriverError> {
+        check_range(address, values.len()).map_err(DriverError)?;
         for i in 0..values.len() {
             self.data[address as usize + i] = values[i];
         }
+        self.sync_channel_data_to_thread()
+    }
+
+    /// set all channels to zero
+    pub fn blackout(&mut self) -> Result<(), DriverError> {
+        self.fade = None;
+        self.data = [0; DMX_CHANNEL_LEN];
         self.sync_channel_data_to_thread()?;
         self.report()?;
         Ok(())
     }
 
-
     fn sync_channel_data_to_thread(&self) -> Result<(), DriverError> {
         match &self.thread_tx {
             Some(tx) => {
@@ -149,11 +242,13 @@ impl DmxBus {
         }
         Ok(data)
     }
-    ///  stop the sending thread
-    fn stop(&mut self) -> Result<(), DriverError> {
-        match &self.thread_tx {
+    ///  stop the sending thread and wait for it to exit
+    pub fn stop(&mut self) -> Result<(), DriverError> {
+        match self.thread_tx.take() {
             Some(tx) => {
-                tx.send(DmxThreadCommandEnum::Stop).expect("dmx bus: send stop command to thread error");
+                tx.send(DmxThreadCommandEnum::Stop)
+                    .map_err(|e| DriverError(format!("dmx bus: send stop command to thread error: {}", e)))?;
+                join_bus_thread(self.thread_handle.take(), &self.device_id)?;
                 info!(
                     LOG_TAG,
                     "dmx bus: stop dmx bus, serial port: {}, data: {:?}",
@@ -168,6 +263,13 @@ impl DmxBus {
     }
 }
 
+/// check if the channels are on the bus
+fn check_range(address: u8, length: usize) -> Result<(), String> {
+    if address as usize + length > DMX_CHANNEL_LEN {
+        return Err(format!("dmx bus: channel out of range, address: {}, length: {}", address, length));
+    }
+    Ok(())
+}
 
 #[cfg(test)]
 mod tests {
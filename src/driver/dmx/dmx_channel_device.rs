@@ -1,12 +1,15 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
+use std::time::Instant;
 
 use super::dmx_bus::DmxBus;
+use super::dmx_fade::DmxFade;
 use super::prelude::*;
 use super::traits::DmxCaller;
-use crate::common::error::DriverError;
-use crate::driver::traits::ReportUpward;
+use crate::common::error::{CommandError, DriverError};
+use crate::driver::traits::{Commandable, ReportUpward, Scheduled};
+use crate::entity::dto::device_command_dto::{CommandParamsEnum, DeviceCommandDto, DmxChannelCommandEnum};
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{
     ChannelStateDto, DmxBusStateDto, StateDtoEnum, StateToDeviceControllerDto,
@@ -26,12 +29,14 @@ pub struct DmxChannelDevice {
     error_msg: Option<String>,
     error_timestamp: Option<u64>,
     last_update: Option<u64>,
+    // crossfade in progress, canceled by setting channels
+    fade: Option<DmxFade>,
 }
 
 impl DmxCaller for DmxChannelDevice {
     fn set_channel(&mut self, channel: DmxAddress, value: DmxValue) -> Result<(), DriverError> {
         // check if the channel is out of range
-        if channel > self.channel_num {
+        if channel >= self.channel_num {
             return Err(DriverError(format!("channelled device set_channel failed, channel out of range, channel = {}, channel_num = {}, device_id = {}", channel, self.channel_num, self.device_id)));
         }
         // update data in vec
@@ -61,8 +66,71 @@ impl DmxChannelDevice {
             error_msg: None,
             error_timestamp: None,
             last_update: None,
+            fade: None,
         }
     }
+
+    /// set continuous channels starting from channel, and report the new state
+    /// the crossfade in progress is canceled
+    pub fn set_channels(&mut self, channel: DmxAddress, values: &[DmxValue]) -> Result<(), DriverError> {
+        self.fade = None;
+        let bus_address = self.bus_address(channel, values.len())?;
+        self.dmx_bus_ref
+            .borrow_mut()
+            .set_channels(bus_address, values)?;
+        for (i, value) in values.iter().enumerate() {
+            self.value[channel as usize + i] = *value;
+        }
+        self.report()?;
+        Ok(())
+    }
+
+    /// fade continuous channels from the current values to the values in duration (millis)
+    pub fn fade_channels(&mut self, channel: DmxAddress, values: Vec<DmxValue>, duration: u64) -> Result<(), DriverError> {
+        self.bus_address(channel, values.len())?;
+        let from = self.value[channel as usize..channel as usize + values.len()].to_vec();
+        self.fade = Some(DmxFade::new(channel, from, values, duration, Instant::now()));
+        Ok(())
+    }
+
+    /// write a fading frame to dmx bus without reporting
+    fn write_frame(&mut self, channel: DmxAddress, values: &[DmxValue]) -> Result<(), DriverError> {
+        let bus_address = self.bus_address(channel, values.len())?;
+        self.dmx_bus_ref
+            .borrow_mut()
+            .write_channels(bus_address, values)?;
+        for (i, value) in values.iter().enumerate() {
+            self.value[channel as usize + i] = *value;
+        }
+        Ok(())
+    }
+
+    /// check if the channels are out of range
+    fn check_channels(&self, channel: DmxAddress, length: usize) -> Result<(), String> {
+        if channel as usize + length > self.channel_num as usize {
+            return Err(format!("channelled device set_channels failed, channel out of range, channel = {}, length = {}, channel_num = {}, device_id = {}", channel, length, self.channel_num, self.device_id));
+        }
+        Ok(())
+    }
+
+    /// check if the channels are out of range, and get the address on dmx bus
+    fn bus_address(&self, channel: DmxAddress, length: usize) -> Result<DmxAddress, DriverError> {
+        self.check_channels(channel, length).map_err(DriverError)?;
+        self.address.checked_add(channel).ok_or_else(|| {
+            DriverError(format!("channelled device set_channels failed, bus address overflow, address = {}, channel = {}, device_id = {}", self.address, channel, self.device_id))
+        })
+    }
+
+    /// set all channels of the device to the same value
+    pub fn set_all(&mut self, value: DmxValue) -> Result<(), DriverError> {
+        let values = vec![value; self.channel_num as usize];
+        self.set_channels(0, &values)
+    }
+
+    /// set all channels of the device to zero
+    pub fn blackout(&mut self) -> Result<(), DriverError> {
+        self.set_all(0)
+    }
 }
 
 impl ReportUpward for DmxChannelDevice {
@@ -71,10 +139,6 @@ impl ReportUpward for DmxChannelDevice {
     }
 
     fn report(&self) -> Result<(), DriverError> {
-        let state_dto = ChannelStateDto {
-            address: self.address,
-            channels: self.value.clone(),
-        };
         self.notify_upward(StateToDeviceControllerDto {
             device_id: self.device_id.clone(),
             device_class: DEVICE_CLASS.to_string(),
@@ -83,7 +147,7 @@ impl ReportUpward for DmxChannelDevice {
                 error_msg: self.error_msg.clone(),
                 error_timestamp: self.error_timestamp,
                 last_update: self.last_update,
-                state: StateDtoEnum::Channel(state_dto),
+                state: self.get_state(),
                 active: true,
             },
         })?;
@@ -91,6 +155,60 @@ impl ReportUpward for DmxChannelDevice {
     }
 }
 
+impl Commandable for DmxChannelDevice {
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        match dto.params {
+            CommandParamsEnum::DmxChannel(DmxChannelCommandEnum::Set(params)) => {
+                self.check_channels(params.channel, params.values.len()).map_err(CommandError::Param)?;
+                match params.fade {
+                    Some(fade) if fade > 0 => self.fade_channels(params.channel, params.values, fade)?,
+                    _ => self.set_channels(params.channel, &params.values)?,
+                }
+            }
+            CommandParamsEnum::DmxChannel(DmxChannelCommandEnum::SetAll(params)) => {
+                self.set_all(params.value)?;
+            }
+            CommandParamsEnum::DmxChannel(DmxChannelCommandEnum::Off) => {
+                self.blackout()?;
+            }
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for DmxChannelDevice: {:?}", dto)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::Channel(ChannelStateDto {
+            address: self.address,
+            channels: self.value.clone(),
+        })
+    }
+}
+
+impl Scheduled for DmxChannelDevice {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.fade.as_ref().map(|fade| fade.next_deadline())
+    }
+
+    fn run_due(&mut self, now: Instant) -> Result<(), DriverError> {
+        let Some(fade) = self.fade.as_mut() else {
+            return Ok(());
+        };
+        if fade.next_deadline() > now {
+            return Ok(());
+        }
+        let channel = fade.channel;
+        let (values, finished) = fade.step(now);
+        if finished {
+            // the final values are reported
+            self.set_channels(channel, &values)
+        } else {
+            self.write_frame(channel, &values)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::common::logger::init_logger;
@@ -0,0 +1,70 @@
+//! dmx crossfade, channel values change linearly from the current values to the target values
+//! the fading frames are written by device thread through Scheduled
+
+use std::time::{Duration, Instant};
+
+use super::prelude::*;
+
+// interval between two fading frames, in millis
+const FADE_STEP_MILLIS: u64 = 40;
+
+pub struct DmxFade {
+    // first channel of the values
+    pub channel: DmxAddress,
+    from: Vec<DmxValue>,
+    to: Vec<DmxValue>,
+    start: Instant,
+    duration: Duration,
+    next_step: Instant,
+}
+
+impl DmxFade {
+    pub fn new(channel: DmxAddress, from: Vec<DmxValue>, to: Vec<DmxValue>, duration: u64, now: Instant) -> Self {
+        DmxFade {
+            channel,
+            from,
+            to,
+            start: now,
+            duration: Duration::from_millis(duration),
+            next_step: now,
+        }
+    }
+
+    /// time of writing the next frame
+    pub fn next_deadline(&self) -> Instant {
+        self.next_step
+    }
+
+    /// values of the frame at the time, and whether fading is finished
+    pub fn step(&mut self, now: Instant) -> (Vec<DmxValue>, bool) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return (self.to.clone(), true);
+        }
+        let ratio = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        let values = self
+            .from
+            .iter()
+            .zip(self.to.iter())
+            .map(|(from, to)| (*from as f64 + (*to as f64 - *from as f64) * ratio).round() as DmxValue)
+            .collect();
+        self.next_step = now + Duration::from_millis(FADE_STEP_MILLIS);
+        (values, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step() {
+        let start = Instant::now();
+        let mut fade = DmxFade::new(2, vec![0, 200], vec![100, 0], 1000, start);
+        assert_eq!(fade.next_deadline(), start);
+        assert_eq!(fade.step(start), (vec![0, 200], false));
+        assert_eq!(fade.step(start + Duration::from_millis(500)), (vec![50, 100], false));
+        assert_eq!(fade.next_deadline(), start + Duration::from_millis(500 + FADE_STEP_MILLIS));
+        assert_eq!(fade.step(start + Duration::from_millis(1200)), (vec![100, 0], true));
+    }
+}
@@ -1,6 +1,7 @@
 use super::entity::*;
 use super::prelude::*;
 use crate::common::error::DriverError;
+use crate::driver::bus_event::BusEventEnum;
 use crate::{debug, info};
 use dmx::DmxTransmitter;
 use std::env;
@@ -14,9 +15,12 @@ const LOOP_INTERVAL: u64 = 100;
 
 pub fn run_loop(
     serial_port_str: &str,
-    channel_data: [DmxValue; DMX_CHANNEL_LEN],
+    // kept by caller, so that the latest data is sent again after restarting
+    channel_data: &mut [DmxValue; DMX_CHANNEL_LEN],
     // dmx 512 值接收器，当 dmx 值改变时接收变量
-    downward_rx: mpsc::Receiver<DmxThreadCommandEnum>,
+    downward_rx: &mpsc::Receiver<DmxThreadCommandEnum>,
+    // bus event of the dmx bus, recovered is pushed after every successful sending
+    on_bus_event: &dyn Fn(BusEventEnum),
 ) -> Result<(), DriverError> {
     let dummy = env::var("dummy").unwrap_or("false".to_string());
 
@@ -36,15 +40,15 @@ pub fn run_loop(
         );
     }
 
-    let mut thread_channel_data = channel_data;
-
     loop {
         debug!(LOG_TAG, "sending dmx data...");
         match dmx_port_option {
             Some(ref mut dmx_port) => {
+                // restart on error, so that the port is opened again
                 dmx_port
-                    .send_dmx_packet(thread_channel_data.as_ref())
-                    .map_err(|e| DriverError(format!("cannot send data to port, port:{}", serial_port_str)))?;
+                    .send_dmx_packet(channel_data.as_ref())
+                    .map_err(|e| DriverError(format!("cannot send data to port, port:{}, err: {}", serial_port_str, e)))?;
+                on_bus_event(BusEventEnum::Recovered);
             }
             None => {
                 info!(LOG_TAG, "dmx worker thread, no port is available, skip this iter");
@@ -57,8 +61,8 @@ pub fn run_loop(
                 match command {
                     DmxThreadCommandEnum::SetChannel(set_channel_bo) => {
                         // check if there is new data, if there is, update dmx data
-                        thread_channel_data.copy_from_slice(&set_channel_bo.channels);
-                        info!(LOG_TAG, "get data from upward channel, relay to dmxbus: data = {:?}", &thread_channel_data);
+                        channel_data.copy_from_slice(&set_channel_bo.channels);
+                        info!(LOG_TAG, "get data from upward channel, relay to dmxbus: data = {:?}", &channel_data);
                     }
 
                     DmxThreadCommandEnum::Stop => {
@@ -68,9 +72,13 @@ pub fn run_loop(
                     }
                 }
             }
-            Err(_) => {
+            Err(mpsc::TryRecvError::Empty) => {
                 debug!(LOG_TAG, "no data, skip");
             }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                info!(LOG_TAG, "stop on dmx bus dropped");
+                break;
+            }
         }
         
         thread::sleep(time::Duration::from_millis(LOOP_INTERVAL));
@@ -94,7 +102,7 @@ mod tests {
         let (downward_tx, downward_rx) = mpsc::channel();
 
         let handle = thread::spawn(move || {
-            run_loop("COM1", [0; DMX_CHANNEL_LEN], downward_rx);
+            run_loop("COM1", &mut [0; DMX_CHANNEL_LEN], &downward_rx, &|_| {});
         });
 
         // downward_tx.send(DmxThreadCommandEnum::Stop).unwrap();
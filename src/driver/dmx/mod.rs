@@ -1,6 +1,7 @@
 pub mod dmx_bus;
 pub mod prelude;
 mod dmx_thread;
+mod dmx_fade;
 mod entity;
 mod traits;
 pub mod dmx_channel_device;
\ No newline at end of file
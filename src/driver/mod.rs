@@ -1,6 +1,7 @@
 /// Driver Mod
 
 pub mod traits;
+pub mod bus_event;
 pub mod traits_dir;
 pub mod device;
 pub mod modbus;
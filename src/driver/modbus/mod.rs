@@ -5,7 +5,7 @@ pub mod modbus_do_controller_coil;
 pub mod modbus_di_port;
 pub mod modbus_do_port;
 pub mod traits;
-mod prelude;
+pub mod prelude;
 pub mod modbus_bus;
 pub mod modbus_do_controller_register;
 pub mod modbus_di_controller_register;
\ No newline at end of file
@@ -7,9 +7,8 @@
 //! - Write operation takes precedence over read operation   
 
 use std::{
-    cell::RefCell,
     sync::{mpsc::Sender, Arc, Mutex},
-    thread,
+    thread::{self, JoinHandle},
 };
 
 use super::{entity::{WriteMultiRegistersDto, WriteSingleRegisterDto}, prelude::*};
@@ -20,23 +19,29 @@ use super::{
     traits::ModbusListener,
 };
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, StateDtoEnum};
-use crate::{common::error::DriverError};
+use crate::common::error::DriverError;
+use crate::common::supervisor::supervise;
+use crate::driver::bus_event::{join_bus_thread, report_bus_event, BusErrorState, BusEventEnum};
 use std::collections::HashMap;
 use std::sync::mpsc;
 use crate::{info, warn, error, trace, debug};
 
 
 const LOG_TAG : &str = "modbus_bus";
+const DEVICE_CLASS: &str = "bus";
+const DEVICE_TYPE: &str = "modbus_bus";
 
 
 pub struct ModbusBus {
     device_id: String,
     serial_port: String,
     baudrate: u32,
-    // Controller hashmap for modbus digital input
-    di_controller_vec: Vec<Box<dyn ModbusListener + Send>>,
+    // Controller hashmap for modbus digital input, shared with device thread for mounting ports
+    di_controller_vec: Vec<Arc<Mutex<dyn ModbusListener + Send>>>,
     // sender to send command to modbus outputing thread
     modbus_thread_command_tx: Option<Sender<ModbusThreadCommandEnum>>,
+    // modbus thread handle, joined on stopping
+    thread_handle: Option<JoinHandle<()>>,
     report_tx: Sender<StateToDeviceControllerDto>,
 }
 
@@ -48,30 +53,38 @@ impl ModbusBus {
 
         let serial_port_clone = self.serial_port.clone();
         let baudrate = self.baudrate;
-        let mut di_controller_map_ref_cell: HashMap<
-            ModbusUnitSize,
-            RefCell<Box<dyn ModbusListener + Send>>,
-        > = HashMap::new();
+        let mut di_controller_map: HashMap<ModbusUnitSize, Arc<Mutex<dyn ModbusListener + Send>>> = HashMap::new();
 
-        // drop all controller form di_controller_vec and push to ref_cell
+        // move all controller from di_controller_vec to the map of modbus thread
         while let Some(controller) = self.di_controller_vec.pop() {
-            let unit = controller.get_unit();
-            di_controller_map_ref_cell.insert(unit, RefCell::new(controller));
+            let unit = controller.lock().unwrap_or_else(|e| e.into_inner()).get_unit();
+            di_controller_map.insert(unit, controller);
         }
 
-        // start running loop
-        let _ = thread::spawn(move || {
+        let device_id = self.device_id.clone();
+        let report_tx = self.report_tx.clone();
+
+        // start running loop, restart on error
+        self.thread_handle = Some(thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                let _ = run_loop(
-                    serial_port_clone.as_str(),
-                    baudrate,
-                    rx,
-                    di_controller_map_ref_cell,
-                )
-                .await;
-            });
-        });
+            let error_state = Mutex::new(BusErrorState::default());
+            let on_bus_event = |event: BusEventEnum| {
+                report_bus_event(&error_state, &report_tx, &device_id, DEVICE_CLASS, DEVICE_TYPE, event);
+            };
+            supervise(
+                &format!("modbus_bus:{}", device_id),
+                |error_msg| on_bus_event(BusEventEnum::Error(error_msg.to_string())),
+                || {
+                    rt.block_on(run_loop(
+                        serial_port_clone.as_str(),
+                        baudrate,
+                        &rx,
+                        &di_controller_map,
+                        &on_bus_event,
+                    ))
+                },
+            );
+        }));
 
         self.modbus_thread_command_tx = Some(tx);
 
@@ -80,6 +93,20 @@ impl ModbusBus {
         Ok(())
     }
 
+    /// stop the modbus thread and wait for it to exit, the bus can not be used after stopping
+    pub fn stop(&mut self) -> Result<(), DriverError> {
+        match self.modbus_thread_command_tx.take() {
+            Some(tx) => {
+                tx.send(ModbusThreadCommandEnum::Stop)
+                    .map_err(|e| DriverError(format!("modbus bus: send stop command to thread error: {}", e)))?;
+                join_bus_thread(self.thread_handle.take(), &self.device_id)?;
+                info!(LOG_TAG, "modbus thread stopped, port: {}", &self.serial_port);
+                Ok(())
+            }
+            None => Err(DriverError("modbus bus: thread tx is none".to_string())),
+        }
+    }
+
     pub fn new(
         device_id: &str,
         serial_port: &str,
@@ -92,6 +119,7 @@ impl ModbusBus {
             baudrate: baudrate,
             di_controller_vec: Vec::new(),
             modbus_thread_command_tx: None,
+            thread_handle: None,
             report_tx,
         }
     }
@@ -101,7 +129,7 @@ impl ModbusBus {
     pub fn add_di_controller(
         &mut self,
         unit: ModbusUnitSize,
-        controller: Box<dyn ModbusListener + Send>,
+        controller: Arc<Mutex<dyn ModbusListener + Send>>,
     ) {
         self.di_controller_vec.push(controller);
     }
@@ -1,6 +1,7 @@
 use std::sync::mpsc::Sender;
 use std::{collections::HashMap, hash::Hash};
 use crate::common::error::DriverError;
+use crate::driver::bus_event::{BusErrorState, BusEventEnum};
 use crate::driver::traits::ReportUpward;
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, DiControllerStateDto, StateDtoEnum};
@@ -24,9 +25,11 @@ pub struct ModbusDiControllerCoil {
     mount_port_map:  HashMap<ModbusAddrSize, Box<dyn ModbusDiControllerListener + Send>>,
     // port state cache
     port_state_vec: Vec<bool>,
+    // all ports are notified on the first polling, so that the initial state is known
+    polled: bool,
     report_tx: Sender<StateToDeviceControllerDto>,
-    error_msg: Option<String>,
-    error_timestamp: Option<u64>,
+    // error_msg and error_timestamp, set when reading from modbus fails
+    error_state: BusErrorState,
     last_update: Option<u64>,
 }
 
@@ -44,9 +47,9 @@ impl ReportUpward for ModbusDiControllerCoil {
             device_class: DEVICE_CLASS.to_string(),
             device_type: DEVICE_TYPE.to_string(),
             status: DeviceReportDto{
-                active: true,
-                error_msg: self.error_msg.clone(),
-                error_timestamp: self.error_timestamp.clone(),
+                active: self.error_state.is_active(),
+                error_msg: self.error_state.error_msg.clone(),
+                error_timestamp: self.error_state.error_timestamp,
                 last_update: self.last_update.clone(),
                 state: StateDtoEnum::DiController(state_dto.clone())
             }
@@ -76,33 +79,41 @@ impl ModbusListener for ModbusDiControllerCoil {
     }
 
     /// read data from modbus and relay to port object
-    /// - if data not change, do nothing
-    /// - if data changed, notify port object
+    /// - address is the port address of the first value
+    /// - if data not change, do nothing, except the first polling
+    /// - if data changed, notify port object of the changed address, ports not mounted are ignored
     /// - TODO 优化：可将缓存的数据和传入的数据保存为按位的整型，然后按位比较，可更快找到差异位置，然后通知下游
     fn notify_from_bus(&mut self, address: ModbusAddrSize, messages: Vec<bool>) -> Result<(), DriverError> {
 
         debug!(LOG_TAG, "received from modbus, address: {}, messages: {:?}", &address, &messages);
 
-        // check if there is device exist, if there is not, ignore
-        if !self.mount_port_map.contains_key(&address) {
-            debug!(LOG_TAG, "cannot find mount port, address: {}", &address);
-            return Ok(())
-        }
-
-        // check if data changed
-        let port = self.mount_port_map.get(&address).ok_or(DriverError("DiController 接收到的消息，没有找到对应的端口".to_string()))?;
-        for (i, message) in messages.iter().enumerate() {
-            if self.port_state_vec[i] != *message {
-                // if data changed, notify port object
-                self.notify_port(address, *message)?;
-                debug!(LOG_TAG, "port status changed, address: {}, messages: {:?}", &address, &messages);
+        // check if data changed, values out of input_num are ignored
+        for (i, message) in messages.into_iter().enumerate() {
+            let port_address = address + i as ModbusAddrSize;
+            match self.port_state_vec.get_mut(port_address as usize) {
+                Some(state) if !self.polled || *state != message => {
+                    // if data changed, notify port object
+                    *state = message;
+                    self.notify_port(port_address, message)?;
+                    debug!(LOG_TAG, "port status changed, address: {}, message: {}", &port_address, message);
+                }
+                _ => {}
             }
         }
-        self.port_state_vec = messages.clone();
+        self.polled = true;
         self.report()?;
         Ok(())
     }
 
+    /// reading failed or recovered, report when the error changes
+    fn notify_bus_event(&mut self, event: BusEventEnum) -> Result<(), DriverError> {
+        if self.error_state.apply(&event) {
+            warn!(LOG_TAG, "bus event, device_id: {}, event: {:?}", &self.device_id, &event);
+            self.report()?;
+        }
+        Ok(())
+    }
+
     /// notify modbus port
     fn notify_port(&self, address: ModbusAddrSize, message: bool) -> Result<(), DriverError> {
         // check if the port exists
@@ -125,9 +136,9 @@ impl ModbusDiControllerCoil {
             input_num,
             mount_port_map: HashMap::new(),
             port_state_vec: vec![false; input_num as usize],
+            polled: false,
             report_tx,
-            error_msg: None,
-            error_timestamp: None,
+            error_state: BusErrorState::default(),
             last_update: None,
         }
     }
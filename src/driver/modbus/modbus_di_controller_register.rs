@@ -1,6 +1,7 @@
 use std::sync::mpsc::Sender;
 use std::{collections::HashMap, hash::Hash};
 use crate::common::error::DriverError;
+use crate::driver::bus_event::{BusErrorState, BusEventEnum};
 use crate::driver::traits::ReportUpward;
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, DiControllerStateDto, StateDtoEnum};
@@ -25,8 +26,8 @@ pub struct ModbusDiControllerRegsiter {
     // port state cache
     port_state_vec: Vec<bool>,
     report_tx: Sender<StateToDeviceControllerDto>,
-    error_msg: Option<String>,
-    error_timestamp: Option<u64>,
+    // error_msg and error_timestamp, set when reading from modbus fails
+    error_state: BusErrorState,
     last_update: Option<u64>,
 }
 
@@ -44,9 +45,9 @@ impl ReportUpward for ModbusDiControllerRegsiter {
             device_class: DEVICE_CLASS.to_string(),
             device_type: DEVICE_TYPE.to_string(),
             status: DeviceReportDto{
-                active: true,
-                error_msg: self.error_msg.clone(),
-                error_timestamp: self.error_timestamp.clone(),
+                active: self.error_state.is_active(),
+                error_msg: self.error_state.error_msg.clone(),
+                error_timestamp: self.error_state.error_timestamp,
                 last_update: self.last_update.clone(),
                 state: StateDtoEnum::DiController(state_dto.clone())
             }
@@ -103,6 +104,15 @@ impl ModbusListener for ModbusDiControllerRegsiter {
         Ok(())
     }
 
+    /// reading failed or recovered, report when the error changes
+    fn notify_bus_event(&mut self, event: BusEventEnum) -> Result<(), DriverError> {
+        if self.error_state.apply(&event) {
+            warn!(LOG_TAG, "bus event, device_id: {}, event: {:?}", &self.device_id, &event);
+            self.report()?;
+        }
+        Ok(())
+    }
+
     /// notify modbus port
     fn notify_port(&self, address: ModbusAddrSize, message: bool) -> Result<(), DriverError> {
         // check if the port exists
@@ -126,8 +136,7 @@ impl ModbusDiControllerRegsiter {
             mount_port_map: HashMap::new(),
             port_state_vec: vec![false; input_num as usize],
             report_tx,
-            error_msg: None,
-            error_timestamp: None,
+            error_state: BusErrorState::default(),
             last_update: None,
         }
     }
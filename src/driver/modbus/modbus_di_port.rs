@@ -4,8 +4,7 @@ use crate::common::error::DriverError;
 use crate::driver::traits::ReportUpward;
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, DiStateDto, StateDtoEnum};
-use crate::{debug, error, info, trace, warn};
-use std::env;
+use crate::debug;
 use std::sync::mpsc;
 
 const DEVICE_TYPE: &str = "modbus_di_port";
@@ -30,35 +29,26 @@ impl ModbusDiControllerListener for ModbusDiPort {
         self.address
     }
 
+    /// relay the changed value upward, modbus bus does not poll in dummy mode
     fn notify(&self, state_value: bool) -> Result<(), DriverError> {
-        let env_mode = env::var("mode").unwrap_or("real".to_string());
-
-        if env_mode == "dummy" {
-            info!(
-                LOG_TAG,
-                "modbus di port is in dummy mode, receive remote data: {:?}", &state_value
-            );
-        } else {
-            let state = StateDtoEnum::Di(DiStateDto { on: state_value });
-            let device_state_dto = StateToDeviceControllerDto {
-                device_id: self.device_id.clone(),
-                device_class: DEVICE_CLASS.to_string(),
-                device_type: DEVICE_TYPE.to_string(),
-                status: DeviceReportDto {
-                    state,
-                    error_msg: self.error_msg.clone(),
-                    error_timestamp: self.error_timestamp,
-                    last_update: self.last_update,
-                    active: true,
-                }
-            };
-            let _ = self.notify_upward(device_state_dto)?;
-            debug!(
-                LOG_TAG,
-                "di port state change, relay to upward, address: {}, message: {}", &self.address, state_value
-            );
-        }
-
+        let state = StateDtoEnum::Di(DiStateDto { on: state_value });
+        let device_state_dto = StateToDeviceControllerDto {
+            device_id: self.device_id.clone(),
+            device_class: DEVICE_CLASS.to_string(),
+            device_type: DEVICE_TYPE.to_string(),
+            status: DeviceReportDto {
+                state,
+                error_msg: self.error_msg.clone(),
+                error_timestamp: self.error_timestamp,
+                last_update: self.last_update,
+                active: true,
+            }
+        };
+        self.notify_upward(device_state_dto)?;
+        debug!(
+            LOG_TAG,
+            "di port state change, relay to upward, address: {}, message: {}", &self.address, state_value
+        );
         Ok(())
     }
 }
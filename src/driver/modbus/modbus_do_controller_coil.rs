@@ -6,8 +6,9 @@ use super::modbus_bus::ModbusBus;
 use super::prelude::*;
 use super::traits::{ModbusCaller, ModbusDoControllerCaller};
 use std::sync::mpsc::{self, Sender};
-use crate::common::error::DriverError;
-use crate::driver::traits::{Refable, ReportUpward};
+use crate::common::error::{CommandError, DriverError};
+use crate::driver::traits::{Commandable, Refable, ReportUpward};
+use crate::entity::dto::device_command_dto::{CommandParamsEnum, DeviceCommandDto, DoControllerCommandEnum};
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, DoControllerStateDto, StateDtoEnum};
 
@@ -70,10 +71,6 @@ impl ReportUpward for ModbusDoControllerCoil {
     }
 
     fn report(&self) -> Result<(), DriverError> {
-        let state_dto = DoControllerStateDto {
-            port: self.port_state_vec.clone()
-        };
-        
         self.notify_upward(StateToDeviceControllerDto{
             device_id: self.device_id.clone(),
             device_class: DEVICE_CLASS.to_string(),
@@ -83,13 +80,38 @@ impl ReportUpward for ModbusDoControllerCoil {
                 error_timestamp: self.error_timestamp,
                 last_update: self.last_update,
                 active: true,
-                state: StateDtoEnum::DoController(state_dto),
+                state: self.get_state(),
             }
         })?;
         Ok(())
     }
 }
 
+impl Commandable for ModbusDoControllerCoil {
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        match dto.params {
+            CommandParamsEnum::DoController(DoControllerCommandEnum::Write(params)) => {
+                self.check_address(params.address, 1).map_err(CommandError::Param)?;
+                self.write_one_port(params.address, params.value)?;
+            }
+            CommandParamsEnum::DoController(DoControllerCommandEnum::WriteMulti(params)) => {
+                self.check_address(params.address, params.values.len()).map_err(CommandError::Param)?;
+                self.write_multi_ports(params.address, &params.values)?;
+            }
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for ModbusDoController: {:?}", dto)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::DoController(DoControllerStateDto {
+            port: self.port_state_vec.clone()
+        })
+    }
+}
+
 impl ModbusDoControllerCoil {
     pub fn new(
         device_id: &str,
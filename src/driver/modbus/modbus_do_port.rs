@@ -1,14 +1,16 @@
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::env;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
 use super::modbus_do_controller_coil::ModbusDoControllerCoil;
 use super::prelude::*;
 use super::traits::{ModbusCaller, ModbusDoControllerCaller};
-use crate::common::error::DriverError;
-use crate::driver::traits::{Commandable, ReportUpward};
-use crate::entity::dto::device_command_dto::DeviceCommandDto;
+use crate::common::error::{CommandError, DriverError};
+use crate::driver::traits::{Commandable, ReportUpward, Scheduled};
+use crate::entity::dto::device_command_dto::{CommandParamsEnum, DeviceCommandDto, DoBlinkParamsDto, DoPortCommandEnum};
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, DoStateDto, StateDtoEnum};
 use crate::{info, warn};
@@ -23,6 +25,10 @@ pub struct ModbusDoPort {
     controller_ref: Rc<RefCell<ModbusDoControllerCoil>>,
     report_tx: Sender<StateToDeviceControllerDto>,
     on: bool,
+    // default duration of pulse, in millis
+    pulse_time: u64,
+    // scheduled switching in time order, cleared by any new command or explicit write
+    schedule: VecDeque<(Instant, bool)>,
     error_msg: Option<String>,
     error_timestamp: Option<u64>,
     last_update: Option<u64>,
@@ -32,6 +38,7 @@ impl ModbusDoPort {
     pub fn new(
         device_id: &str,
         address: ModbusAddrSize,
+        pulse_time: u64,
         controller_ref: Rc<RefCell<ModbusDoControllerCoil>>,
         report_tx: Sender<StateToDeviceControllerDto>,
     ) -> Self {
@@ -41,11 +48,83 @@ impl ModbusDoPort {
             controller_ref,
             report_tx,
             on: false,
+            pulse_time,
+            schedule: VecDeque::new(),
             error_msg: None,
             error_timestamp: None,
             last_update: None,
         }
     }
+
+    /// switch the port on or off, the scheduled switching is canceled
+    pub fn set_on(&mut self, on: bool) -> Result<(), DriverError> {
+        self.schedule.clear();
+        self.switch(on)
+    }
+
+    /// switch the port without touching the schedule
+    fn switch(&mut self, on: bool) -> Result<(), DriverError> {
+        self.on = on;
+        self.write(on)
+    }
+
+    /// switch on now, and switch off after duration
+    fn on_for(&mut self, duration: u64) -> Result<(), DriverError> {
+        self.set_on(true)?;
+        self.schedule.push_back((Instant::now() + Duration::from_millis(duration), false));
+        Ok(())
+    }
+
+    /// switch on and off for count times, the port is on for half of the period
+    fn blink(&mut self, params: DoBlinkParamsDto) -> Result<(), CommandError> {
+        if params.period < 2 || params.count == 0 {
+            return Err(CommandError::Param(format!(
+                "ModbusDoPort: invalid blink param, period should be at least 2 ms and count should be positive, device_id={}, param={:?}",
+                &self.device_id, params
+            )));
+        }
+        let start = Instant::now();
+        let period = Duration::from_millis(params.period);
+        let half_period = period / 2;
+        self.set_on(true)?;
+        for i in 0..params.count {
+            let cycle_start = start + period * i;
+            if i > 0 {
+                self.schedule.push_back((cycle_start, true));
+            }
+            self.schedule.push_back((cycle_start + half_period, false));
+        }
+        Ok(())
+    }
+
+    /// switch the port on or off, the port is written even if the state is not changed
+    /// the scheduled switching is canceled
+    pub fn sync_on(&mut self, on: bool) -> Result<(), DriverError> {
+        self.schedule.clear();
+        self.on = on;
+        self.write_port(on, true)
+    }
+
+    /// write the port through controller, and report the new state
+    fn write_port(&self, value: bool, force: bool) -> Result<(), DriverError> {
+        let dummy = env::var("dummy").unwrap_or("false".to_string());
+        if let Ok(mut controller) = self.controller_ref.try_borrow_mut() {
+            if dummy == "true" {
+                info!(LOG_TAG, "**DUMMY MODE** ModbusDoPort: write dummy, address={}, value={}", self.address, value);
+            } else if force {
+                controller.sync_one_port(self.address, value)?;
+            } else {
+                controller.write_one_port(self.address, value)?;
+            }
+        } else {
+            return Err(DriverError(format!(
+                "ModbusDoPort: controller borrow failed, cannot write data, device_id={}",
+                &self.device_id
+            )));
+        }
+        self.report()?;
+        Ok(())
+    }
 }
 
 impl ReportUpward for ModbusDoPort {
@@ -54,9 +133,6 @@ impl ReportUpward for ModbusDoPort {
     }
 
     fn report(&self) -> Result<(), DriverError> {
-        let state_dto = DoStateDto {
-            on: self.on,
-        };
         self.notify_upward(StateToDeviceControllerDto {
             device_id: self.device_id.clone(),
             device_class: DEVICE_CLASS.to_string(),
@@ -65,7 +141,7 @@ impl ReportUpward for ModbusDoPort {
                 error_msg: self.error_msg.clone(),
                 error_timestamp: self.error_timestamp,
                 last_update: self.last_update,
-                state: StateDtoEnum::Do(state_dto),
+                state: self.get_state(),
                 active: true
             }
         })?;
@@ -74,15 +150,48 @@ impl ReportUpward for ModbusDoPort {
 }
 
 impl Commandable for ModbusDoPort {
-    fn cmd (&mut self, dto: DeviceCommandDto) -> Result<(), DriverError> {
-        if dto.action == "on" {
-            self.on = true;
-            self.write(true)?;
-        } else if dto.action == "off" {
-            self.on = false;
-            self.write(false)?;
-        } else {
-            return Err(DriverError(format!("invalid action for ModbusDoPort: {}", dto.action)));
+    /// new command replaces the scheduled switching, invalid command keeps it
+    fn cmd (&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        match dto.params {
+            CommandParamsEnum::DoPort(DoPortCommandEnum::On) => self.set_on(true)?,
+            CommandParamsEnum::DoPort(DoPortCommandEnum::Off) => self.set_on(false)?,
+            CommandParamsEnum::DoPort(DoPortCommandEnum::Toggle) => self.set_on(!self.on)?,
+            CommandParamsEnum::DoPort(DoPortCommandEnum::Pulse(params)) => {
+                let duration = params.map_or(self.pulse_time, |params| params.duration);
+                self.on_for(duration)?
+            }
+            CommandParamsEnum::DoPort(DoPortCommandEnum::OnFor(params)) => self.on_for(params.duration)?,
+            CommandParamsEnum::DoPort(DoPortCommandEnum::Blink(params)) => self.blink(params)?,
+            CommandParamsEnum::DoPort(DoPortCommandEnum::Cancel) => {
+                self.schedule.clear();
+                info!(LOG_TAG, "scheduled switching canceled, device_id={}", &self.device_id);
+            }
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for ModbusDoPort: {:?}", dto)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::Do(DoStateDto {
+            on: self.on,
+        })
+    }
+}
+
+impl Scheduled for ModbusDoPort {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.schedule.front().map(|(deadline, _)| *deadline)
+    }
+
+    fn run_due(&mut self, now: Instant) -> Result<(), DriverError> {
+        while let Some((deadline, on)) = self.schedule.front().cloned() {
+            if deadline > now {
+                break;
+            }
+            self.schedule.pop_front();
+            self.switch(on)?;
         }
         Ok(())
     }
@@ -94,21 +203,7 @@ impl ModbusDoControllerCaller for ModbusDoPort {
     }
 
     fn write(&self, value: bool) -> Result<(), DriverError> {
-        let dummy = env::var("dummy").unwrap_or("false".to_string());
-        if let Ok(mut controller) = self.controller_ref.try_borrow_mut() {
-            if dummy == "true" {
-                info!(LOG_TAG, "**DUMMY MODE** ModbusDoPort: write dummy, address={}, value={}", self.address, value);
-            } else {
-                controller.write_one_port(self.address, value)?;
-            }
-        } else {
-            return Err(DriverError(format!(
-                "ModbusDoPort: controller borrow failed, cannot write data, device_id={}",
-                &self.device_id
-            )));
-        }
-        self.report()?;
-        Ok(())
+        self.write_port(value, false)
     }
 }
 
@@ -116,18 +211,96 @@ impl ModbusDoControllerCaller for ModbusDoPort {
 mod test {
     use super::super::modbus_bus::ModbusBus;
     use super::*;
+    use crate::entity::dto::device_command_dto::{DoDurationParamsDto, DoBlinkParamsDto};
     use std::env;
     use std::sync::mpsc;
 
+    fn make_port() -> (ModbusDoPort, mpsc::Receiver<StateToDeviceControllerDto>) {
+        env::set_var("dummy", "true");
+        let (tx, rx) = mpsc::channel();
+        let modbus = Rc::new(RefCell::new(ModbusBus::new("bus", "/dev/null", 9600, tx.clone())));
+        let controller = Rc::new(RefCell::new(ModbusDoControllerCoil::new("controller", 1, 8, modbus, tx.clone())));
+        (ModbusDoPort::new("port", 1, 1000, controller, tx), rx)
+    }
+
+    fn command(port: &mut ModbusDoPort, params: DoPortCommandEnum) -> Result<(), CommandError> {
+        port.cmd(DeviceCommandDto {
+            server_id: "server".to_string(),
+            device_id: "port".to_string(),
+            device_type: DEVICE_TYPE.to_string(),
+            session_id: "session".to_string(),
+            action: String::new(),
+            params: CommandParamsEnum::DoPort(params),
+        })
+    }
+
+    fn is_on(port: &ModbusDoPort) -> bool {
+        matches!(port.get_state(), StateDtoEnum::Do(DoStateDto { on: true }))
+    }
+
+    #[test]
+    fn test_pulse() {
+        let (mut port, _rx) = make_port();
+        let start = Instant::now();
+        command(&mut port, DoPortCommandEnum::Pulse(None)).unwrap();
+        assert!(is_on(&port));
+        let deadline = port.next_deadline().unwrap();
+        assert!(deadline >= start + Duration::from_millis(1000));
+
+        port.run_due(deadline - Duration::from_millis(1)).unwrap();
+        assert!(is_on(&port));
+        port.run_due(deadline).unwrap();
+        assert!(!is_on(&port));
+        assert_eq!(port.next_deadline(), None);
+    }
+
     #[test]
-    fn test_modbus_do_port_new() {
-        // env::set_var("dummy", "true");
-        // let modbus = ModbusBus::new("test", "/dev/null", 9600);
-        // let controller = ModbusDoController::new(
-        //     "test", 1, 8, Rc::new(modbus)
-        // );
-        // let port = ModbusDoPort::new("test", 1, Rc::new(controller));
-
-        // port.write(true).unwrap();
+    fn test_on_for() {
+        let (mut port, _rx) = make_port();
+        let start = Instant::now();
+        command(&mut port, DoPortCommandEnum::OnFor(DoDurationParamsDto { duration: 300 })).unwrap();
+        let deadline = port.next_deadline().unwrap();
+        assert!(deadline >= start + Duration::from_millis(300) && deadline < start + Duration::from_millis(1000));
+        port.run_due(deadline).unwrap();
+        assert!(!is_on(&port));
+    }
+
+    #[test]
+    fn test_blink() {
+        let (mut port, _rx) = make_port();
+        command(&mut port, DoPortCommandEnum::Blink(DoBlinkParamsDto { period: 100, count: 2 })).unwrap();
+        assert!(is_on(&port));
+        // off, on, off
+        let mut switched = Vec::new();
+        while let Some(deadline) = port.next_deadline() {
+            port.run_due(deadline).unwrap();
+            switched.push(is_on(&port));
+        }
+        assert_eq!(switched, vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (mut port, _rx) = make_port();
+        command(&mut port, DoPortCommandEnum::Blink(DoBlinkParamsDto { period: 100, count: 3 })).unwrap();
+
+        // invalid command keeps the running blink
+        assert!(matches!(
+            command(&mut port, DoPortCommandEnum::Blink(DoBlinkParamsDto { period: 1, count: 3 })),
+            Err(CommandError::Param(_))
+        ));
+        assert!(port.next_deadline().is_some());
+
+        command(&mut port, DoPortCommandEnum::Cancel).unwrap();
+        assert_eq!(port.next_deadline(), None);
+        assert!(is_on(&port));
+
+        // explicit write, e.g. broadcast all_off, cancels the schedule
+        command(&mut port, DoPortCommandEnum::Pulse(None)).unwrap();
+        port.set_on(false).unwrap();
+        assert_eq!(port.next_deadline(), None);
+        command(&mut port, DoPortCommandEnum::Pulse(None)).unwrap();
+        port.sync_on(false).unwrap();
+        assert_eq!(port.next_deadline(), None);
     }
 }
@@ -4,6 +4,7 @@
 //! 如有需要写入接口的数据，则在循环中断，并写入数据
 
 use crate::common::error::DriverError;
+use crate::driver::bus_event::BusEventEnum;
 
 use super::prelude::*;
 use super::{
@@ -11,7 +12,10 @@ use super::{
     traits::{ModbusControllerType, ModbusListener},
 };
 use crate::{debug, error, info, trace, warn};
-use std::{cell::RefCell, collections::HashMap, sync::mpsc::Receiver};
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Receiver, Arc, Mutex},
+};
 use tokio_modbus::{client::Context, prelude::*, Slave};
 use tokio_serial::SerialStream;
 
@@ -24,11 +28,13 @@ const MODBUS_POLLING_INTERVAL: u64 = 100;
 pub async fn run_loop(
     serial_port: &str,
     baudrate: u32,
-    command_rx: Receiver<ModbusThreadCommandEnum>,
+    command_rx: &Receiver<ModbusThreadCommandEnum>,
 
     // di controller map, used for polling
-    // inner mutable: because we need to call ModbusDigitalInputMountable object
-    di_controller_map: HashMap<ModbusUnitSize, RefCell<Box<dyn ModbusListener + Send>>>,
+    // shared with device thread, the lock is not held while reading from modbus
+    di_controller_map: &HashMap<ModbusUnitSize, Arc<Mutex<dyn ModbusListener + Send>>>,
+    // bus event of the modbus bus itself
+    on_bus_event: &dyn Fn(BusEventEnum),
 ) -> Result<(), DriverError> {
     let mut context: Option<Context> = None;
 
@@ -46,6 +52,8 @@ pub async fn run_loop(
         let slave = Slave::broadcast();
         context = Some(rtu::attach_slave(port, slave));
     }
+    // the port is opened, clear the error of last failure
+    on_bus_event(BusEventEnum::Recovered);
 
     loop {
         // send command to modbus thread
@@ -96,42 +104,39 @@ pub async fn run_loop(
         }
 
         // if there is no command received, it will poll all input devices
-        // 对 controller_map 轮询
-        for unit_key in di_controller_map.keys() {
-            let controller_cell = di_controller_map.get(unit_key).ok_or(DriverError(format!(
-                "modbus worker，cannot get controller, exception: {}",
-                unit_key
-            )))?;
-            let mut controller = controller_cell.borrow_mut();
-            let unit = controller.get_unit();
-            let port_num = controller.get_port_num();
-            let controller_type = controller.get_controller_type();
-            let mut result = Ok(vec![]);
-            let context_ref = context.as_mut();
+        // 对 controller_map 轮询，dummy mode has no port to poll
+        for controller_lock in di_controller_map.values() {
+            let Some(context_ref) = context.as_mut() else {
+                break;
+            };
+            let (unit, port_num, controller_type) = {
+                let controller = controller_lock.lock().unwrap_or_else(|e| e.into_inner());
+                (controller.get_unit(), controller.get_port_num(), controller.get_controller_type())
+            };
 
             // read input value according to which type of controller
-            match controller_type {
-                ModbusControllerType::Coil => {
-                    // read port status from modbus
-                    result = read_coils(&mut context_ref.unwrap(), unit, 0, port_num).await;
-                }
+            let result = match controller_type {
+                // read port status from modbus
+                ModbusControllerType::Coil => read_coils(context_ref, unit, 0, port_num).await,
                 ModbusControllerType::Register => {
-                    let result_u16 =
-                        read_input_registers(&mut context_ref.unwrap(), unit, 0, port_num).await;
-                    result = result_u16.map(vec_u16_to_bool);
+                    read_input_registers(context_ref, unit, 0, port_num).await.map(vec_u16_to_bool)
                 }
-            }
+            };
 
+            let mut controller = controller_lock.lock().unwrap_or_else(|e| e.into_inner());
             match result {
                 Ok(ret) => {
-                    // relay data to controller
-                    controller.notify_from_bus(*unit_key as ModbusAddrSize, ret)?;
+                    // relay data to controller, the ports are read from address 0
+                    controller.notify_bus_event(BusEventEnum::Recovered)?;
+                    controller.notify_from_bus(0, ret)?;
                 }
                 Err(e) => {
                     error!(
                         LOG_TAG,
                         "modbus worker thread, reading modbus port failed {}", e
-                    )
+                    );
+                    // push the error to the controller, so that it is reported as inactive
+                    controller.notify_bus_event(BusEventEnum::Error(e.0))?;
                 }
             }
         }
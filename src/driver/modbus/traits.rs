@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 
 use super::{modbus_bus::ModbusBus, prelude::*};
-use crate::{common::error::DriverError, driver::traits::ReportUpward};
+use crate::{common::error::DriverError, driver::{bus_event::BusEventEnum, traits::ReportUpward}};
 
 // ================= di ====================
 
@@ -34,6 +34,9 @@ pub trait ModbusListener {
         values: Vec<bool>,
     ) -> Result<(), DriverError>;
 
+    /// reading from modbus failed or recovered
+    fn notify_bus_event(&mut self, event: BusEventEnum) -> Result<(), DriverError>;
+
     /// relay data to port device object
     fn notify_port(&self, address: ModbusAddrSize, values: bool) -> Result<(), DriverError>;
 }
@@ -65,20 +68,28 @@ pub trait ModbusCaller: ReportUpward{
         values: &[bool],
     ) -> Result<(), DriverError>;
 
+    /// check if the ports to write are in range
+    fn check_address(&self, address: ModbusAddrSize, length: usize) -> Result<(), String> {
+        if address as usize + length > self.get_output_num() as usize {
+            return Err(format!(
+                "ModbusDoController: writing address out of range, device_id: {}, address: {}, length: {}",
+                self.get_device_id(), address, length
+            ));
+        }
+        Ok(())
+    }
+
     fn write_one_port(&mut self, address: ModbusAddrSize, value: bool) -> Result<(), DriverError> {
         // check address range
-        let device_id = self.get_device_id();
-        if address >= self.get_output_num() {
-            return Err(DriverError(format!("ModbusDoController: writing address out of range, device_id: {}, address: {}, value: {}", &device_id, address, value)));
-        }
+        self.check_address(address, 1).map_err(DriverError)?;
 
         // check if the value is different
         let port_state_vec = self.get_port_state_vec_ref();
+        let is_diff = port_state_vec[address as usize] != value;
         // update port state
         port_state_vec[address as usize] = value;
-        let port_state = & port_state_vec[address as usize];
 
-        if *port_state != value {
+        if is_diff {
             let _ = self.set_port(address, value)?;
         }
 
@@ -86,16 +97,23 @@ pub trait ModbusCaller: ReportUpward{
         Ok(())
     }
 
+    /// write one port even if the cached state is the same
+    /// used when the real port state is unknown, e.g. restoring state after restarting
+    fn sync_one_port(&mut self, address: ModbusAddrSize, value: bool) -> Result<(), DriverError> {
+        self.check_address(address, 1).map_err(DriverError)?;
+        self.get_port_state_vec_ref()[address as usize] = value;
+        self.set_port(address, value)?;
+        self.report()?;
+        Ok(())
+    }
+
     fn write_multi_ports(
         &mut self,
         address: ModbusAddrSize,
         values: &[bool],
     ) -> Result<(), DriverError> {
         // check address range
-        let device_id = self.get_device_id();
-        if address + values.len() as ModbusAddrSize > self.get_output_num() {
-            return Err(DriverError(format!("ModbusDoController: writing address out of range, device_id: {}, address: {}, values: {:?}", &device_id, address, values)));
-        }
+        self.check_address(address, values.len()).map_err(DriverError)?;
 
         // check if the values are different
         let port_state_vec = self.get_port_state_vec_ref();
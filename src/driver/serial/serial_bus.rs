@@ -14,10 +14,14 @@ use super::{
     traits::SerialMountable,
 };
 use crate::{
-    common::error::DriverError,
-    entity::dto::device_state_dto::StateToDeviceControllerDto
+    common::error::{CommandError, DriverError},
+    common::supervisor::supervise,
+    driver::bus_event::{join_bus_thread, report_bus_event, BusErrorState, BusEventEnum},
+    driver::traits::Commandable,
+    entity::dto::device_command_dto::{CommandParamsEnum, DeviceCommandDto, SerialBusCommandEnum},
+    entity::dto::device_state_dto::{StateDtoEnum, StateToDeviceControllerDto},
 };
-use std::sync::mpsc::Sender;
+use std::sync::{mpsc::Sender, Mutex};
 use std::{
     cell::RefCell,
     thread,
@@ -43,15 +47,45 @@ pub struct SerialBus {
     thread_handle: Option<thread::JoinHandle<()>>,
 }
 
+impl Commandable for SerialBus {
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError> {
+        match dto.params {
+            CommandParamsEnum::SerialBus(SerialBusCommandEnum::Send(params)) => {
+                if params.data.len() > u8::MAX as usize {
+                    return Err(CommandError::Param(format!("SerialBus frame data too long, length: {}", params.data.len())));
+                }
+                self.send_data(SerialDataBo {
+                    command: params.command,
+                    data: params.data,
+                })?;
+            }
+            _ => {
+                return Err(CommandError::Param(format!("invalid command data for SerialBus: {:?}", dto)));
+            }
+        }
+        Ok(())
+    }
+
+    /// serial bus has no state
+    fn get_state(&self) -> StateDtoEnum {
+        StateDtoEnum::Empty
+    }
+}
+
 impl SerialBus {
-    pub fn new(device_id: &str, serial_port: &str, baudrate: u32) -> SerialBus {
+    pub fn new(
+        device_id: &str,
+        serial_port: &str,
+        baudrate: u32,
+        report_tx: Sender<StateToDeviceControllerDto>,
+    ) -> SerialBus {
         SerialBus {
             device_id: device_id.to_string(),
             device_class: "bus".to_string(),
             device_type: "serial".to_string(),
             serial_port: serial_port.to_string(),
             baudrate,
-            upward_channel: None,
+            upward_channel: Some(report_tx),
             command_channel_tx: None,
             listeners: Vec::new(),
             thread_handle: None,
@@ -74,12 +108,32 @@ impl SerialBus {
             listeners_ref_cell_vec.push(RefCell::new(listener));
         }
 
+        let device_id = self.device_id.clone();
+        let device_class = self.device_class.clone();
+        let device_type = self.device_type.clone();
+        let upward_channel = self.upward_channel.clone();
+
+        // restart on error
         self.thread_handle = Some(thread::spawn(move || {
-            let _ = run_loop(
-                serial_port_str.as_str(),
-                baudrate,
-                command_channel_rx,
-                listeners_ref_cell_vec,
+            let mut command_channel_rx = command_channel_rx;
+            let error_state = Mutex::new(BusErrorState::default());
+            let on_bus_event = |event: BusEventEnum| {
+                if let Some(tx) = &upward_channel {
+                    report_bus_event(&error_state, tx, &device_id, &device_class, &device_type, event);
+                }
+            };
+            supervise(
+                &format!("serial_bus:{}", device_id),
+                |error_msg| on_bus_event(BusEventEnum::Error(error_msg.to_string())),
+                || {
+                    run_loop(
+                        serial_port_str.as_str(),
+                        baudrate,
+                        &mut command_channel_rx,
+                        &listeners_ref_cell_vec,
+                        &on_bus_event,
+                    )
+                },
             );
         }));
 
@@ -106,7 +160,7 @@ impl SerialBus {
         Ok(())
     }
 
-    /// send stop signal
+    /// send stop signal and wait for the thread to exit
     pub fn stop(&mut self) -> Result<(), DriverError> {
         if let Some(tx) = self.command_channel_tx.as_mut() {
             let _ = tx
@@ -118,7 +172,7 @@ impl SerialBus {
             )));
         }
         self.command_channel_tx = None;
-        Ok(())
+        join_bus_thread(self.thread_handle.take(), &self.device_id)
     }
 
     /// echo testing data
@@ -152,7 +206,8 @@ mod tests {
     #[test]
     fn test_new() {
         set_env();
-        let serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600);
+        let (tx, _rx) = mpsc::channel();
+        let serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600, tx);
     }
 
     /// test with serial remote listener
@@ -160,7 +215,7 @@ mod tests {
     fn test_mount_serial_remote_listener() {
         set_env();
         let (tx, rx) = mpsc::channel();
-        let mut serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600);
+        let mut serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600, tx.clone());
         let mut serial_remote_controller = SerialRemoteController::new(
             "serial_remote_controller_1",
             8,
@@ -179,7 +234,8 @@ mod tests {
     #[test]
     fn test_send_data() {
         set_env();
-        let mut serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600);
+        let (tx, _rx) = mpsc::channel();
+        let mut serial_bus = SerialBus::new("serial_bus_1", "/dev/ttyUSB1", 9600, tx);
         serial_bus.start().unwrap();
         println!("线程已启动");
         std::thread::sleep(std::time::Duration::from_secs(2));
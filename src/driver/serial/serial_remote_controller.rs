@@ -1,6 +1,7 @@
 use std::sync::mpsc::Sender;
 use rodio::Device;
 
+use crate::driver::bus_event::{BusErrorState, BusEventEnum};
 use crate::driver::traits::ReportUpward;
 use crate::entity::dto::device_report_dto::DeviceReportDto;
 use crate::entity::dto::device_state_dto::{StateToDeviceControllerDto, RemoteStateDto, StateDtoEnum};
@@ -18,8 +19,8 @@ pub struct SerialRemoteController {
     device_id: String,
     button_num: u8,
     report_channel: Sender<StateToDeviceControllerDto>,
-    error_msg: Option<String>,
-    error_timestamp: Option<u64>,
+    // error_msg and error_timestamp, set when reading from serial port fails
+    error_state: BusErrorState,
     last_update: Option<u64>,
 }
 
@@ -30,8 +31,7 @@ impl SerialRemoteController {
             device_id: device_id.to_string(),
             button_num,
             report_channel,
-            error_msg: None,
-            error_timestamp: None,
+            error_state: BusErrorState::default(),
             last_update: None,
         }
     }
@@ -52,9 +52,9 @@ impl SerialMountable for SerialRemoteController {
                     device_class: DEVICE_CLASS.to_string(),
                     device_type: DEVICE_TYPE.to_string(),
                     status: DeviceReportDto{
-                        active: true,
-                        error_msg: self.error_msg.clone(),
-                        error_timestamp: self.error_timestamp.clone(),
+                        active: self.error_state.is_active(),
+                        error_msg: self.error_state.error_msg.clone(),
+                        error_timestamp: self.error_state.error_timestamp,
                         last_update: self.last_update.clone(),
                         state: state
                     },
@@ -65,6 +65,26 @@ impl SerialMountable for SerialRemoteController {
         }
         Ok(())
     }
+
+    /// report when the error changes, the last pressed button is kept
+    fn notify_bus_event(&mut self, event: BusEventEnum) -> Result<(), DriverError> {
+        if !self.error_state.apply(&event) {
+            return Ok(());
+        }
+        warn!(LOG_TAG, "bus event, device_id: {}, event: {:?}", &self.device_id, &event);
+        self.notify_upward(StateToDeviceControllerDto {
+            device_id: self.device_id.clone(),
+            device_class: DEVICE_CLASS.to_string(),
+            device_type: DEVICE_TYPE.to_string(),
+            status: DeviceReportDto {
+                active: self.error_state.is_active(),
+                error_msg: self.error_state.error_msg.clone(),
+                error_timestamp: self.error_state.error_timestamp,
+                last_update: self.last_update.clone(),
+                state: StateDtoEnum::Empty,
+            },
+        })
+    }
 }
 
 impl ReportUpward for SerialRemoteController {
@@ -2,6 +2,7 @@ use super::entity::SerialDataBo;
 use super::entity::SerialThreadCommand;
 use super::traits::SerialMountable;
 use crate::common::error::DriverError;
+use crate::driver::bus_event::BusEventEnum;
 use crate::{debug, error, info, trace, warn};
 use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt, TryStreamExt};
@@ -28,9 +29,11 @@ pub fn run_loop(
     serial_port: &str,
     baudrate: u32,
     // the sending command channel
-    mut command_rx: Receiver<SerialThreadCommand>,
+    command_rx: &mut Receiver<SerialThreadCommand>,
     // listeners
-    listener_vec: Vec<RefCell<Box<dyn SerialMountable + Send>>>,
+    listener_vec: &[RefCell<Box<dyn SerialMountable + Send>>],
+    // bus event of the serial bus itself
+    on_bus_event: &dyn Fn(BusEventEnum),
 ) -> Result<(), DriverError> {
     let env_mode = std::env::var("mode").unwrap_or("real".to_string());
     let mut writer_opt: Option<SplitSink<Framed<SerialStream, _>, _>> = None;
@@ -52,11 +55,13 @@ pub fn run_loop(
         writer_opt = Some(writer);
         reader_opt = Some(reader);
     }
+    // the port is opened, clear the error of last failure
+    on_bus_event(BusEventEnum::Recovered);
 
     let rt = tokio::runtime::Runtime::new()
         .expect("PANIC: cannot start serial thread, cannot init tokio runtime");
 
-    let _ = rt.block_on(async {
+    rt.block_on(async {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(LOOP_INTERVAL));
 
         loop {
@@ -86,7 +91,8 @@ pub fn run_loop(
                             }
                         }
                     } else {
-                        warn!(LOG_TAG, "SerialBus command receiving channel received None data, tx could be closed");
+                        warn!(LOG_TAG, "SerialBus command receiving channel closed, exiting");
+                        break;
                     }
                 }
 
@@ -96,8 +102,11 @@ pub fn run_loop(
                         match reader.try_next().await {
                             Ok(Some(data)) => {
                                 trace!(LOG_TAG, "got data: {:?}", &data);
+                                on_bus_event(BusEventEnum::Recovered);
                                 for listener in listener_vec.iter() {
-                                    listener.borrow_mut().notify(data.clone())?;
+                                    let mut listener = listener.borrow_mut();
+                                    listener.notify_bus_event(BusEventEnum::Recovered)?;
+                                    listener.notify(data.clone())?;
                                 }
                             }
                             Ok(None) => {
@@ -105,6 +114,12 @@ pub fn run_loop(
                             }
                             Err(e) => {
                                 error!(LOG_TAG, "read data error: {:?}", e);
+                                // push the error to the bus and listeners, so that they are reported as inactive
+                                let error_msg = format!("serial bus read data error: {}", e);
+                                on_bus_event(BusEventEnum::Error(error_msg.clone()));
+                                for listener in listener_vec.iter() {
+                                    listener.borrow_mut().notify_bus_event(BusEventEnum::Error(error_msg.clone()))?;
+                                }
                             }
                         }
                     } else {
@@ -114,8 +129,7 @@ pub fn run_loop(
             }
         }
         Ok::<(), DriverError>(())
-    });
-    Ok(())
+    })
 }
 
 /// Line Codec for Serial Data
@@ -1,8 +1,12 @@
 use crate::common::error::DriverError;
+use crate::driver::bus_event::BusEventEnum;
 use super::entity::SerialDataBo;
 
 /// device that can be mounted to serial bus
 pub trait SerialMountable {
     /// notify the device, then the device will call upward channel
     fn notify(&self, data: SerialDataBo) -> Result<(), DriverError>;
+
+    /// reading from serial port failed or recovered
+    fn notify_bus_event(&mut self, event: BusEventEnum) -> Result<(), DriverError>;
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
-use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::{common::error::DriverError, entity::dto::device_command_dto::DeviceCommandDto};
-use std::{rc::Rc, sync::mpsc};
+use crate::entity::dto::device_state_dto::{StateDtoEnum, StateToDeviceControllerDto};
+use crate::{common::error::{CommandError, DriverError}, entity::dto::device_command_dto::DeviceCommandDto};
+use std::{rc::Rc, sync::mpsc, time::Instant};
 
 /// the device that can send data to upward channel
 pub trait ReportUpward {
@@ -21,7 +21,20 @@ pub trait ReportUpward {
 
 /// the device that can be commanded by device manager
 pub trait Commandable {
-    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), DriverError>;
+    fn cmd(&mut self, dto: DeviceCommandDto) -> Result<(), CommandError>;
+
+    /// current state of the device, used for replying the command result
+    fn get_state(&self) -> StateDtoEnum;
+}
+
+/// the device that has scheduled work, e.g. switching off after a pulse
+/// the work is run by device thread, so that the timing does not depend on the command latency
+pub trait Scheduled {
+    /// time of the next scheduled work, none if nothing is scheduled
+    fn next_deadline(&self) -> Option<Instant>;
+
+    /// run the scheduled work which is due at now
+    fn run_due(&mut self, now: Instant) -> Result<(), DriverError>;
 }
 
 /// device that can be mounted by other device
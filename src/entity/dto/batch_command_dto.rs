@@ -0,0 +1,49 @@
+//! batch command data transmission object
+//! several device commands in one mqtt message, applied in order by device thread
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// param of batch command in mqtt payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchParamDto {
+    // validate all commands before executing any of them
+    #[serde(default)]
+    pub atomic: bool,
+    pub commands: Vec<BatchCommandItemDto>,
+}
+
+/// single command of batch command, param is parsed by device thread according to device type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCommandItemDto {
+    pub device_id: String,
+    pub action: String,
+    #[serde(default)]
+    pub param: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchCommandDto {
+    pub server_id: String,
+    // session_id of the incoming mqtt message, the reply will carry the same session_id
+    pub session_id: String,
+    pub atomic: bool,
+    pub commands: Vec<BatchCommandItemDto>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_param() {
+        let param: BatchParamDto = serde_json::from_str(
+            r#"{"commands": [{"device_id": "do-1", "action": "on"}, {"device_id": "audio-1", "action": "play", "param": {"hash": "abc"}}]}"#,
+        )
+        .unwrap();
+        assert!(!param.atomic);
+        assert_eq!(param.commands.len(), 2);
+        assert!(param.commands[0].param.is_null());
+        assert_eq!(param.commands[1].param["hash"], "abc");
+    }
+}
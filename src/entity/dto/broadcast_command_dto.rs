@@ -0,0 +1,80 @@
+//! broadcast command data transmission object
+//! broadcast command is sent to every device matching the filter
+
+use serde::{Deserialize, Serialize};
+
+/// actions supported by broadcast command
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastActionEnum {
+    /// turn off all do ports
+    AllOff,
+    /// set all dmx channels to zero
+    Blackout,
+    /// stop all playing audio
+    StopAudio,
+    /// reload file list from flow server, the files are shared by the audio devices matching the filter
+    ReloadFiles,
+}
+
+impl BroadcastActionEnum {
+    /// parse from the action string in mqtt payload, none if it is not a broadcast action
+    pub fn from_action(action: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(action.to_string())).ok()
+    }
+}
+
+/// filter of broadcast command, empty field matches all devices
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BroadcastFilterDto {
+    pub device_type: Option<String>,
+    pub room: Option<String>,
+}
+
+impl BroadcastFilterDto {
+    pub fn is_match(&self, device_type: &str, room: &str) -> bool {
+        if let Some(filter_type) = &self.device_type {
+            if filter_type != device_type {
+                return false;
+            }
+        }
+        if let Some(filter_room) = &self.room {
+            if filter_room != room {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BroadcastCommandDto {
+    // session_id of the incoming mqtt message, the reply will carry the same session_id
+    pub session_id: String,
+    pub action: BroadcastActionEnum,
+    pub filter: BroadcastFilterDto,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_action() {
+        assert_eq!(BroadcastActionEnum::from_action("all_off"), Some(BroadcastActionEnum::AllOff));
+        assert_eq!(BroadcastActionEnum::from_action("stop_audio"), Some(BroadcastActionEnum::StopAudio));
+        assert_eq!(BroadcastActionEnum::from_action("reload_files"), Some(BroadcastActionEnum::ReloadFiles));
+        assert_eq!(BroadcastActionEnum::from_action("update"), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = BroadcastFilterDto {
+            device_type: None,
+            room: Some("hall".to_string()),
+        };
+        assert!(filter.is_match("modbus_do_port", "hall"));
+        assert!(!filter.is_match("modbus_do_port", "lobby"));
+        assert!(BroadcastFilterDto::default().is_match("dmx_bus", ""));
+    }
+}
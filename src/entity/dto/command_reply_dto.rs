@@ -0,0 +1,53 @@
+//! command reply data transmission object
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::device_report_dto::DeviceReportDto;
+use super::device_state_dto::StateDtoEnum;
+use super::scene_command_dto::SceneDto;
+use super::schedule_command_dto::ScheduleDto;
+
+// reply codes, same as the code field in mqtt payload
+pub const REPLY_CODE_OK: i32 = 200;
+pub const REPLY_CODE_PARAM_FAIL: i32 = 400;
+pub const REPLY_CODE_ERROR: i32 = 500;
+
+/// reply of a device command, sent back to flow server with the session_id of the command
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandReplyDto {
+    pub session_id: String,
+    pub code: i32,
+    pub msg: String,
+    pub device_type: Option<String>,
+    pub device_id: Option<String>,
+    pub action: String,
+    // device state after the command is applied, none if the command failed
+    pub state: Option<StateDtoEnum>,
+    // reply of every command in a batch command, none for single command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<CommandReplyDto>>,
+    // device status of get command, key is device_id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<HashMap<String, DeviceReportDto>>,
+    // schedules added or listed by schedule commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedules: Option<Vec<ScheduleDto>>,
+    // scenes saved or listed by scene commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenes: Option<Vec<SceneDto>>,
+}
+
+impl CommandReplyDto {
+    /// successful reply without payload, other fields are set by the caller
+    pub fn new(session_id: String, action: String) -> Self {
+        CommandReplyDto {
+            session_id,
+            code: REPLY_CODE_OK,
+            msg: "success".to_string(),
+            action,
+            ..Default::default()
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
 /// used for commanding device
@@ -6,17 +6,163 @@ use serde_json::Value;
 pub struct DeviceCommandDto {
     pub server_id: String,
     pub device_id: String,
+    pub device_type: String,
+    // session_id of the incoming mqtt message, the reply will carry the same session_id
+    pub session_id: String,
     pub action: String,
     pub params: CommandParamsEnum
 }
 
+/// typed command of every commandable device type
+/// action and param are validated when parsing, drivers only match on the typed command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CommandParamsEnum {
-    Empty,
-    Audio(AudioParamsDto)
+    DoPort(DoPortCommandEnum),
+    DoController(DoControllerCommandEnum),
+    DmxBus(DmxBusCommandEnum),
+    DmxChannel(DmxChannelCommandEnum),
+    SerialBus(SerialBusCommandEnum),
+    Audio(AudioCommandEnum),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// parse action and param into command enum tagged by action
+pub fn parse_action<T: DeserializeOwned>(action: &str, param: Value) -> Result<T, serde_json::Error> {
+    // actions without param accept null, missing param or empty object
+    let param = match param {
+        Value::Object(map) if map.is_empty() => Value::Null,
+        param => param,
+    };
+    serde_json::from_value(serde_json::json!({
+        "action": action,
+        "param": param,
+    }))
+}
+
+/// modbus_do_port
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum DoPortCommandEnum {
+    On,
+    Off,
+    // switch to the opposite state
+    Toggle,
+    // on, then off after duration, duration defaults to pulse_time of device config
+    Pulse(Option<DoDurationParamsDto>),
+    // on, then off after duration, can be canceled by cancel
+    OnFor(DoDurationParamsDto),
+    // on and off for count times, on for half of the period
+    Blink(DoBlinkParamsDto),
+    // cancel the scheduled switching, keep the current state
+    Cancel,
+}
+
+/// time in millis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoDurationParamsDto {
+    pub duration: u64,
+}
+
+/// time in millis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoBlinkParamsDto {
+    pub period: u64,
+    pub count: u32,
+}
+
+/// modbus_do_controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum DoControllerCommandEnum {
+    // write single port
+    Write(DoWriteParamsDto),
+    // write continuous ports starting from address
+    WriteMulti(DoWriteMultiParamsDto),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoWriteParamsDto {
+    pub address: u16,
+    pub value: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoWriteMultiParamsDto {
+    pub address: u16,
+    pub values: Vec<bool>,
+}
+
+/// dmx_bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum DmxBusCommandEnum {
+    // set continuous channels starting from offset
+    SetChannels(DmxSetChannelsParamsDto),
+    // set all channels to zero
+    Blackout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DmxSetChannelsParamsDto {
+    pub offset: u8,
+    pub values: Vec<u8>,
+    // crossfade from the current values, in millis
+    #[serde(default)]
+    pub fade: Option<u64>,
+}
+
+/// dmx_channel, channel is the index inside the device, starting from 0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum DmxChannelCommandEnum {
+    // set continuous channels starting from channel
+    Set(DmxChannelSetParamsDto),
+    // set all channels to the same value
+    SetAll(DmxChannelSetAllParamsDto),
+    // set all channels to zero
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DmxChannelSetParamsDto {
+    #[serde(default)]
+    pub channel: u8,
+    pub values: Vec<u8>,
+    // crossfade from the current values, in millis
+    #[serde(default)]
+    pub fade: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DmxChannelSetAllParamsDto {
+    pub value: u8,
+}
+
+/// serial_bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum SerialBusCommandEnum {
+    // send a frame, the leading 0xfa, param length and tailing 0xed are added by serial bus
+    Send(SerialFrameParamsDto),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerialFrameParamsDto {
+    pub command: u8,
+    #[serde(default)]
+    pub data: Vec<u8>,
+}
+
+/// audio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "param", rename_all = "snake_case")]
+pub enum AudioCommandEnum {
+    Play(AudioParamsDto),
+    Pause(AudioParamsDto),
+    Stop(AudioParamsDto),
+    Resume(AudioParamsDto),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AudioParamsDto {
     pub hash: String
-}
\ No newline at end of file
+}
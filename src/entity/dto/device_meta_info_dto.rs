@@ -1,6 +1,7 @@
 use serde_json::Value;
 
 use crate::entity::dto::device_state_dto::StateDtoEnum;
+use crate::entity::po::device_config_po::{RestorePolicyEnum, RulePo};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum DeviceStatusEnum{
@@ -20,6 +21,12 @@ pub struct DeviceMetaInfoDto {
     pub device_type: String,
     pub config: Value,
     pub device_status: DeviceStatusEnum,
+    // mark device offline if no report in this window, in millis, none for never
+    pub stale_timeout: Option<u64>,
+    // how output state is restored after the device is made
+    pub restore_policy: RestorePolicyEnum,
+    // local rules evaluated on the reports of this device
+    pub rules: Vec<RulePo>,
 
     // for reporting device state part 
     pub error_msg: Option<String>,
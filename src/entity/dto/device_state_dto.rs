@@ -5,6 +5,7 @@ use std::error::Error;
 use serde::{Deserialize, Serialize};
 
 use super::device_report_dto::DeviceReportDto;
+use crate::util::time::get_timestamp_millis;
 
 /// universal device status enum
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,6 +20,7 @@ pub enum StateDtoEnum {
     Remote(RemoteStateDto),
     Di(DiStateDto),
     Do(DoStateDto),
+    Sequence(SequenceStateDto),
 }
 
 /// used for device report to device controller
@@ -31,6 +33,38 @@ pub struct StateToDeviceControllerDto {
 }
 
 impl StateToDeviceControllerDto {
+    /// report that device is not working, the device status will be set to error
+    pub fn error(device_id: &str, device_class: &str, device_type: &str, error_msg: String) -> Self {
+        StateToDeviceControllerDto {
+            device_id: device_id.to_string(),
+            device_class: device_class.to_string(),
+            device_type: device_type.to_string(),
+            status: DeviceReportDto {
+                active: false,
+                error_msg: Some(error_msg),
+                error_timestamp: Some(get_timestamp_millis()),
+                last_update: None,
+                state: StateDtoEnum::Empty,
+            },
+        }
+    }
+
+    /// report that device is working again, the error is cleared and the last known state is kept
+    pub fn recovered(device_id: &str, device_class: &str, device_type: &str) -> Self {
+        StateToDeviceControllerDto {
+            device_id: device_id.to_string(),
+            device_class: device_class.to_string(),
+            device_type: device_type.to_string(),
+            status: DeviceReportDto {
+                active: true,
+                error_msg: None,
+                error_timestamp: None,
+                last_update: None,
+                state: StateDtoEnum::Empty,
+            },
+        }
+    }
+
     pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
         Ok(serde_json::to_string(self)?)
     }
@@ -91,4 +125,37 @@ pub struct DiStateDto {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DoStateDto {
     pub on: bool
-}
\ No newline at end of file
+}
+
+// virtual device states
+
+/// event of sequence detector, reported on every matched step, match, failure and reset
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceEventEnum {
+    // waiting for the first step
+    Idle,
+    // some steps are matched
+    SequenceProgress,
+    SequenceMatched,
+    SequenceFailed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceFailReasonEnum {
+    // reset timeout since the first step
+    Timeout,
+    // input of the detector which does not match the expected step
+    WrongInput,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SequenceStateDto {
+    pub event: SequenceEventEnum,
+    // only for sequence_failed
+    pub reason: Option<SequenceFailReasonEnum>,
+    // matched steps, zero after matched or failed
+    pub progress: usize,
+    pub total: usize,
+}
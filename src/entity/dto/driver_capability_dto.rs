@@ -0,0 +1,12 @@
+//! device driver capability data transmission object
+
+use serde::{Deserialize, Serialize};
+
+/// what a device type supports, reported with heartbeat
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DriverCapabilityDto {
+    // allowed device_type of master device
+    pub master_types: Vec<String>,
+    // actions accepted by command
+    pub actions: Vec<String>,
+}
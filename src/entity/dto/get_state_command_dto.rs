@@ -0,0 +1,20 @@
+//! state query command data transmission object
+
+use serde::{Deserialize, Serialize};
+
+/// param of get command in mqtt payload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetStateParamDto {
+    // read state from driver instead of the cached device info
+    #[serde(default)]
+    pub fresh: bool,
+}
+
+/// query state of one device, or all devices if device_id is none
+#[derive(Debug, Clone)]
+pub struct GetStateCommandDto {
+    // session_id of the incoming mqtt message, the reply will carry the same session_id
+    pub session_id: String,
+    pub device_id: Option<String>,
+    pub fresh: bool,
+}
@@ -0,0 +1,55 @@
+//! input event data transmission object
+//! edges of di and presses of remote, derived from device reports in reporting thread
+
+use super::device_state_dto::StateDtoEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEventEnum {
+    // di turns on
+    Rise,
+    // di turns off
+    Fall,
+    // remote button is pressed
+    Press(u8),
+}
+
+impl InputEventEnum {
+    /// derive input event from the last known state and the reported state
+    /// edges of di need the last known state, the first report after starting is not an edge
+    pub fn from_state(previous: &StateDtoEnum, current: &StateDtoEnum) -> Option<Self> {
+        match (previous, current) {
+            (StateDtoEnum::Di(previous), StateDtoEnum::Di(current)) if previous.on != current.on => {
+                Some(if current.on { InputEventEnum::Rise } else { InputEventEnum::Fall })
+            }
+            // remote reports every press, error reports carry no state
+            (_, StateDtoEnum::Remote(current)) => Some(InputEventEnum::Press(current.pressed)),
+            _ => None,
+        }
+    }
+}
+
+/// input event of device, dispatched to sequence detectors in device thread
+#[derive(Debug, Clone)]
+pub struct InputEventDto {
+    pub device_id: String,
+    pub event: InputEventEnum,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::dto::device_state_dto::{DiStateDto, RemoteStateDto};
+
+    #[test]
+    fn test_from_state() {
+        let di = |on| StateDtoEnum::Di(DiStateDto { on });
+        assert_eq!(InputEventEnum::from_state(&di(false), &di(true)), Some(InputEventEnum::Rise));
+        assert_eq!(InputEventEnum::from_state(&di(true), &di(false)), Some(InputEventEnum::Fall));
+        assert_eq!(InputEventEnum::from_state(&di(true), &di(true)), None);
+        assert_eq!(InputEventEnum::from_state(&StateDtoEnum::Empty, &di(true)), None);
+
+        let pressed = StateDtoEnum::Remote(RemoteStateDto { pressed: 3 });
+        assert_eq!(InputEventEnum::from_state(&pressed, &pressed), Some(InputEventEnum::Press(3)));
+        assert_eq!(InputEventEnum::from_state(&pressed, &StateDtoEnum::Empty), None);
+    }
+}
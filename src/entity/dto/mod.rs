@@ -4,4 +4,14 @@ pub mod device_command_dto;
 pub mod server_state_dto;
 pub mod device_meta_info_dto;
 pub mod device_report_dto;
-pub mod mqtt_dto;
\ No newline at end of file
+pub mod mqtt_dto;
+pub mod command_reply_dto;
+pub mod broadcast_command_dto;
+pub mod batch_command_dto;
+pub mod get_state_command_dto;
+pub mod reload_devices_command_dto;
+pub mod worker_status_dto;
+pub mod driver_capability_dto;
+pub mod schedule_command_dto;
+pub mod scene_command_dto;
+pub mod input_event_dto;
@@ -4,14 +4,47 @@ use crate::util::time::get_timestamp;
 use crate::util::gen_id::generate_uuid;
 use crate::common::setting::Settings;
 
-use super::device_command_dto::CommandParamsEnum;
+use super::batch_command_dto::BatchCommandDto;
+use super::broadcast_command_dto::BroadcastCommandDto;
+use super::command_reply_dto::CommandReplyDto;
+use super::get_state_command_dto::GetStateCommandDto;
+use super::input_event_dto::InputEventDto;
+use super::reload_devices_command_dto::ReloadDevicesCommandDto;
+use super::scene_command_dto::{SceneCommandDto, SceneRecallDto};
+use super::schedule_command_dto::ScheduleCommandDto;
+use super::device_command_dto::{CommandParamsEnum, DeviceCommandDto};
 use super::device_state_dto::StateToDeviceControllerDto;
 use super::server_state_dto::ServerStateDto;
 
+/// for sending command from mqtt client to device thread
+#[derive(Debug)]
+pub enum MqttToDeviceEnum {
+    DeviceCommand(DeviceCommandDto),
+    // command fired inside device server by local rules or schedules, the result is logged without reply
+    LocalCommand(DeviceCommandDto),
+    Broadcast(BroadcastCommandDto),
+    Batch(BatchCommandDto),
+    GetState(GetStateCommandDto),
+    ReloadDevices(ReloadDevicesCommandDto),
+    // save, capture, delete, list or recall scenes, forwarded to scene thread
+    Scene(SceneCommandDto),
+    // device commands of the recalled scene from scene thread
+    SceneRecall(SceneRecallDto),
+    // add, cancel or list schedules, forwarded to scheduler thread
+    Schedule(ScheduleCommandDto),
+    // edge of di or press of remote from reporting thread, dispatched to sequence detectors
+    InputEvent(InputEventDto),
+    // set devices to safe state and stop device threads
+    Shutdown,
+}
+
 /// for sending mqtt message
 pub enum DeviceToMqttEnum {
     ServerState(ServerStateDto),
-    DeviceState(StateToDeviceControllerDto)
+    DeviceState(StateToDeviceControllerDto),
+    CommandReply(CommandReplyDto),
+    // all devices are stopped, flush pending messages, publish offline and quit
+    Shutdown,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
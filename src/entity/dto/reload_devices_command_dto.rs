@@ -0,0 +1,14 @@
+//! device config reloading command data transmission object
+
+use std::sync::mpsc::Sender;
+
+use super::command_reply_dto::CommandReplyDto;
+
+/// re-fetch device config and rebuild changed devices
+#[derive(Debug, Clone)]
+pub struct ReloadDevicesCommandDto {
+    // session_id of the incoming request, the reply will carry the same session_id
+    pub session_id: String,
+    // http request waits for the reply on this channel, otherwise the reply is sent by mqtt
+    pub reply_tx: Option<Sender<CommandReplyDto>>,
+}
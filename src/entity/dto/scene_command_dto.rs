@@ -0,0 +1,170 @@
+//! scene command data transmission object
+//! a scene is a named set of device commands (target states), saved locally and recalled by one command
+
+use std::sync::mpsc::Sender;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::batch_command_dto::{BatchCommandDto, BatchCommandItemDto};
+use super::command_reply_dto::CommandReplyDto;
+
+/// actions of managing and recalling scenes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SceneActionEnum {
+    /// define a scene by device commands, the scene with the same scene_id is replaced
+    SceneSave,
+    /// save the current state of devices as a scene
+    SceneCapture,
+    /// remove a scene
+    SceneDelete,
+    /// list all scenes
+    SceneList,
+    /// apply all device commands of a scene
+    RecallScene,
+}
+
+impl SceneActionEnum {
+    /// parse from the action string in mqtt payload, none if it is not a scene action
+    pub fn from_action(action: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(action.to_string())).ok()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SceneActionEnum::SceneSave => "scene_save",
+            SceneActionEnum::SceneCapture => "scene_capture",
+            SceneActionEnum::SceneDelete => "scene_delete",
+            SceneActionEnum::SceneList => "scene_list",
+            SceneActionEnum::RecallScene => "recall_scene",
+        }
+    }
+}
+
+/// param of scene_save
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneSaveParamDto {
+    pub scene_id: String,
+    #[serde(default)]
+    pub name: String,
+    // device commands applied in order when recalling
+    pub targets: Vec<BatchCommandItemDto>,
+}
+
+/// param of scene_capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneCaptureParamDto {
+    pub scene_id: String,
+    #[serde(default)]
+    pub name: String,
+    // devices to capture, empty for all devices which support capturing
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+}
+
+/// param of scene_delete
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneIdParamDto {
+    pub scene_id: String,
+}
+
+/// param of recall_scene
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecallSceneParamDto {
+    pub scene_id: String,
+    // crossfade time of dmx devices, in millis
+    #[serde(default)]
+    pub fade: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SceneRequestEnum {
+    Save(SceneSaveParamDto),
+    Capture(SceneCaptureParamDto),
+    Delete(SceneIdParamDto),
+    List,
+    Recall(RecallSceneParamDto),
+}
+
+impl SceneRequestEnum {
+    /// parse param according to action
+    pub fn from_action(action: SceneActionEnum, param: Value) -> Result<Self, serde_json::Error> {
+        match action {
+            SceneActionEnum::SceneSave => Ok(SceneRequestEnum::Save(serde_json::from_value(param)?)),
+            SceneActionEnum::SceneCapture => Ok(SceneRequestEnum::Capture(serde_json::from_value(param)?)),
+            SceneActionEnum::SceneDelete => Ok(SceneRequestEnum::Delete(serde_json::from_value(param)?)),
+            SceneActionEnum::SceneList => Ok(SceneRequestEnum::List),
+            SceneActionEnum::RecallScene => Ok(SceneRequestEnum::Recall(serde_json::from_value(param)?)),
+        }
+    }
+
+    pub fn action(&self) -> SceneActionEnum {
+        match self {
+            SceneRequestEnum::Save(_) => SceneActionEnum::SceneSave,
+            SceneRequestEnum::Capture(_) => SceneActionEnum::SceneCapture,
+            SceneRequestEnum::Delete(_) => SceneActionEnum::SceneDelete,
+            SceneRequestEnum::List => SceneActionEnum::SceneList,
+            SceneRequestEnum::Recall(_) => SceneActionEnum::RecallScene,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneCommandDto {
+    // session_id of the incoming request, the reply will carry the same session_id
+    pub session_id: String,
+    pub request: SceneRequestEnum,
+    // http request waits for the reply on this channel, otherwise the reply is sent by mqtt
+    pub reply_tx: Option<Sender<CommandReplyDto>>,
+}
+
+/// device commands of the recalled scene, resolved by scene thread and applied by device thread
+#[derive(Debug, Clone)]
+pub struct SceneRecallDto {
+    pub batch: BatchCommandDto,
+    // http request waits for the reply on this channel, otherwise the reply is sent by mqtt
+    pub reply_tx: Option<Sender<CommandReplyDto>>,
+}
+
+/// scene in the reply of scene commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDto {
+    pub scene_id: String,
+    pub name: String,
+    pub targets: Vec<BatchCommandItemDto>,
+    // epoch millis
+    pub update_time: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_action() {
+        assert_eq!(SceneActionEnum::from_action("recall_scene"), Some(SceneActionEnum::RecallScene));
+        assert_eq!(SceneActionEnum::from_action("schedule_add"), None);
+        assert_eq!(SceneActionEnum::SceneCapture.as_str(), "scene_capture");
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = SceneRequestEnum::from_action(
+            SceneActionEnum::SceneSave,
+            json!({"scene_id": "open", "targets": [{"device_id": "do-1", "action": "on"}]}),
+        )
+        .unwrap();
+        match request {
+            SceneRequestEnum::Save(param) => {
+                assert_eq!(param.name, "");
+                assert_eq!(param.targets.len(), 1);
+            }
+            _ => panic!("wrong request: {:?}", request),
+        }
+        let request = SceneRequestEnum::from_action(SceneActionEnum::RecallScene, json!({"scene_id": "open"})).unwrap();
+        assert!(matches!(request, SceneRequestEnum::Recall(RecallSceneParamDto { fade: None, .. })));
+        assert!(SceneRequestEnum::from_action(SceneActionEnum::SceneCapture, Value::Null).is_err());
+    }
+}
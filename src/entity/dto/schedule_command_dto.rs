@@ -0,0 +1,136 @@
+//! schedule command data transmission object
+//! device commands fired later by the local scheduler, once after a delay / at a time, or repeatedly by cron expression
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// actions of managing schedules
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ScheduleActionEnum {
+    /// add a schedule, the schedule with the same schedule_id is replaced
+    #[serde(rename = "schedule_add")]
+    Add,
+    /// remove a schedule
+    #[serde(rename = "schedule_cancel")]
+    Cancel,
+    /// list all schedules
+    #[serde(rename = "schedule_list")]
+    List,
+}
+
+impl ScheduleActionEnum {
+    /// parse from the action string in mqtt payload, none if it is not a schedule action
+    pub fn from_action(action: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(action.to_string())).ok()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleActionEnum::Add => "schedule_add",
+            ScheduleActionEnum::Cancel => "schedule_cancel",
+            ScheduleActionEnum::List => "schedule_list",
+        }
+    }
+}
+
+/// param of schedule_add, exactly one of delay, at and cron should be set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleAddParamDto {
+    // generated if missing
+    pub schedule_id: Option<String>,
+    pub device_id: String,
+    pub action: String,
+    #[serde(default)]
+    pub param: Value,
+    // fire once after delay, in millis
+    pub delay: Option<u64>,
+    // fire once at epoch millis
+    pub at: Option<u64>,
+    // fire repeatedly, matched against local time
+    pub cron: Option<String>,
+}
+
+/// param of schedule_cancel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleCancelParamDto {
+    pub schedule_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleRequestEnum {
+    Add(ScheduleAddParamDto),
+    Cancel(ScheduleCancelParamDto),
+    List,
+}
+
+impl ScheduleRequestEnum {
+    /// parse param according to action
+    pub fn from_action(action: ScheduleActionEnum, param: Value) -> Result<Self, serde_json::Error> {
+        match action {
+            ScheduleActionEnum::Add => Ok(ScheduleRequestEnum::Add(serde_json::from_value(param)?)),
+            ScheduleActionEnum::Cancel => Ok(ScheduleRequestEnum::Cancel(serde_json::from_value(param)?)),
+            ScheduleActionEnum::List => Ok(ScheduleRequestEnum::List),
+        }
+    }
+
+    pub fn action(&self) -> ScheduleActionEnum {
+        match self {
+            ScheduleRequestEnum::Add(_) => ScheduleActionEnum::Add,
+            ScheduleRequestEnum::Cancel(_) => ScheduleActionEnum::Cancel,
+            ScheduleRequestEnum::List => ScheduleActionEnum::List,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduleCommandDto {
+    // session_id of the incoming mqtt message, the reply will carry the same session_id
+    pub session_id: String,
+    pub request: ScheduleRequestEnum,
+}
+
+/// schedule in the reply of schedule commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleDto {
+    pub schedule_id: String,
+    pub device_id: String,
+    pub action: String,
+    pub param: Value,
+    // epoch millis of one-shot schedule
+    pub at: Option<u64>,
+    pub cron: Option<String>,
+    // next firing time in epoch millis, none if it never fires again
+    pub next_fire_time: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_action() {
+        assert_eq!(ScheduleActionEnum::from_action("schedule_add"), Some(ScheduleActionEnum::Add));
+        assert_eq!(ScheduleActionEnum::from_action("all_off"), None);
+        assert_eq!(ScheduleActionEnum::List.as_str(), "schedule_list");
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = ScheduleRequestEnum::from_action(
+            ScheduleActionEnum::Add,
+            json!({"device_id": "do-1", "action": "on", "cron": "0 9 * * *"}),
+        )
+        .unwrap();
+        match request {
+            ScheduleRequestEnum::Add(param) => {
+                assert!(param.schedule_id.is_none());
+                assert!(param.param.is_null());
+                assert_eq!(param.cron.as_deref(), Some("0 9 * * *"));
+            }
+            _ => panic!("wrong request: {:?}", request),
+        }
+        assert!(ScheduleRequestEnum::from_action(ScheduleActionEnum::Cancel, Value::Null).is_err());
+        assert_eq!(ScheduleRequestEnum::from_action(ScheduleActionEnum::List, Value::Null).unwrap(), ScheduleRequestEnum::List);
+    }
+}
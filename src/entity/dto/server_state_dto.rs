@@ -3,10 +3,72 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 use crate::device_controller::entity::device_po::DevicePo;
-use super::{device_report_dto::DeviceReportDto};
+use super::{
+    device_meta_info_dto::{DeviceMetaInfoDto, DeviceStatusEnum},
+    device_report_dto::DeviceReportDto,
+    driver_capability_dto::DriverCapabilityDto,
+    worker_status_dto::WorkerStatusDto,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerStateDto {
     pub device_config: HashMap<String, DevicePo>,
-    pub device_status: HashMap<String, DeviceReportDto>
+    pub device_status: HashMap<String, DeviceReportDto>,
+    // supervised worker threads, including bus threads
+    #[serde(default)]
+    pub workers: HashMap<String, WorkerStatusDto>,
+    // device count by status, and error devices
+    #[serde(default)]
+    pub summary: ServerSummaryDto,
+    // device types supported by this server, device_type -> capability
+    #[serde(default)]
+    pub drivers: HashMap<String, DriverCapabilityDto>,
+}
+
+/// summary of server state, used to find misconfigured devices quickly
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerSummaryDto {
+    pub device_count: usize,
+    pub active_count: usize,
+    pub initialized_count: usize,
+    pub error_count: usize,
+    pub offline_count: usize,
+    // device_id -> error message
+    pub error_devices: HashMap<String, String>,
+    // names of supervised workers which are not alive
+    pub failed_workers: Vec<String>,
+}
+
+impl ServerSummaryDto {
+    pub fn from_state(
+        device_info_map: &HashMap<String, DeviceMetaInfoDto>,
+        workers: &HashMap<String, WorkerStatusDto>,
+    ) -> Self {
+        let mut summary = ServerSummaryDto {
+            device_count: device_info_map.len(),
+            ..Default::default()
+        };
+        for (device_id, device_info) in device_info_map {
+            match device_info.device_status {
+                DeviceStatusEnum::ACTIVE => summary.active_count += 1,
+                DeviceStatusEnum::Initialized => summary.initialized_count += 1,
+                DeviceStatusEnum::OFFLINE => summary.offline_count += 1,
+                DeviceStatusEnum::ERROR => {
+                    summary.error_count += 1;
+                    summary.error_devices.insert(
+                        device_id.clone(),
+                        device_info.error_msg.clone().unwrap_or_default(),
+                    );
+                }
+                DeviceStatusEnum::NotInitialized => {}
+            }
+        }
+        summary.failed_workers = workers
+            .iter()
+            .filter(|(_, status)| !status.alive)
+            .map(|(name, _)| name.clone())
+            .collect();
+        summary.failed_workers.sort();
+        summary
+    }
 }
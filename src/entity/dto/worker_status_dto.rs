@@ -0,0 +1,12 @@
+//! worker thread status data transmission object
+
+use serde::{Deserialize, Serialize};
+
+/// running status of supervised worker thread, reported with heartbeat
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerStatusDto {
+    pub alive: bool,
+    pub restart_count: u32,
+    pub error_msg: Option<String>,
+    pub error_timestamp: Option<u64>,
+}
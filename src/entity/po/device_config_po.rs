@@ -1,79 +1,139 @@
 //! 设备配置和创建设备相关 Bo
 
-use serde::{Deserialize, Serialize};
-
-/// used for creating device
-#[derive(Debug, Clone)]
-pub struct DeviceCreatePo {
-    // 设备一级类目
-    pub device_class: String,
-    // 设备二级类目
-    pub device_type: String,
-    // 设备 id
-    pub device_id: String,
-    // 设备名称
-    pub name: String,
-    // 设备描述
-    pub description: String,
-    // 设备区域（房间）
-    pub room: String,
-    // 设备配置（json string）
-    pub config: ConfigPo,
-
-    // 主设备 id，只有子设备存在才有效
-    pub master_device_id: Option<String>
-}
-
-#[derive(Debug, Clone)]
-pub enum ConfigPo {
-    Modbus(ModbusConfigPo),
-    DmxBus(DmxBusConfigPo),
-    SerialBus(SerialBusConfigPo),
-    ModbusDigitalInputController(ModbusDigitalInputControllerConfigPo),
-    ModbusDigitalOutputController(ModbusDigitalOutputControllerConfigPo),
-    Audio(AudioConfigPo),
-    Di(DiConfigPo),
-    Do(DoConfigPo),
-    Remote(RemoteConfigPo),
-    DmxCustom(DmxCustomConfigPo),
-    Dummy(DummyConfigPo)
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::error::DriverError;
+use crate::driver::dmx::prelude::DmxAddress;
+use crate::driver::modbus::prelude::{ModbusAddrSize, ModbusUnitSize};
+
+/// deserialize config json into typed config, error message contains device_id and the invalid field
+/// common fields like master_device_id and safe_state are ignored
+pub fn parse_config<T: DeserializeOwned>(device_id: &str, config: &Value) -> Result<T, DriverError> {
+    serde_path_to_error::deserialize(config).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        // missing field error happens on the whole object, the field name is in the message
+        if path == "." {
+            DriverError(format!("invalid device config, device_id: {}, error: {}", device_id, inner))
+        } else {
+            DriverError(format!(
+                "invalid device config, device_id: {}, field: {}, error: {}",
+                device_id, path, inner
+            ))
+        }
+    })
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DummyConfigPo {
+/// how output device state is restored after the device is made, "restore_policy" of device config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestorePolicyEnum {
+    // keep the state after starting, wait for the command from flow server
+    #[default]
+    Upstream,
+    // write the safe state of device config
+    Safe,
+    // write the last saved state, safe state if there is no saved state
+    Last,
+}
+
+impl RestorePolicyEnum {
+    /// read restore_policy from device config, default upstream
+    pub fn from_config(device_id: &str, config: &Value) -> Result<Self, DriverError> {
+        match config.get("restore_policy") {
+            Some(policy) => parse_config(device_id, policy),
+            None => Ok(RestorePolicyEnum::Upstream),
+        }
+    }
+}
+
+/// input event of a local rule
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTriggerEnum {
+    // di turns on
+    Rise,
+    // di turns off
+    Fall,
+    // di turns on or off
+    Change,
+    // remote button is pressed
+    Press,
+}
 
+/// local rule of input device, "rules" of device config
+/// the command is sent to target device when the input device reports the trigger event
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RulePo {
+    pub trigger: RuleTriggerEnum,
+    // button number for press, none for any button
+    #[serde(default)]
+    pub button: Option<u8>,
+    // target device id
+    pub target: String,
+    pub action: String,
+    #[serde(default)]
+    pub param: Value,
+}
+
+impl RulePo {
+    /// read rules from device config, empty if not configured
+    pub fn from_config(device_id: &str, config: &Value) -> Result<Vec<Self>, DriverError> {
+        match config.get("rules") {
+            Some(rules) => parse_config(device_id, rules),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn default_sequence_timeout() -> u64 {
+    10000
+}
+
+fn default_baudrate() -> u32 {
+    9600
+}
+
+fn default_pulse_time() -> u64 {
+    1000
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ModbusConfigPo {
     pub serial_port: String,
+    #[serde(default = "default_baudrate")]
     pub baudrate: u32
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DmxBusConfigPo {
-    pub ftdi_serial: String,
+    pub serial_port: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SerialBusConfigPo {
     pub serial_port: String,
+    #[serde(default = "default_baudrate")]
     pub baudrate:u32
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ModbusDigitalInputControllerConfigPo {
-    pub unit: u32,
-    pub input_num: u32,
+    pub unit: ModbusUnitSize,
+    #[serde(rename = "num")]
+    pub input_num: ModbusAddrSize,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ModbusDigitalOutputControllerConfigPo {
-    pub unit: u32,
-    pub output_num: u32,
+    pub unit: ModbusUnitSize,
+    #[serde(rename = "num")]
+    pub output_num: ModbusAddrSize,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AudioChannelEnum {
     Left,
     Right,
@@ -83,26 +143,105 @@ pub enum AudioChannelEnum {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AudioConfigPo {
     pub soundcard_id: String,
+    #[serde(rename = "channel")]
     pub to_channel: AudioChannelEnum
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DiConfigPo {
-    pub address:u32
+    pub address: ModbusAddrSize
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DoConfigPo {
-    pub address:u32
+    pub address: ModbusAddrSize,
+    // 脉冲输出时长（毫秒）
+    #[serde(default = "default_pulse_time")]
+    pub pulse_time: u64
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RemoteConfigPo {
-    pub num_button: u32
+    pub num_button: u8
+}
+
+/// how the steps of sequence detector are matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceModeEnum {
+    // steps are triggered in order
+    #[default]
+    Sequence,
+    // all steps are active at the same time, in any order
+    Combination,
+}
+
+/// input of sequence detector: rise of di port, or press of remote button when button is set
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SequenceStepPo {
+    pub device_id: String,
+    #[serde(default)]
+    pub button: Option<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SequenceDetectorConfigPo {
+    #[serde(default)]
+    pub mode: SequenceModeEnum,
+    pub steps: Vec<SequenceStepPo>,
+    // reset timeout since the first step, in millis
+    #[serde(default = "default_sequence_timeout")]
+    pub timeout: u64,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DmxCustomConfigPo {
-    pub channels: u32,
-    pub address: u32
-}
\ No newline at end of file
+    #[serde(rename = "channel_num")]
+    pub channels: DmxAddress,
+    pub address: DmxAddress
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let modbus_config: ModbusConfigPo = parse_config("bus", &json!({"serial_port": "/dev/modbus0"})).unwrap();
+        assert_eq!(modbus_config.baudrate, 9600);
+
+        let e = parse_config::<ModbusConfigPo>("bus", &json!({"baudrate": 9600})).unwrap_err();
+        assert!(e.0.contains("device_id: bus"));
+        assert!(e.0.contains("serial_port"));
+
+        let e = parse_config::<DoConfigPo>("port", &json!({"address": "one"})).unwrap_err();
+        assert!(e.0.contains("device_id: port, field: address"));
+
+        let e = parse_config::<AudioConfigPo>("audio", &json!({"soundcard_id": "usb-1", "channel": "middle"})).unwrap_err();
+        assert!(e.0.contains("field: channel"));
+    }
+
+    #[test]
+    fn test_restore_policy() {
+        assert_eq!(RestorePolicyEnum::from_config("port", &json!({})).unwrap(), RestorePolicyEnum::Upstream);
+        assert_eq!(RestorePolicyEnum::from_config("port", &json!({"restore_policy": "last"})).unwrap(), RestorePolicyEnum::Last);
+        assert!(RestorePolicyEnum::from_config("port", &json!({"restore_policy": "latest"})).is_err());
+    }
+
+    #[test]
+    fn test_rules() {
+        assert!(RulePo::from_config("di", &json!({})).unwrap().is_empty());
+        let rules = RulePo::from_config("remote", &json!({"rules": [
+            {"trigger": "press", "button": 3, "target": "audio-1", "action": "play", "param": {"hash": "abc"}}
+        ]})).unwrap();
+        assert_eq!(rules[0].trigger, RuleTriggerEnum::Press);
+        assert_eq!(rules[0].button, Some(3));
+        let rules = RulePo::from_config("di", &json!({"rules": [{"trigger": "rise", "target": "do-1", "action": "pulse"}]})).unwrap();
+        assert_eq!(rules[0].button, None);
+        assert_eq!(rules[0].param, Value::Null);
+        let e = RulePo::from_config("di", &json!({"rules": [{"trigger": "up", "target": "do-1", "action": "on"}]})).unwrap_err();
+        assert!(e.0.contains("field: [0].trigger"));
+    }
+}
@@ -248,6 +248,15 @@ impl FileController {
             return Some(format!("{}/{}", FILE_FOLDER, filename));
         }
     }
+
+    /// get file hash by file path, reverse of get_path_by_hash
+    pub fn get_hash_by_path(&self, path: &str) -> Option<String> {
+        let map_guard = self.cache.lock().unwrap();
+        map_guard
+            .values()
+            .find(|file_po| format!("{}/{}", FILE_FOLDER, file_po.filename) == path)
+            .map(|file_po| file_po.hash.clone())
+    }
 }
 
 /// transform json object to file po
@@ -1,8 +1,11 @@
-use std::{sync::mpsc, thread};
+use std::{sync::mpsc::{self, Sender}, thread::{self, JoinHandle}, time::{Duration, Instant}};
 
 use common::logger::init_logger;
+use common::setting::Settings;
 use device_controller::device_controller::DeviceController;
+use entity::dto::mqtt_dto::MqttToDeviceEnum;
 use mqtt_client::client::MqttClient;
+use web::web_server::web_thread;
 
 mod mqtt_client;
 mod common;
@@ -11,29 +14,44 @@ mod device_controller;
 mod file_controller;
 mod entity;
 mod util;
+mod web;
 
 // #[macro_use] extern crate log;
 
 const LOG_TAG: &str = "main";
 const SLEEP_INTERVAL: u64 = 1000;
+// interval of checking threads exiting when shutting down
+const SHUTDOWN_CHECK_INTERVAL: u64 = 100;
 
 fn main() {
     init_logger().expect("Fail to initialize logger");
 
+    // SIGINT and SIGTERM
+    let (shutdown_tx, shutdown_rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = shutdown_tx.send(());
+    }).expect("Fail to set signal handler");
+
     let (device_to_mqtt_tx, device_to_mqtt_rx) = mpsc::channel();
     let (mqtt_to_device_tx, mqtt_to_device_rx) = mpsc::channel();
 
     let device_controller = DeviceController::new();
     let mqtt_client = MqttClient::new();
 
-    let mut handle_vec = device_controller.start(device_to_mqtt_tx, mqtt_to_device_rx).expect("Failed to start device controller");
-    let handle = mqtt_client.start(mqtt_to_device_tx, device_to_mqtt_rx);
+    let mut handle_vec = device_controller.start(device_to_mqtt_tx, mqtt_to_device_tx.clone(), mqtt_to_device_rx).expect("Failed to start device controller");
+    // http server is optional, the server keeps running if it cannot start
+    let _ = web_thread(mqtt_to_device_tx.clone());
+    let handle = mqtt_client.start(mqtt_to_device_tx.clone(), device_to_mqtt_rx);
     handle_vec.push(handle);
 
     info!(LOG_TAG, "main thread starting done");
 
     loop{
-        thread::sleep(std::time::Duration::from_millis(SLEEP_INTERVAL));
+        if shutdown_rx.recv_timeout(Duration::from_millis(SLEEP_INTERVAL)).is_ok() {
+            info!(LOG_TAG, "termination signal received, main thread shutting down");
+            shutdown(&mqtt_to_device_tx, &handle_vec);
+            break;
+        }
         let mut finish = false;
         for handle in handle_vec.iter_mut() {
             if handle.is_finished() {
@@ -48,3 +66,23 @@ fn main() {
         }
     }
 }
+
+/// ask device thread to set devices to safe state and stop buses,
+/// then wait for device, reporting, heartbeating and mqtt threads exiting within shutdown timeout.
+/// sqlite connections are opened per query and closed after using, nothing to close here
+fn shutdown(mqtt_to_device_tx: &Sender<MqttToDeviceEnum>, handle_vec: &[JoinHandle<()>]) {
+    if let Err(e) = mqtt_to_device_tx.send(MqttToDeviceEnum::Shutdown) {
+        error!(LOG_TAG, "cannot send shutdown command to device thread, error msg: {}", e);
+        return;
+    }
+    let deadline = Instant::now() + Duration::from_millis(Settings::get().server.shutdown_timeout);
+    while Instant::now() < deadline {
+        if handle_vec.iter().all(|handle| handle.is_finished()) {
+            info!(LOG_TAG, "all threads exited, shutdown done");
+            return;
+        }
+        thread::sleep(Duration::from_millis(SHUTDOWN_CHECK_INTERVAL));
+    }
+    let running = handle_vec.iter().filter(|handle| !handle.is_finished()).count();
+    warn!(LOG_TAG, "shutdown timeout, {} threads are still running", running);
+}
@@ -1,26 +1,36 @@
-use std::os::linux::raw;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use super::message_listener::on_message;
+use super::outbound_buffer::OutboundBuffer;
 use super::protocol::Protocol;
 use crate::common::error::{DeviceServerError, ServerErrorCode};
-use crate::common::mqtt;
+use crate::common::mqtt::{self, MqttTlsConfig};
 use crate::common::setting::Settings;
-use crate::entity::dto::device_command_dto::DeviceCommandDto;
+use crate::common::supervisor::supervise;
+use crate::entity::dto::command_reply_dto::CommandReplyDto;
 use crate::entity::dto::device_state_dto::StateToDeviceControllerDto;
-use crate::entity::dto::mqtt_dto::{DeviceToMqttEnum, MqttDataDeviceCommandDto, MqttPayloadDto};
+use crate::entity::dto::mqtt_dto::{DeviceToMqttEnum, MqttDataDeviceCommandDto, MqttPayloadDto, MqttToDeviceEnum};
 use crate::entity::dto::server_state_dto::{self, ServerStateDto};
 use crate::{debug, error, info, warn};
 use std::result::Result;
 
 const LOG_TAG: &str = "mqtt_client";
 
+/// interval of checking reconnect event when there is no outbound message
+const LOOP_INTERVAL: u64 = 1000;
+
+/// QoS of online / offline presence messages
+const PRESENCE_QOS: i32 = 1;
+
 pub struct MqttClient {
     // mqtt connection
     con: Option<mqtt::MqttConnection>,
     // message protocol
     protocol: Protocol,
+    // outbound messages buffered while disconnected
+    buffer: OutboundBuffer<DeviceToMqttEnum>,
 }
 
 impl MqttClient {
@@ -29,6 +39,7 @@ impl MqttClient {
         MqttClient {
             con: None,
             protocol: Protocol::new(),
+            buffer: OutboundBuffer::new(setting.mqtt.buffer_size, setting.mqtt.buffer_drop_policy),
         }
     }
 
@@ -38,7 +49,7 @@ impl MqttClient {
     /// after calling this function, ownership of mqtt client will be transferred into new thread
     pub fn start(
         mut self,
-        mqtt_to_device_tx: Sender<DeviceCommandDto>,
+        mqtt_to_device_tx: Sender<MqttToDeviceEnum>,
         device_to_mqtt_rx: Receiver<DeviceToMqttEnum>,
     ) -> JoinHandle<()> {
         thread::spawn(move || {
@@ -54,10 +65,49 @@ impl MqttClient {
                     .expect("mqtt broker port data type error, is not u16"),
                 setting.mqtt.client_id.as_str(),
             );
+            let reconnect_min_interval = Duration::from_secs(setting.mqtt.reconnect_min_interval);
+            let reconnect_max_interval = Duration::from_secs(setting.mqtt.reconnect_max_interval);
+            con.set_reconnect_interval(reconnect_min_interval, reconnect_max_interval);
+            if let Some(username) = &setting.mqtt.username {
+                con.set_credentials(
+                    username.as_str(),
+                    setting.mqtt.password.as_deref().unwrap_or_default(),
+                );
+            }
+            if setting.mqtt.tls {
+                con.set_tls(MqttTlsConfig {
+                    ca_file: setting.mqtt.ca_file.clone(),
+                    cert_file: setting.mqtt.cert_file.clone(),
+                    key_file: setting.mqtt.key_file.clone(),
+                });
+            }
 
-            con.connect().expect("mqtt connect error");
+            // last will, published by broker when the connection is lost abnormally
+            let offline_topic = self.protocol.topic_self_declare("offline", None, None);
+            let will_payload = self
+                .protocol
+                .payload_from_server(None, None, None, None)
+                .to_json()
+                .expect("cannot make mqtt last will payload");
+            con.set_last_will(offline_topic.as_str(), will_payload.as_str(), PRESENCE_QOS);
+
+            // automatic reconnect only works after the first successful connection,
+            // so retry the first connection with exponential backoff
+            let mut retry_interval = reconnect_min_interval;
+            while let Err(e) = con.connect() {
+                warn!(LOG_TAG, "mqtt connect failed, retry in {:?}, err: {e}", retry_interval);
+                thread::sleep(retry_interval);
+                retry_interval = (retry_interval * 2).min(reconnect_max_interval);
+            }
 
-            con.set_callback(move |_cli, msg| {
+            // subscriptions are lost after reconnecting (clean session), notify the loop to redo it
+            let (reconnected_tx, reconnected_rx) = mpsc::channel::<()>();
+            con.set_connected_callback(move |_cli| {
+                info!(LOG_TAG, "mqtt reconnected");
+                let _ = reconnected_tx.send(());
+            });
+
+            con.set_callback(move |cli, msg| {
                 if let Some(msg) = msg {
                     info!(
                         LOG_TAG,
@@ -65,7 +115,7 @@ impl MqttClient {
                         msg.topic()
                     );
                     let msg_copy = msg.clone();
-                    match on_message(msg, mqtt_to_device_tx.clone()) {
+                    match on_message(cli, msg, mqtt_to_device_tx.clone()) {
                         Ok(_) => {}
                         Err(e) => {
                             error!(
@@ -81,8 +131,16 @@ impl MqttClient {
 
             self.con = Some(con);
 
-            self.subscribe_topics()
-                .expect("mqtt subscribe topics error");
+            // subscription failure is retried in the loop, same as after reconnecting
+            let mut subscribe_pending = false;
+            if let Err(e) = self.subscribe_topics() {
+                error!(LOG_TAG, "mqtt subscribe topics error, retry in loop, err: {e}");
+                subscribe_pending = true;
+            }
+
+            if let Err(e) = self.publish_online() {
+                error!(LOG_TAG, "mqtt publish online error, err: {e}");
+            }
 
             info!(
                 LOG_TAG,
@@ -91,33 +149,139 @@ impl MqttClient {
                 setting.mqtt.broker_port
             );
 
-            // mqtt start ok, wait for inbounding messages
-            loop {
-                if let Ok(msg) = device_to_mqtt_rx.recv() {
-                    match msg {
-                        DeviceToMqttEnum::ServerState(server_state_dto) => {
-                            // server state message
-                            if let Err(e) = self.publish_heartbeat(server_state_dto) {
-                                error!(LOG_TAG, "mqtt publish heartbeat error, err: {e}");
+            // mqtt start ok, wait for inbounding messages, restart on panic
+            supervise("mqtt_client", |_| {}, || -> Result<(), DeviceServerError> {
+                loop {
+                    match device_to_mqtt_rx.recv_timeout(Duration::from_millis(LOOP_INTERVAL)) {
+                        Ok(DeviceToMqttEnum::Shutdown) => {
+                            info!(LOG_TAG, "mqtt client thread exiting: shutdown");
+                            // publish messages sent before shutdown
+                            while let Ok(msg) = device_to_mqtt_rx.try_recv() {
+                                if self.is_connected() {
+                                    self.dispatch_or_buffer(msg);
+                                } else {
+                                    self.buffer_message(msg);
+                                }
                             }
+                            self.shutdown();
+                            return Ok(());
                         }
-                        DeviceToMqttEnum::DeviceState(state_dto) => {
-                            // device state message
-                            if let Err(e) = self.publish_status(state_dto) {
-                                error!(LOG_TAG, "mqtt publish status error, err: {e}");
+                        Ok(msg) => {
+                            if self.is_connected() {
+                                self.flush_buffer();
+                                self.dispatch_or_buffer(msg);
+                            } else {
+                                self.buffer_message(msg);
                             }
                         }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => {
+                            warn!(LOG_TAG, "mqtt client thread exiting: device to mqtt channel closing");
+                            self.shutdown();
+                            return Ok(());
+                        }
+                    }
+
+                    if reconnected_rx.try_recv().is_ok() {
+                        while reconnected_rx.try_recv().is_ok() {}
+                        subscribe_pending = true;
+                        // last will may have been published by broker during disconnection
+                        if let Err(e) = self.publish_online() {
+                            error!(LOG_TAG, "mqtt publish online error, err: {e}");
+                        }
+                        if !self.buffer.is_empty() {
+                            info!(LOG_TAG, "mqtt flushing {} buffered messages", self.buffer.len());
+                            self.flush_buffer();
+                        }
+                    }
+
+                    if subscribe_pending && self.is_connected() {
+                        match self.subscribe_topics() {
+                            Ok(_) => subscribe_pending = false,
+                            Err(e) => error!(LOG_TAG, "mqtt subscribe topics error, retry in {}ms, err: {e}", LOOP_INTERVAL),
+                        }
                     }
                 }
-            }
+            });
         })
     }
 
+    /// flush buffered messages, publish offline message and disconnect
+    fn shutdown(&mut self) {
+        self.flush_buffer();
+        if let Err(e) = self.publish_offline() {
+            error!(LOG_TAG, "mqtt publish offline error, err: {e}");
+        }
+        if let Some(con) = &self.con {
+            if let Err(e) = con.disconnect() {
+                error!(LOG_TAG, "mqtt disconnect error, err: {e}");
+            }
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        match &self.con {
+            Some(con) => con.is_connected(),
+            None => false,
+        }
+    }
+
+    fn buffer_message(&mut self, msg: DeviceToMqttEnum) {
+        if !self.buffer.push(msg) {
+            warn!(LOG_TAG, "mqtt outbound buffer is full, message dropped");
+        }
+    }
+
+    /// publish message, put it back into buffer if the connection is lost during publishing
+    /// return false if the message is buffered
+    fn dispatch_or_buffer(&mut self, msg: DeviceToMqttEnum) -> bool {
+        match self.dispatch(&msg) {
+            Ok(_) => true,
+            Err(e) => {
+                if self.is_connected() {
+                    error!(LOG_TAG, "mqtt publish error, err: {e}");
+                    true
+                } else {
+                    warn!(LOG_TAG, "mqtt connection lost while publishing, buffer the message");
+                    self.buffer.push_front(msg);
+                    false
+                }
+            }
+        }
+    }
+
+    /// send all buffered messages in order, stop when the connection is lost again
+    fn flush_buffer(&mut self) {
+        while self.is_connected() {
+            match self.buffer.pop() {
+                Some(msg) => {
+                    if !self.dispatch_or_buffer(msg) {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    fn dispatch(&self, msg: &DeviceToMqttEnum) -> Result<(), DeviceServerError> {
+        match msg {
+            // server state message
+            DeviceToMqttEnum::ServerState(server_state_dto) => self.publish_heartbeat(server_state_dto),
+            // device state message
+            DeviceToMqttEnum::DeviceState(state_dto) => self.publish_status(state_dto),
+            // device command reply message
+            DeviceToMqttEnum::CommandReply(reply_dto) => self.publish_reply(reply_dto),
+            // handled in the main loop
+            DeviceToMqttEnum::Shutdown => Ok(()),
+        }
+    }
+
     /// according topic and payload to publish message
-    pub fn publish(&self, topic: &str, payload: &str) -> Result<(), DeviceServerError> {
+    pub fn publish(&self, topic: &str, payload: &str, qos: i32, retained: bool) -> Result<(), DeviceServerError> {
         match &self.con {
             Some(con) => {
-                con.publish(topic, payload).map_err(|e| DeviceServerError {
+                con.publish(topic, payload, qos, retained).map_err(|e| DeviceServerError {
                     code: ServerErrorCode::MqttError,
                     msg: format!("mqtt publish error: {e}"),
                 })?;
@@ -133,7 +297,7 @@ impl MqttClient {
     }
 
     /// publish heartbeat message
-    pub fn publish_heartbeat(&self, server_state: ServerStateDto) -> Result<(), DeviceServerError> {
+    pub fn publish_heartbeat(&self, server_state: &ServerStateDto) -> Result<(), DeviceServerError> {
         // 1 make topic
         let topic = self.protocol.topic_self_declare("status", None, None);
 
@@ -149,13 +313,14 @@ impl MqttClient {
             .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish heartbeat message, transform from payload to json failed, json error: {e}")})?;
 
         // 4 publish
-        self.publish(topic.as_str(), json_str.as_str())?;
+        let setting = Settings::get();
+        self.publish(topic.as_str(), json_str.as_str(), setting.mqtt.heartbeat_qos, false)?;
 
         Ok(())
     }
 
     /// publish device status message
-    pub fn publish_status(&self, state_dto: StateToDeviceControllerDto) -> Result<(), DeviceServerError> {
+    pub fn publish_status(&self, state_dto: &StateToDeviceControllerDto) -> Result<(), DeviceServerError> {
         let topic = self.protocol.topic_self_declare(
             "status",
             Some(state_dto.device_class.clone()),
@@ -174,26 +339,51 @@ impl MqttClient {
 
         let json_str = payload.to_json()
             .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish status message, transform from payload to json failed, json error: {e}")})?;
-        self.publish(topic.as_str(), json_str.as_str())?;
+        let setting = Settings::get();
+        self.publish(topic.as_str(), json_str.as_str(), setting.mqtt.status_qos, setting.mqtt.retain_status)?;
         Ok(())
     }
 
-    /// publish offline message
+    /// publish command reply message, the reply carries the session_id of the command
+    pub fn publish_reply(&self, reply_dto: &CommandReplyDto) -> Result<(), DeviceServerError> {
+        let topic = self.protocol.topic_self_declare(
+            "reply",
+            reply_dto.device_type.clone(),
+            reply_dto.device_id.clone(),
+        );
+        let payload = self.protocol.reply_payload(reply_dto);
+
+        let json_str = payload.to_json()
+            .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish reply message, transform from payload to json failed, json error: {e}")})?;
+        let setting = Settings::get();
+        self.publish(topic.as_str(), json_str.as_str(), setting.mqtt.command_qos, false)?;
+        Ok(())
+    }
+
+    /// publish retained online presence message, and clear the retained offline message
+    pub fn publish_online(&self) -> Result<(), DeviceServerError> {
+        let topic = self.protocol.topic_self_declare("online", None, None);
+        let payload = self.protocol.payload_from_server(None, None, None, None);
+        let json_str = payload.to_json()
+            .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish online message, transform from payload to json failed, json error: {e}")})?;
+        self.publish(topic.as_str(), json_str.as_str(), PRESENCE_QOS, true)?;
+
+        let offline_topic = self.protocol.topic_self_declare("offline", None, None);
+        self.publish(offline_topic.as_str(), "", PRESENCE_QOS, true)?;
+        Ok(())
+    }
+
+    /// publish retained offline message, and clear the retained online message
     pub fn publish_offline(&self) -> Result<(), DeviceServerError> {
-        match &self.con {
-            Some(con) => {
-                let topic = self.protocol.topic_self_declare("offline", None, None);
-                let payload = self.protocol.payload_from_server(None, None, None, None);
-                let json_str = payload.to_json()
-                    .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish offline message, transform from payload to json failed, json error: {e}")})?;
-                self.publish(topic.as_str(), json_str.as_str())?;
-                Ok(())
-            }
-            None => Err(DeviceServerError {
-                code: ServerErrorCode::MqttError,
-                msg: format!("mqtt publish error: not connect"),
-            }),
-        }
+        let topic = self.protocol.topic_self_declare("offline", None, None);
+        let payload = self.protocol.payload_from_server(None, None, None, None);
+        let json_str = payload.to_json()
+            .map_err(|e| DeviceServerError {code: ServerErrorCode::MqttError, msg: format!("cannot publish offline message, transform from payload to json failed, json error: {e}")})?;
+        self.publish(topic.as_str(), json_str.as_str(), PRESENCE_QOS, true)?;
+
+        let online_topic = self.protocol.topic_self_declare("online", None, None);
+        self.publish(online_topic.as_str(), "", PRESENCE_QOS, true)?;
+        Ok(())
     }
 
     /// register predefined topics
@@ -228,7 +418,7 @@ impl MqttClient {
         match &self.con {
             Some(con) => {
                 info!(LOG_TAG, "mqtt subscribe topic: {}", topic);
-                con.subscribe(topic).map_err(|e| DeviceServerError {
+                con.subscribe(topic, Settings::get().mqtt.command_qos).map_err(|e| DeviceServerError {
                     code: ServerErrorCode::MqttError,
                     msg: format!("mqtt subscribe error: {e}"),
                 })?;
@@ -0,0 +1,60 @@
+//! send broadcast command to all matching devices
+
+use std::sync::mpsc::Sender;
+
+use paho_mqtt::AsyncClient;
+
+use crate::{
+    common::error::{DeviceServerError, ServerErrorCode},
+    entity::dto::{
+        broadcast_command_dto::{BroadcastActionEnum, BroadcastCommandDto, BroadcastFilterDto},
+        command_reply_dto::{REPLY_CODE_ERROR, REPLY_CODE_PARAM_FAIL},
+        mqtt_dto::{MqttPayloadDto, MqttToDeviceEnum},
+    },
+};
+
+use super::device_commander::reply_failure;
+
+/// send broadcast command to device manager
+/// param of the payload is the optional filter: {"device_type": "xxx", "room": "xxx"}
+pub fn broadcast_command(
+    cli: &AsyncClient,
+    action: BroadcastActionEnum,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let action_str = payload.data["action"].as_str().unwrap_or("").to_string();
+
+    let param = payload.data["param"].clone();
+    let filter: BroadcastFilterDto = if param.is_null() {
+        BroadcastFilterDto::default()
+    } else {
+        match serde_json::from_value(param) {
+            Ok(filter) => filter,
+            Err(e) => {
+                let msg = format!("parse broadcast filter from json to dto error: {e}");
+                reply_failure(cli, REPLY_CODE_PARAM_FAIL, &msg, session_id, None, None, action_str);
+                return Err(DeviceServerError {
+                    code: ServerErrorCode::MqttError,
+                    msg,
+                });
+            }
+        }
+    };
+
+    let broadcast_dto = BroadcastCommandDto {
+        session_id: session_id.clone(),
+        action,
+        filter,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::Broadcast(broadcast_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send broadcast command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, None, action_str);
+        return Err(err);
+    }
+    Ok(())
+}
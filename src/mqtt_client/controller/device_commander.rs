@@ -1,36 +1,282 @@
 use std::sync::mpsc::Sender;
+use std::thread;
+
+use paho_mqtt::AsyncClient;
 
 use crate::{
     common::error::{DeviceServerError, ServerErrorCode},
+    common::setting::Settings,
+    device_controller::driver_registry::DriverRegistry,
     entity::dto::{
-        device_command_dto::{AudioParamsDto, CommandParamsEnum, DeviceCommandDto},
-        mqtt_dto::{MqttDataDeviceCommandDto, MqttPayloadDto, MqttTopicDto},
+        batch_command_dto::{BatchCommandDto, BatchParamDto},
+        get_state_command_dto::{GetStateCommandDto, GetStateParamDto},
+        reload_devices_command_dto::ReloadDevicesCommandDto,
+        scene_command_dto::{SceneActionEnum, SceneCommandDto, SceneRequestEnum},
+        schedule_command_dto::{ScheduleActionEnum, ScheduleCommandDto, ScheduleRequestEnum},
+        command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR, REPLY_CODE_PARAM_FAIL},
+        device_command_dto::DeviceCommandDto,
+        mqtt_dto::{MqttDataDeviceCommandDto, MqttPayloadDto, MqttToDeviceEnum, MqttTopicDto},
     },
+    error,
 };
 
+use super::super::protocol::Protocol;
+
+const LOG_TAG: &str = "device_commander";
+
 /// send device command to device manager
+/// if the command cannot be delivered to device manager, reply the failure to flow server directly
 pub fn control_device_command(
+    cli: &AsyncClient,
     topic: MqttTopicDto,
     payload: MqttPayloadDto,
-    command_tx: Sender<DeviceCommandDto>,
+    command_tx: Sender<MqttToDeviceEnum>,
 ) -> Result<(), DeviceServerError> {
-    let device_command_dto = make_device_command_dto(topic, payload)?;
-    command_tx
-        .send(device_command_dto)
-        .map_err(|e| DeviceServerError {
+    let session_id = payload.session_id.clone();
+    let device_type = topic.device_type.clone();
+    let device_id = topic.device_id.clone();
+    let action = payload.data["action"].as_str().unwrap_or("").to_string();
+
+    // get is supported by every device type
+    if action == "get" {
+        return control_get_command(cli, device_id, payload, command_tx);
+    }
+
+    let device_command_dto = match make_device_command_dto(topic, payload) {
+        Ok(dto) => dto,
+        Err(e) => {
+            reply_failure(cli, REPLY_CODE_PARAM_FAIL, &e.msg, session_id, device_type, device_id, action);
+            return Err(e);
+        }
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::DeviceCommand(device_command_dto)) {
+        let err = DeviceServerError {
             code: ServerErrorCode::MqttError,
             msg: format!("send device command dto error: {e}"),
-        })?;
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, device_type, device_id, action);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// send batch command to device manager
+/// param of the payload: {"atomic": false, "commands": [{"device_id": "xxx", "action": "xxx", "param": null}]}
+pub fn control_batch_command(
+    cli: &AsyncClient,
+    topic: MqttTopicDto,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let action = payload.data["action"].as_str().unwrap_or("").to_string();
+
+    let batch_param: BatchParamDto = match serde_json::from_value(payload.data["param"].clone()) {
+        Ok(batch_param) => batch_param,
+        Err(e) => {
+            let msg = format!("parse batch params from json to dto error: {e}");
+            reply_failure(cli, REPLY_CODE_PARAM_FAIL, &msg, session_id, None, None, action);
+            return Err(DeviceServerError {
+                code: ServerErrorCode::MqttError,
+                msg,
+            });
+        }
+    };
+
+    let batch_command_dto = BatchCommandDto {
+        server_id: topic.server_id.unwrap_or_default(),
+        session_id: session_id.clone(),
+        atomic: batch_param.atomic,
+        commands: batch_param.commands,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::Batch(batch_command_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send batch command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, None, action);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// send state query command to device manager, device_id is none for querying all devices
+/// param of the payload: {"fresh": false}
+pub fn control_get_command(
+    cli: &AsyncClient,
+    device_id: Option<String>,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let param = payload.data["param"].clone();
+    let get_param: GetStateParamDto = if param.is_null() {
+        GetStateParamDto::default()
+    } else {
+        match serde_json::from_value(param) {
+            Ok(get_param) => get_param,
+            Err(e) => {
+                let msg = format!("parse get params from json to dto error: {e}");
+                reply_failure(cli, REPLY_CODE_PARAM_FAIL, &msg, session_id, None, device_id, "get".to_string());
+                return Err(DeviceServerError {
+                    code: ServerErrorCode::MqttError,
+                    msg,
+                });
+            }
+        }
+    };
+
+    let get_command_dto = GetStateCommandDto {
+        session_id: session_id.clone(),
+        device_id: device_id.clone(),
+        fresh: get_param.fresh,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::GetState(get_command_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send get command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, device_id, "get".to_string());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// send reload devices command to device manager, the reply is sent after reloading
+pub fn control_reload_devices_command(
+    cli: &AsyncClient,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let reload_command_dto = ReloadDevicesCommandDto {
+        session_id: session_id.clone(),
+        reply_tx: None,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::ReloadDevices(reload_command_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send reload devices command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, None, "reload_devices".to_string());
+        return Err(err);
+    }
     Ok(())
 }
 
+/// send scene command to device manager
+/// param of scene_save: {"scene_id": "xxx", "name": "xxx", "targets": [{"device_id": "xxx", "action": "xxx", "param": null}]}
+/// param of recall_scene: {"scene_id": "xxx", "fade": 2000}
+pub fn control_scene_command(
+    cli: &AsyncClient,
+    action: SceneActionEnum,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let request = match SceneRequestEnum::from_action(action, payload.data["param"].clone()) {
+        Ok(request) => request,
+        Err(e) => {
+            let msg = format!("parse {} params from json to dto error: {e}", action.as_str());
+            reply_failure(cli, REPLY_CODE_PARAM_FAIL, &msg, session_id, None, None, action.as_str().to_string());
+            return Err(DeviceServerError {
+                code: ServerErrorCode::MqttError,
+                msg,
+            });
+        }
+    };
+
+    let scene_command_dto = SceneCommandDto {
+        session_id: session_id.clone(),
+        request,
+        reply_tx: None,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::Scene(scene_command_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send scene command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, None, action.as_str().to_string());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// send schedule command to device manager, it is forwarded to scheduler thread
+/// param of schedule_add: {"schedule_id": "xxx", "device_id": "xxx", "action": "on", "param": null, "cron": "0 9 * * *"}
+/// param of schedule_cancel: {"schedule_id": "xxx"}
+pub fn control_schedule_command(
+    cli: &AsyncClient,
+    action: ScheduleActionEnum,
+    payload: MqttPayloadDto,
+    command_tx: Sender<MqttToDeviceEnum>,
+) -> Result<(), DeviceServerError> {
+    let session_id = payload.session_id.clone();
+    let request = match ScheduleRequestEnum::from_action(action, payload.data["param"].clone()) {
+        Ok(request) => request,
+        Err(e) => {
+            let msg = format!("parse {} params from json to dto error: {e}", action.as_str());
+            reply_failure(cli, REPLY_CODE_PARAM_FAIL, &msg, session_id, None, None, action.as_str().to_string());
+            return Err(DeviceServerError {
+                code: ServerErrorCode::MqttError,
+                msg,
+            });
+        }
+    };
+
+    let schedule_command_dto = ScheduleCommandDto {
+        session_id: session_id.clone(),
+        request,
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::Schedule(schedule_command_dto)) {
+        let err = DeviceServerError {
+            code: ServerErrorCode::MqttError,
+            msg: format!("send schedule command dto error: {e}"),
+        };
+        reply_failure(cli, REPLY_CODE_ERROR, &err.msg, session_id, None, None, action.as_str().to_string());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// publish failure reply on the mqtt callback thread
+pub fn reply_failure(
+    cli: &AsyncClient,
+    code: i32,
+    msg: &str,
+    session_id: String,
+    device_type: Option<String>,
+    device_id: Option<String>,
+    action: String,
+) {
+    let protocol = Protocol::new();
+    let topic = protocol.topic_self_declare("reply", device_type.clone(), device_id.clone());
+    let payload = protocol.reply_payload(&CommandReplyDto {
+        code,
+        msg: msg.to_string(),
+        device_type,
+        device_id,
+        ..CommandReplyDto::new(session_id.clone(), action)
+    });
+    match payload.to_json() {
+        Ok(json_str) => {
+            let token = cli.publish(paho_mqtt::Message::new(topic, json_str, Settings::get().mqtt.command_qos));
+            // waiting for the token in the callback thread blocks the delivery, wait in another thread
+            thread::spawn(move || {
+                if let Err(e) = token.wait() {
+                    error!(LOG_TAG, "publish failure reply error, session_id: {}, error msg: {}", session_id, e);
+                }
+            });
+        }
+        Err(e) => error!(LOG_TAG, "make failure reply error, session_id: {}, error msg: {}", session_id, e),
+    }
+}
+
 /// make device command dto from topic and payload
 fn make_device_command_dto(
     topic: MqttTopicDto,
     payload: MqttPayloadDto,
 ) -> Result<DeviceCommandDto, DeviceServerError> {
-    let mut params = CommandParamsEnum::Empty;
-
     // get action from payload
     let action = payload.data["action"]
         .as_str()
@@ -43,18 +289,17 @@ fn make_device_command_dto(
     let param = payload.data["param"].clone();
 
     // set pararms according to different device type
-    if topic.device_type == Some("audio".to_string()) {
-        let audio_params: AudioParamsDto =
-            serde_json::from_value(param).map_err(|e| DeviceServerError {
-                code: ServerErrorCode::MqttError,
-                msg: format!("parse audio params from json to dto error: {e}"),
-            })?;
-        params = CommandParamsEnum::Audio(audio_params);
-    }
+    let device_type = topic.device_type.clone().unwrap_or_default();
+    let params = DriverRegistry::get().parse_command(device_type.as_str(), action.as_str(), param).map_err(|e| DeviceServerError {
+        code: ServerErrorCode::MqttError,
+        msg: format!("invalid {} command `{}`: {e}", device_type, action),
+    })?;
 
     Ok(DeviceCommandDto {
         server_id: topic.server_id.unwrap(),
         device_id: topic.device_id.unwrap(),
+        device_type,
+        session_id: payload.session_id,
         action: action,
         params: params,
     })
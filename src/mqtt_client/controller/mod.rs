@@ -1,2 +1,3 @@
 pub mod device_commander;
-pub mod server_updater;
\ No newline at end of file
+pub mod server_updater;
+pub mod broadcaster;
\ No newline at end of file
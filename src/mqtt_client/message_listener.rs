@@ -7,16 +7,22 @@ use paho_mqtt::{AsyncClient, Message};
 use crate::{
     common::error::{DeviceServerError, ServerErrorCode},
     entity::dto::{
-        device_command_dto::{AudioParamsDto, CommandParamsEnum, DeviceCommandDto},
-        mqtt_dto::{MqttDataDeviceCommandDto, MqttPayloadDto, MqttTopicDto},
+        broadcast_command_dto::BroadcastActionEnum,
+        command_reply_dto::REPLY_CODE_PARAM_FAIL,
+        scene_command_dto::SceneActionEnum,
+        schedule_command_dto::ScheduleActionEnum,
+        device_command_dto::{AudioParamsDto, CommandParamsEnum},
+        mqtt_dto::{MqttDataDeviceCommandDto, MqttPayloadDto, MqttToDeviceEnum, MqttTopicDto},
     },
 };
 
-use super::{controller::device_commander::control_device_command, protocol::Protocol, controller::server_updater::update};
+use super::{controller::device_commander::{control_batch_command, control_device_command, control_get_command, control_reload_devices_command, control_scene_command, control_schedule_command, reply_failure}, protocol::Protocol, controller::server_updater::update};
+use super::controller::broadcaster::broadcast_command;
 
 pub fn on_message(
+    cli: &AsyncClient,
     msg: Message,
-    command_tx: Sender<DeviceCommandDto>,
+    command_tx: Sender<MqttToDeviceEnum>,
 ) -> Result<(), DeviceServerError> {
     // 1. parse topic
     let topic_dto = Protocol::parse_topic(msg.topic()).map_err(|e| DeviceServerError {
@@ -35,7 +41,7 @@ pub fn on_message(
 
     if let Some(ref device_id) = topic_dto.device_id {
         // 3.1 if there is device_id in topic_dto, which means that is a device command
-        control_device_command(topic_dto, payload_dto, command_tx)?;
+        control_device_command(cli, topic_dto, payload_dto, command_tx)?;
     } else {
         // 3.2 if there is not device_id, which means the target of the message is server
         // updating file controller data
@@ -47,6 +53,24 @@ pub fn on_message(
         )?;
         if action == "update" {
             update(topic_dto, payload_dto)?;
+        } else if action == "get" {
+            control_get_command(cli, None, payload_dto, command_tx)?;
+        } else if action == "reload_devices" {
+            control_reload_devices_command(cli, payload_dto, command_tx)?;
+        } else if action == "batch" {
+            control_batch_command(cli, topic_dto, payload_dto, command_tx)?;
+        } else if let Some(scene_action) = SceneActionEnum::from_action(action) {
+            control_scene_command(cli, scene_action, payload_dto, command_tx)?;
+        } else if let Some(schedule_action) = ScheduleActionEnum::from_action(action) {
+            control_schedule_command(cli, schedule_action, payload_dto, command_tx)?;
+        } else if let Some(broadcast_action) = BroadcastActionEnum::from_action(action) {
+            broadcast_command(cli, broadcast_action, payload_dto, command_tx)?;
+        } else {
+            reply_failure(cli, REPLY_CODE_PARAM_FAIL, "unknown action", payload_dto.session_id.clone(), None, None, action.to_string());
+            return Err(DeviceServerError {
+                code: ServerErrorCode::MqttError,
+                msg: format!("unknown action: {action}"),
+            });
         }
     }
 
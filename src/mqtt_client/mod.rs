@@ -1,4 +1,5 @@
 pub mod client;
 mod protocol;
 pub mod controller;
-pub mod message_listener;
\ No newline at end of file
+pub mod message_listener;
+mod outbound_buffer;
\ No newline at end of file
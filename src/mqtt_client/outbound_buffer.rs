@@ -0,0 +1,103 @@
+//! 断线期间的上行消息缓存
+//! 有上限，缓存满时按照丢弃策略丢弃最旧或最新的消息
+
+use std::collections::VecDeque;
+
+use crate::common::setting::BufferDropPolicy;
+
+pub struct OutboundBuffer<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    drop_policy: BufferDropPolicy,
+}
+
+impl<T> OutboundBuffer<T> {
+    pub fn new(capacity: usize, drop_policy: BufferDropPolicy) -> Self {
+        OutboundBuffer {
+            queue: VecDeque::new(),
+            capacity,
+            drop_policy,
+        }
+    }
+
+    /// push message to the end of buffer
+    /// return false if a message is dropped
+    pub fn push(&mut self, item: T) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        if self.queue.len() < self.capacity {
+            self.queue.push_back(item);
+            return true;
+        }
+        match self.drop_policy {
+            BufferDropPolicy::Oldest => {
+                self.queue.pop_front();
+                self.queue.push_back(item);
+            }
+            BufferDropPolicy::Newest => {}
+        }
+        false
+    }
+
+    /// put back a message which failed to send, it will be sent first next time
+    pub fn push_front(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.queue.len() >= self.capacity {
+            self.queue.pop_back();
+        }
+        self.queue.push_front(item);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.queue.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drop_oldest() {
+        let mut buffer = OutboundBuffer::new(2, BufferDropPolicy::Oldest);
+        assert!(buffer.push(1));
+        assert!(buffer.push(2));
+        assert!(!buffer.push(3));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let mut buffer = OutboundBuffer::new(2, BufferDropPolicy::Newest);
+        assert!(buffer.push(1));
+        assert!(buffer.push(2));
+        assert!(!buffer.push(3));
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_push_front() {
+        let mut buffer = OutboundBuffer::new(2, BufferDropPolicy::Oldest);
+        buffer.push(1);
+        buffer.push(2);
+        buffer.push_front(0);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some(0));
+        assert_eq!(buffer.pop(), Some(1));
+    }
+}
@@ -1,5 +1,6 @@
 
 use std::error::Error;
+use crate::entity::dto::command_reply_dto::{CommandReplyDto, REPLY_CODE_OK, REPLY_CODE_PARAM_FAIL};
 use crate::entity::dto::mqtt_dto::{MqttTopicDto, MqttPayloadDto};
 use crate::common::setting::Settings;
 
@@ -44,6 +45,9 @@ impl Protocol {
     /// parse topic to dto
     pub fn parse_topic(topic_str: &str) -> Result<MqttTopicDto, Box<dyn Error>>{
         let topic_vec: Vec<&str> = topic_str.split("/").collect();
+        if topic_vec.len() < 2 {
+            return Err(format!("invalid topic: {}", topic_str).into());
+        }
         let command = topic_vec[0].to_string();
         let application = topic_vec[1].to_string();
         let mut scenario = Option::None;
@@ -56,12 +60,12 @@ impl Protocol {
             scenario = Some(topic_vec[2].to_string());
         }
 
-        if topic_vec.len() >= 4 {
+        if topic_vec.len() >= 5 {
             server_type = Some(topic_vec[3].to_string());
             server_id = Some(topic_vec[4].to_string());
         }
 
-        if topic_vec.len() >= 6 {
+        if topic_vec.len() >= 7 {
             device_type = Some(topic_vec[5].to_string());
             device_id = Some(topic_vec[6].to_string());
         }
@@ -152,7 +156,7 @@ impl Protocol {
     }
 
     /// 发送参数错误消息
-    pub fn param_fail_payload(self, msg: Option<String>, session_id: Option<String>, target_type: Option<String>, target_id: Option<String>) -> MqttPayloadDto {
+    pub fn param_fail_payload(&self, msg: Option<String>, session_id: Option<String>, target_type: Option<String>, target_id: Option<String>) -> MqttPayloadDto {
         MqttPayloadDto::new(
             Some(400),
             msg,
@@ -180,4 +184,33 @@ impl Protocol {
             data
         )
     }
+
+    /// 指令回复消息，根据 code 生成不同的 payload
+    /// code 200: command applied, 400: command param error, 500: device error
+    pub fn reply_payload(&self, reply_dto: &CommandReplyDto) -> MqttPayloadDto {
+        let mut data = serde_json::json!({
+            "device_id": reply_dto.device_id,
+            "action": reply_dto.action,
+            "state": reply_dto.state,
+        });
+        if let Some(results) = &reply_dto.results {
+            data["results"] = serde_json::json!(results);
+        }
+        if let Some(status) = &reply_dto.status {
+            data["status"] = serde_json::json!(status);
+        }
+        if let Some(schedules) = &reply_dto.schedules {
+            data["schedules"] = serde_json::json!(schedules);
+        }
+        if let Some(scenes) = &reply_dto.scenes {
+            data["scenes"] = serde_json::json!(scenes);
+        }
+        let mut payload = match reply_dto.code {
+            REPLY_CODE_OK => self.payload_from_server(None, Some(reply_dto.session_id.clone()), None, reply_dto.device_id.clone()),
+            REPLY_CODE_PARAM_FAIL => self.param_fail_payload(Some(reply_dto.msg.clone()), Some(reply_dto.session_id.clone()), None, reply_dto.device_id.clone()),
+            _ => self.error_payload(Some(reply_dto.msg.clone()), Some(reply_dto.session_id.clone()), None, reply_dto.device_id.clone()),
+        };
+        payload.data = data;
+        payload
+    }
 }
\ No newline at end of file
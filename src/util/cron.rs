@@ -0,0 +1,287 @@
+//! cron expression with 5 fields: minute hour day-of-month month day-of-week
+//! each field accepts `*`, numbers, ranges `a-b`, lists `a,b` and steps `*/n` or `a-b/n`
+//! day-of-week is 0-7, both 0 and 7 are sunday
+//! if both day-of-month and day-of-week are restricted, the day matches either of them (same as crontab)
+//! the expression is matched against local time of the timezone, daylight saving time is followed:
+//! - local time skipped by the clock jumping forward fires at the end of the gap
+//! - local time repeated by the clock falling back fires once, at the first occurrence
+
+use chrono::{DateTime, LocalResult, Offset, TimeZone};
+use chrono_tz::Tz;
+
+const MINUTES_PER_DAY: i64 = 1440;
+const MILLIS_PER_MINUTE: i64 = 60000;
+// stop searching if there is no match in 5 years, e.g. "0 0 30 2 *"
+const MAX_SEARCH_MINUTES: i64 = 5 * 366 * MINUTES_PER_DAY;
+// longest gap of local time skipped by daylight saving time
+const MAX_GAP_MINUTES: i64 = MINUTES_PER_DAY;
+
+/// timezone that cron expressions are matched in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CronTimezone {
+    // timezone of the system
+    Local,
+    // IANA timezone name, e.g. Asia/Shanghai
+    Named(Tz),
+}
+
+impl CronTimezone {
+    /// system local timezone if name is none
+    pub fn parse(name: Option<&str>) -> Result<Self, String> {
+        match name {
+            Some(name) => name
+                .parse::<Tz>()
+                .map(CronTimezone::Named)
+                .map_err(|e| format!("invalid timezone `{}`: {}", name, e)),
+            None => Ok(CronTimezone::Local),
+        }
+    }
+
+    /// local time of the epoch millis, in minutes since local epoch
+    fn local_minute(&self, millis: u64) -> i64 {
+        let minute = (millis as i64).div_euclid(MILLIS_PER_MINUTE);
+        let utc = match DateTime::from_timestamp(minute * 60, 0) {
+            Some(utc) => utc.naive_utc(),
+            None => return minute,
+        };
+        let offset_seconds = match self {
+            CronTimezone::Local => chrono::Local.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+            CronTimezone::Named(tz) => tz.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+        };
+        minute + offset_seconds as i64 / 60
+    }
+
+    /// epoch millis of the local time in minutes, the first one if repeated, none if skipped
+    fn to_millis(self, local_minute: i64) -> Option<u64> {
+        let local = DateTime::from_timestamp(local_minute * 60, 0)?.naive_utc();
+        let millis = match self {
+            CronTimezone::Local => earliest_millis(chrono::Local.from_local_datetime(&local)),
+            CronTimezone::Named(tz) => earliest_millis(tz.from_local_datetime(&local)),
+        }?;
+        u64::try_from(millis).ok()
+    }
+}
+
+fn earliest_millis<T: TimeZone>(result: LocalResult<DateTime<T>>) -> Option<i64> {
+    result.earliest().map(|time| time.timestamp_millis())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpr {
+    // bit n is set if value n matches
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // day-of-month / day-of-week is `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("cron expression should have 5 fields, got {}: `{}`", fields.len(), expr));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(CronExpr {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// the first matching time strictly after the given time, in epoch millis
+    /// the expression is matched against local time of the timezone
+    pub fn next_after(&self, after_millis: u64, timezone: CronTimezone) -> Option<u64> {
+        let start = timezone.local_minute(after_millis) + 1;
+        let mut t = start;
+        while t - start <= MAX_SEARCH_MINUTES {
+            let days = t.div_euclid(MINUTES_PER_DAY);
+            let minute_of_day = t.rem_euclid(MINUTES_PER_DAY);
+            let (_, month, day) = civil_from_days(days);
+            // 1970-01-01 is thursday
+            let weekday = (days + 4).rem_euclid(7);
+            if !has_bit(self.months, month as i64) || !self.match_day(day as i64, weekday) {
+                t = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            let hour = minute_of_day / 60;
+            if !has_bit(self.hours, hour) {
+                t = days * MINUTES_PER_DAY + (hour + 1) * 60;
+                continue;
+            }
+            if !has_bit(self.minutes, minute_of_day % 60) {
+                t += 1;
+                continue;
+            }
+            match resolve_local_minute(t, timezone) {
+                // repeated local time already passed
+                Some(millis) if millis > after_millis => return Some(millis),
+                _ => t += 1,
+            }
+        }
+        None
+    }
+
+    fn match_day(&self, day: i64, weekday: i64) -> bool {
+        let day_match = has_bit(self.days, day);
+        let weekday_match = has_bit(self.weekdays, weekday);
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday_match,
+            (false, true) => day_match,
+            (false, false) => day_match || weekday_match,
+        }
+    }
+}
+
+/// epoch millis of the local time, skipped local time is moved to the end of the gap
+fn resolve_local_minute(local_minute: i64, timezone: CronTimezone) -> Option<u64> {
+    (local_minute..=local_minute + MAX_GAP_MINUTES).find_map(|t| timezone.to_millis(t))
+}
+
+fn has_bit(mask: u64, n: i64) -> bool {
+    mask & (1 << n) != 0
+}
+
+/// parse one field into bit mask
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step in cron field `{}`", field))?;
+                if step == 0 {
+                    return Err(format!("step should be positive in cron field `{}`", field));
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, field)?, parse_value(end, field)?)
+        } else {
+            let value = parse_value(range, field)?;
+            // `a/n` means from a to max
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("value out of range {}-{} in cron field `{}`", min, max, field));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, field: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid value `{}` in cron field `{}`", value, field))
+}
+
+/// convert days since epoch to (year, month, day), proleptic gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 00:00 UTC, monday
+    const DAY_2024_01_01: u64 = 19723 * 86400000;
+    const HOUR: u64 = 3600000;
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(19723 + 59), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(CronExpr::parse("0 9 * * 1-5").is_ok());
+        assert!(CronExpr::parse("*/15 8-18 1,15 * 7").is_ok());
+        assert!(CronExpr::parse("0 9 * *").is_err());
+        assert!(CronExpr::parse("60 9 * * *").is_err());
+        assert!(CronExpr::parse("0 9 * * mon").is_err());
+        assert!(CronExpr::parse("*/0 9 * * *").is_err());
+    }
+
+    const UTC: CronTimezone = CronTimezone::Named(chrono_tz::UTC);
+
+    #[test]
+    fn test_timezone_parse() {
+        assert_eq!(CronTimezone::parse(None), Ok(CronTimezone::Local));
+        assert_eq!(CronTimezone::parse(Some("Asia/Shanghai")), Ok(CronTimezone::Named(chrono_tz::Asia::Shanghai)));
+        assert!(CronTimezone::parse(Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let cron = CronExpr::parse("0 9 * * *").unwrap();
+        let now = DAY_2024_01_01 + 8 * HOUR;
+        assert_eq!(cron.next_after(now, UTC), Some(DAY_2024_01_01 + 9 * HOUR));
+        // strictly after
+        assert_eq!(cron.next_after(DAY_2024_01_01 + 9 * HOUR, UTC), Some(DAY_2024_01_01 + 33 * HOUR));
+        // 09:00 at UTC+8 is 01:00 UTC
+        let shanghai = CronTimezone::Named(chrono_tz::Asia::Shanghai);
+        assert_eq!(cron.next_after(now, shanghai), Some(DAY_2024_01_01 + 25 * HOUR));
+    }
+
+    #[test]
+    fn test_next_after_dst() {
+        let berlin = CronTimezone::Named(chrono_tz::Europe::Berlin);
+        // 2024-03-31 02:00 CET jumps to 03:00 CEST
+        let day_03_31 = DAY_2024_01_01 + 90 * 24 * HOUR;
+        // 08:00 is 07:00 UTC before, 06:00 UTC after
+        let cron = CronExpr::parse("0 8 * * *").unwrap();
+        assert_eq!(cron.next_after(day_03_31 - 24 * HOUR, berlin), Some(day_03_31 - 24 * HOUR + 7 * HOUR));
+        assert_eq!(cron.next_after(day_03_31, berlin), Some(day_03_31 + 6 * HOUR));
+        // skipped 02:30 fires at 03:00 CEST
+        let cron = CronExpr::parse("30 2 * * *").unwrap();
+        assert_eq!(cron.next_after(day_03_31 - 12 * HOUR, berlin), Some(day_03_31 + HOUR));
+        assert_eq!(cron.next_after(day_03_31 + HOUR, berlin), Some(day_03_31 + 24 * HOUR + HOUR / 2));
+
+        // 2024-10-27 03:00 CEST falls back to 02:00 CET, repeated 02:30 fires once
+        let day_10_27 = DAY_2024_01_01 + 300 * 24 * HOUR;
+        assert_eq!(cron.next_after(day_10_27 - 12 * HOUR, berlin), Some(day_10_27 + HOUR / 2));
+        assert_eq!(cron.next_after(day_10_27 + HOUR / 2, berlin), Some(day_10_27 + 24 * HOUR + 3 * HOUR / 2));
+    }
+
+    #[test]
+    fn test_next_after_weekday() {
+        // saturday and sunday, 2024-01-06 is saturday
+        let cron = CronExpr::parse("30 18 * * 6,7").unwrap();
+        assert_eq!(cron.next_after(DAY_2024_01_01, UTC), Some(DAY_2024_01_01 + 5 * 24 * HOUR + 18 * HOUR + 1800000));
+        // day-of-month or day-of-week
+        let cron = CronExpr::parse("0 0 3 * 2").unwrap();
+        assert_eq!(cron.next_after(DAY_2024_01_01, UTC), Some(DAY_2024_01_01 + 24 * HOUR));
+        // never matches
+        let cron = CronExpr::parse("0 0 30 2 *").unwrap();
+        assert_eq!(cron.next_after(DAY_2024_01_01, UTC), None);
+    }
+}
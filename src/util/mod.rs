@@ -1,3 +1,3 @@
 pub mod time;
 pub mod gen_id;
-pub mod json;
\ No newline at end of file
+pub mod cron;
@@ -6,4 +6,12 @@ pub fn get_timestamp() -> f64 {
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards");
     since_the_epoch.as_secs_f64()
-}
\ No newline at end of file
+}
+
+pub fn get_timestamp_millis() -> u64 {
+    let start = SystemTime::now();
+    let since_the_epoch = start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    since_the_epoch.as_millis() as u64
+}
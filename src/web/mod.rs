@@ -0,0 +1,4 @@
+//! http 接口
+//! - 设备配置热加载
+
+pub mod web_server;
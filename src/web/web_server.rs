@@ -0,0 +1,175 @@
+//! http server, runs in its own thread with actix runtime
+
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use actix_web::{delete, get, post, web, App, HttpResponse, HttpServer};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::common::setting::Settings;
+use crate::entity::dto::{
+    command_reply_dto::{CommandReplyDto, REPLY_CODE_ERROR, REPLY_CODE_OK, REPLY_CODE_PARAM_FAIL},
+    mqtt_dto::MqttToDeviceEnum,
+    reload_devices_command_dto::ReloadDevicesCommandDto,
+    scene_command_dto::{
+        RecallSceneParamDto, SceneCaptureParamDto, SceneCommandDto, SceneIdParamDto, SceneRequestEnum, SceneSaveParamDto,
+    },
+};
+use crate::util::gen_id::generate_uuid;
+use crate::{error, info};
+
+const LOG_TAG: &str = "web_server";
+// waiting time for device thread to finish reloading
+const RELOAD_TIMEOUT: u64 = 60000;
+// waiting time for device thread to handle scene command
+const SCENE_TIMEOUT: u64 = 10000;
+
+/// start http server thread
+pub fn web_thread(command_tx: Sender<MqttToDeviceEnum>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let settings = Settings::get();
+        let address = (settings.web.web_host.clone(), settings.web.web_port);
+        info!(LOG_TAG, "http server starting, address: {:?}", address);
+
+        let result = actix_web::rt::System::new().block_on(async move {
+            HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(command_tx.clone()))
+                    .service(reload_devices)
+                    .service(list_scenes)
+                    .service(save_scene)
+                    .service(capture_scene)
+                    .service(delete_scene)
+                    .service(recall_scene)
+            })
+            .workers(1)
+            .bind(address)?
+            .run()
+            .await
+        });
+        if let Err(e) = result {
+            error!(LOG_TAG, "http server stopped, error msg: {}", e);
+        }
+    })
+}
+
+/// re-fetch device config and rebuild changed devices, response after reloading
+#[post("/api/v1/devices/reload")]
+async fn reload_devices(command_tx: web::Data<Sender<MqttToDeviceEnum>>) -> HttpResponse {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let session_id = generate_uuid();
+    let reload_command_dto = ReloadDevicesCommandDto {
+        session_id: session_id.clone(),
+        reply_tx: Some(reply_tx),
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::ReloadDevices(reload_command_dto)) {
+        return make_response(REPLY_CODE_ERROR, format!("send reload devices command dto error: {e}"), session_id);
+    }
+
+    let reply = web::block(move || reply_rx.recv_timeout(Duration::from_millis(RELOAD_TIMEOUT))).await;
+    match reply {
+        Ok(Ok(reply)) => make_response(reply.code, reply.msg, reply.session_id),
+        Ok(Err(e)) => make_response(REPLY_CODE_ERROR, format!("wait for reload devices reply error: {e}"), session_id),
+        Err(e) => make_response(REPLY_CODE_ERROR, format!("wait for reload devices reply error: {e}"), session_id),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RecallQuery {
+    // crossfade time of dmx devices, in millis
+    fade: Option<u64>,
+}
+
+#[get("/api/v1/scenes")]
+async fn list_scenes(command_tx: web::Data<Sender<MqttToDeviceEnum>>) -> HttpResponse {
+    send_scene_command(&command_tx, SceneRequestEnum::List).await
+}
+
+/// define a scene by device commands, body is the same as the param of scene_save
+#[post("/api/v1/scenes")]
+async fn save_scene(command_tx: web::Data<Sender<MqttToDeviceEnum>>, body: web::Json<SceneSaveParamDto>) -> HttpResponse {
+    send_scene_command(&command_tx, SceneRequestEnum::Save(body.into_inner())).await
+}
+
+/// save the current state of devices as a scene, body is the same as the param of scene_capture
+#[post("/api/v1/scenes/capture")]
+async fn capture_scene(command_tx: web::Data<Sender<MqttToDeviceEnum>>, body: web::Json<SceneCaptureParamDto>) -> HttpResponse {
+    send_scene_command(&command_tx, SceneRequestEnum::Capture(body.into_inner())).await
+}
+
+#[delete("/api/v1/scenes/{scene_id}")]
+async fn delete_scene(command_tx: web::Data<Sender<MqttToDeviceEnum>>, path: web::Path<String>) -> HttpResponse {
+    let scene_id = path.into_inner();
+    send_scene_command(&command_tx, SceneRequestEnum::Delete(SceneIdParamDto { scene_id })).await
+}
+
+#[post("/api/v1/scenes/{scene_id}/recall")]
+async fn recall_scene(
+    command_tx: web::Data<Sender<MqttToDeviceEnum>>,
+    path: web::Path<String>,
+    query: web::Query<RecallQuery>,
+) -> HttpResponse {
+    let scene_id = path.into_inner();
+    let param = RecallSceneParamDto { scene_id, fade: query.fade };
+    send_scene_command(&command_tx, SceneRequestEnum::Recall(param)).await
+}
+
+/// send scene command to device thread and wait for the reply
+async fn send_scene_command(command_tx: &Sender<MqttToDeviceEnum>, request: SceneRequestEnum) -> HttpResponse {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let session_id = generate_uuid();
+    let scene_command_dto = SceneCommandDto {
+        session_id: session_id.clone(),
+        request,
+        reply_tx: Some(reply_tx),
+    };
+    if let Err(e) = command_tx.send(MqttToDeviceEnum::Scene(scene_command_dto)) {
+        return make_response(REPLY_CODE_ERROR, format!("send scene command dto error: {e}"), session_id);
+    }
+
+    let reply = web::block(move || reply_rx.recv_timeout(Duration::from_millis(SCENE_TIMEOUT))).await;
+    match reply {
+        Ok(Ok(reply)) => make_reply_response(reply),
+        Ok(Err(e)) => make_response(REPLY_CODE_ERROR, format!("wait for scene command reply error: {e}"), session_id),
+        Err(e) => make_response(REPLY_CODE_ERROR, format!("wait for scene command reply error: {e}"), session_id),
+    }
+}
+
+/// response with scenes and results of the reply
+fn make_reply_response(reply: CommandReplyDto) -> HttpResponse {
+    let mut data = json!({"session_id": reply.session_id});
+    if let Some(scenes) = &reply.scenes {
+        data["scenes"] = json!(scenes);
+    }
+    if let Some(results) = &reply.results {
+        data["results"] = json!(results);
+    }
+    let body = json!({
+        "code": reply.code,
+        "msg": reply.msg,
+        "data": data,
+    });
+    match reply.code {
+        REPLY_CODE_OK => HttpResponse::Ok().json(body),
+        REPLY_CODE_PARAM_FAIL => HttpResponse::BadRequest().json(body),
+        _ => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+/// response body has the same format as flow server api
+fn make_response(code: i32, msg: String, session_id: String) -> HttpResponse {
+    let body = json!({
+        "code": code,
+        "msg": msg,
+        "data": {"session_id": session_id},
+    });
+    if code == REPLY_CODE_OK {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::InternalServerError().json(body)
+    }
+}